@@ -0,0 +1,81 @@
+//! Derive macros for the `efflux` crate.
+//!
+//! This crate should not be used directly; instead enable the `derive`
+//! feature of `efflux`, which re-exports these macros alongside the traits
+//! they implement (e.g. `efflux::context::Contextual`).
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, parse_quote, DeriveInput, GenericParam};
+
+/// Derives an implementation of the `Contextual` marker trait.
+///
+/// As `Contextual` requires `Any`, a `'static` bound is added to all type
+/// parameters of the deriving type:
+///
+/// ```rust
+/// use efflux::context::Context;
+/// use efflux_derive::Contextual;
+///
+/// #[derive(Contextual)]
+/// struct MyState(usize);
+///
+/// let mut ctx = Context::new();
+///
+/// ctx.insert(MyState(3));
+///
+/// assert_eq!(ctx.get::<MyState>().map(|state| state.0), Some(3));
+/// ```
+#[proc_macro_derive(Contextual)]
+pub fn derive_contextual(input: TokenStream) -> TokenStream {
+    expand_contextual(parse_macro_input!(input as DeriveInput)).into()
+}
+
+/// Expands the `Contextual` implementation for a type.
+fn expand_contextual(mut input: DeriveInput) -> TokenStream2 {
+    for param in &mut input.generics.params {
+        if let GenericParam::Type(param) = param {
+            param.bounds.push(parse_quote!('static));
+        }
+    }
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    quote! {
+        impl #impl_generics ::efflux::context::Contextual for #name #ty_generics #where_clause {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_contextual_expansion() {
+        let cases = vec![
+            (
+                quote!(
+                    struct State;
+                ),
+                "impl :: efflux :: context :: Contextual for State { }",
+            ),
+            (
+                quote!(
+                    struct State<T: Clone>(T)
+                    where
+                        T: Default;
+                ),
+                "impl < T : Clone + 'static > :: efflux :: context :: Contextual \
+                 for State < T > where T : Default { }",
+            ),
+        ];
+
+        for (input, expected) in cases {
+            let input = syn::parse2::<DeriveInput>(input).unwrap();
+            assert_eq!(expand_contextual(input).to_string(), expected);
+        }
+    }
+}
@@ -57,8 +57,10 @@ impl Mapper for WordcountMapper {
 
         // split on spaces to find words
         for word in value.split(" ") {
-            // write each word
-            ctx.write_fmt(word, 1);
+            // write each word, stopping on error (efflux will report it)
+            if ctx.write_fmt(word, 1).is_err() {
+                return;
+            }
         }
     }
 }
@@ -2,6 +2,7 @@
 extern crate efflux;
 
 use efflux::prelude::{Context, Reducer};
+use efflux::reducer::Values;
 
 fn main() {
     // simply run the reduction phase with our reducer
@@ -14,16 +15,9 @@ struct WordcountReducer;
 // Reducing stage implementation.
 impl Reducer for WordcountReducer {
     /// Reduction implementation for the word counter example.
-    fn reduce(&mut self, key: &[u8], values: &[&[u8]], ctx: &mut Context) {
-        // base counter
-        let mut count = 0;
-
-        for value in values {
-            // parse each value sum them all to obtain total appearances
-            count += std::str::from_utf8(value).unwrap().parse::<usize>().unwrap();
-        }
-
-        // write the word and the total count as bytes
-        ctx.write(key, count.to_string().as_bytes());
+    fn reduce_streaming(&mut self, key: &[u8], values: &mut Values<'_, '_>, ctx: &mut Context) {
+        // sum all values lazily to obtain total appearances, then write the
+        // word and the total count as bytes (efflux reports errors)
+        let _ = ctx.write(key, values.sum::<usize>().to_string());
     }
 }
@@ -0,0 +1,991 @@
+//! Provided stages for common aggregations.
+//!
+//! This module offers ready-made reducers for aggregations which appear
+//! in almost every job, such as summing the values of each key. Numeric
+//! reducers are built on the typed adapters, so values which fail to parse
+//! (or sums which overflow) are handled using the `ErrorPolicy` stored in
+//! the `Context`:
+//!
+//! ```rust,no_run
+//! use efflux::aggregate::SumReducer;
+//!
+//! efflux::run_reducer(SumReducer::default());
+//! ```
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashSet};
+use std::fmt::Display;
+use std::marker::PhantomData;
+use std::str::FromStr;
+
+use crate::context::{Configuration, Context, ErrorPolicy};
+use crate::reducer::{Reducer, Values};
+use crate::typed::{fail, reject, Typed, TypedReducer};
+
+/// Reducer to emit the sum of the integer values of each key.
+pub type SumReducer = Typed<Sum<i64>>;
+
+/// Reducer to emit the sum of the floating point values of each key.
+pub type FloatSumReducer = Typed<Sum<f64>>;
+
+/// Reducer to emit summary statistics of the values of each key.
+pub type StatsReducer = Typed<Stats>;
+
+/// Trait to represent numeric types which can be summed.
+pub trait Summable: FromStr + Display {
+    /// Sums all values provided by an iterator.
+    ///
+    /// This returns `None` if the sum cannot be represented by the type.
+    fn sum<I>(values: I) -> Option<Self>
+    where
+        I: Iterator<Item = Self>;
+}
+
+macro_rules! impl_summable_int {
+    ($($t:ty),+) => {$(
+        /// `Summable` implementation for integers, using checked addition.
+        impl Summable for $t {
+            #[inline]
+            fn sum<I>(mut values: I) -> Option<Self>
+            where
+                I: Iterator<Item = Self>,
+            {
+                values.try_fold(0, |sum: $t, value| sum.checked_add(value))
+            }
+        }
+    )+};
+}
+
+macro_rules! impl_summable_float {
+    ($($t:ty),+) => {$(
+        /// `Summable` implementation for floats, using compensated summation.
+        ///
+        /// This tracks the precision lost in each addition, so that values of
+        /// very different magnitudes can be summed without losing small values.
+        impl Summable for $t {
+            fn sum<I>(values: I) -> Option<Self>
+            where
+                I: Iterator<Item = Self>,
+            {
+                let mut sum: $t = 0.0;
+                let mut compensation: $t = 0.0;
+
+                for value in values {
+                    let total = sum + value;
+
+                    compensation += if sum.abs() >= value.abs() {
+                        (sum - total) + value
+                    } else {
+                        (value - total) + sum
+                    };
+
+                    sum = total;
+                }
+
+                Some(sum + compensation)
+            }
+        }
+    )+};
+}
+
+impl_summable_int!(i32, i64, i128, u32, u64, u128);
+impl_summable_float!(f32, f64);
+
+/// Typed reducer to emit the sum of the values of each key.
+///
+/// This is typically executed via the `SumReducer` or `FloatSumReducer`
+/// aliases, although any `Summable` type can be used with `Typed`. Keys with
+/// a sum which overflows are handled via the `ErrorPolicy`; they're either
+/// skipped, counted under the `efflux` group counter `overflow_errors`, or
+/// cause the stage to fail.
+#[derive(Debug)]
+pub struct Sum<T>(PhantomData<T>);
+
+/// `TypedReducer` implementation to sum the parsed values.
+impl<T> TypedReducer for Sum<T>
+where
+    T: Summable,
+{
+    type Value = T;
+
+    /// Reduction handler by summing the values and emitting the total.
+    fn reduce(&mut self, key: &[u8], values: impl Iterator<Item = T>, ctx: &mut Context) {
+        let total = match T::sum(values) {
+            Some(total) => total.to_string(),
+            None => {
+                let key = String::from_utf8_lossy(key);
+                let msg = format!("sum overflowed for key: {:?}", key);
+                let policy = ctx.get::<ErrorPolicy>().copied().unwrap_or_default();
+
+                if let Err(err) = fail(msg, "overflow_errors", policy, ctx.skipped_mut()) {
+                    ctx.set_error(err);
+                }
+                return;
+            }
+        };
+
+        // errors are surfaced by the lifecycle
+        let _ = ctx.write(key, total);
+    }
+}
+
+/// Default implementation for `Sum`.
+impl<T> Default for Sum<T> {
+    fn default() -> Self {
+        Sum(PhantomData)
+    }
+}
+
+/// Running summary statistics over a stream of values.
+///
+/// The mean and variance are updated as each value is added using Welford's
+/// algorithm, which is numerically stable and never holds values in memory.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RunningStats {
+    count: u64,
+    mean: f64,
+    m2: f64,
+}
+
+impl RunningStats {
+    /// Creates a new, empty `RunningStats`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a value to the statistics.
+    pub fn push(&mut self, value: f64) {
+        self.count += 1;
+
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value - self.mean);
+    }
+
+    /// Returns the number of values added.
+    #[inline]
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Returns the mean of all values added, or `0` if there are none.
+    #[inline]
+    pub fn mean(&self) -> f64 {
+        self.mean
+    }
+
+    /// Returns the sum of squared differences from the mean.
+    #[inline]
+    pub fn m2(&self) -> f64 {
+        self.m2
+    }
+
+    /// Returns the sample variance of all values added.
+    ///
+    /// This is `0` when fewer than two values have been added.
+    pub fn variance(&self) -> f64 {
+        if self.count < 2 {
+            return 0.0;
+        }
+        self.m2 / (self.count - 1) as f64
+    }
+}
+
+/// Output formats for the statistics emitted by `Stats`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum StatsFormat {
+    /// Emits the count, mean and variance as tab separated fields.
+    Fields,
+    /// Emits the count, mean and variance as a JSON object.
+    Json,
+}
+
+/// Typed reducer to emit summary statistics of the values of each key.
+///
+/// Values are parsed as `f64` and summarized via `RunningStats`, so groups
+/// are never buffered. Non-finite values (such as `NaN` or `inf`) are handled
+/// as values which failed to parse, using the `ErrorPolicy`. The count, mean
+/// and (sample) variance of each key are emitted using the format provided
+/// via `with_format`, otherwise using the `efflux.reduce.stats.format` key of
+/// the `Configuration` (either `fields` or `json`), defaulting to
+/// `StatsFormat::Fields`. Keys without any values (e.g. when all values fail
+/// to parse) are not emitted.
+#[derive(Debug, Default)]
+pub struct Stats {
+    format: Option<StatsFormat>,
+}
+
+impl Stats {
+    /// Creates a new `Stats`, using the configured output format.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a new `Stats`, using the provided output format.
+    pub fn with_format(format: StatsFormat) -> Self {
+        Self {
+            format: Some(format),
+        }
+    }
+}
+
+/// `TypedReducer` implementation to summarize the parsed values.
+impl TypedReducer for Stats {
+    type Value = f64;
+
+    /// Setup handler by reading the output format from the `Configuration`.
+    fn setup(&mut self, ctx: &mut Context) {
+        if self.format.is_some() {
+            return;
+        }
+
+        let format = ctx
+            .get::<Configuration>()
+            .and_then(|conf| conf.get("efflux.reduce.stats.format"));
+
+        self.format = Some(match format {
+            Some("json") => StatsFormat::Json,
+            _ => StatsFormat::Fields,
+        });
+    }
+
+    /// Reduction handler by summarizing the values and emitting the statistics.
+    fn reduce(&mut self, key: &[u8], values: impl Iterator<Item = f64>, ctx: &mut Context) {
+        let policy = ctx.get::<ErrorPolicy>().copied().unwrap_or_default();
+        let mut stats = RunningStats::new();
+
+        for value in values {
+            // non-finite values would poison every statistic of the key
+            if !value.is_finite() {
+                let value = value.to_string();
+                if let Err(err) = reject::<f64>(value.as_bytes(), policy, ctx.skipped_mut()) {
+                    ctx.set_error(err);
+                    return;
+                }
+                continue;
+            }
+            stats.push(value);
+        }
+
+        if stats.count() == 0 {
+            return;
+        }
+
+        let count = stats.count();
+        let mean = stats.mean();
+        let variance = stats.variance();
+
+        let summary = match self.format.unwrap_or(StatsFormat::Fields) {
+            StatsFormat::Fields => format!("{}\t{}\t{}", count, mean, variance),
+            StatsFormat::Json => format!(
+                "{{\"count\":{},\"mean\":{},\"variance\":{}}}",
+                count,
+                json_number(mean),
+                json_number(variance)
+            ),
+        };
+
+        // errors are surfaced by the lifecycle
+        let _ = ctx.write(key, summary);
+    }
+}
+
+/// Formats a float as a JSON number, using `null` for non-finite values.
+///
+/// Statistics can still overflow when summarizing finite values, and JSON
+/// has no representation for `NaN` or infinite numbers.
+fn json_number(value: f64) -> String {
+    if value.is_finite() {
+        value.to_string()
+    } else {
+        "null".to_string()
+    }
+}
+
+/// Reducer to emit the top `n` values of each key.
+///
+/// Values are ranked using a sort key extracted via the provided closure,
+/// and only the best `n` values are held in memory (using a bounded heap),
+/// so groups are never buffered in full. By default the values with the
+/// largest sort keys are emitted, in descending order; `ascending` will
+/// instead emit the values with the smallest sort keys, in ascending order.
+/// Values with equal sort keys are ranked in the order they were received.
+pub struct TopNReducer<F, K>
+where
+    F: FnMut(&[u8]) -> K,
+    K: Ord,
+{
+    n: usize,
+    key_fn: F,
+    ascending: bool,
+    counter: u64,
+    heap: BinaryHeap<Reverse<Ranked<K>>>,
+}
+
+impl<F, K> TopNReducer<F, K>
+where
+    F: FnMut(&[u8]) -> K,
+    K: Ord,
+{
+    /// Creates a new `TopNReducer` to emit the `n` largest values of each key.
+    pub fn new(n: usize, key_fn: F) -> Self {
+        Self {
+            n,
+            key_fn,
+            ascending: false,
+            counter: 0,
+            heap: BinaryHeap::with_capacity(n),
+        }
+    }
+
+    /// Creates a new `TopNReducer` to emit the `n` smallest values of each key.
+    pub fn ascending(n: usize, key_fn: F) -> Self {
+        Self {
+            ascending: true,
+            ..Self::new(n, key_fn)
+        }
+    }
+
+    /// Offers a value to the heap, replacing the worst value when full.
+    fn offer(&mut self, value: &[u8]) {
+        if self.n == 0 {
+            return;
+        }
+
+        let ranked = Ranked {
+            key: (self.key_fn)(value),
+            seq: self.counter,
+            ascending: self.ascending,
+            value: Vec::new(),
+        };
+
+        self.counter += 1;
+
+        if self.heap.len() < self.n {
+            self.heap.push(Reverse(Ranked {
+                value: value.to_vec(),
+                ..ranked
+            }));
+            return;
+        }
+
+        // the top of the heap is the worst value currently held
+        if let Some(mut worst) = self.heap.peek_mut() {
+            if ranked > worst.0 {
+                worst.0 = Ranked {
+                    value: value.to_vec(),
+                    ..ranked
+                };
+            }
+        }
+    }
+
+    /// Emits all values held in the heap, best first, before clearing it.
+    fn emit(&mut self, key: &[u8], ctx: &mut Context) {
+        let heap = std::mem::take(&mut self.heap);
+
+        for Reverse(ranked) in heap.into_sorted_vec() {
+            // errors are surfaced by the lifecycle
+            if ctx.write(key, &ranked.value).is_err() {
+                break;
+            }
+        }
+
+        self.counter = 0;
+    }
+}
+
+/// `Reducer` implementation to emit the top values of each group.
+impl<F, K> Reducer for TopNReducer<F, K>
+where
+    F: FnMut(&[u8]) -> K,
+    K: Ord,
+{
+    /// Reduction handler by ranking the buffered values.
+    fn reduce(&mut self, key: &[u8], values: &[&[u8]], ctx: &mut Context) {
+        for value in values {
+            self.offer(value);
+        }
+        self.emit(key, ctx);
+    }
+
+    /// Reduction handler by ranking the streamed values.
+    fn reduce_streaming(&mut self, key: &[u8], values: &mut Values<'_, '_>, ctx: &mut Context) {
+        while let Some(value) = values.next_value() {
+            self.offer(value);
+        }
+        self.emit(key, ctx);
+    }
+}
+
+/// Reducer to emit each distinct value of each key once.
+///
+/// Values seen within the current group are tracked in memory, and a warning
+/// is logged when a group holds more distinct values than the limit set via
+/// `efflux.reduce.distinct.warn.values` (defaulting to `100000`). For groups
+/// too large to track, `sorted` will only compare each value to the previous
+/// value, which requires constant memory but relies on values being sorted
+/// (e.g. via a `ValueOrder`, or `Reducer::value_comparator`).
+#[derive(Debug)]
+pub struct DistinctReducer {
+    sorted: bool,
+    limit: usize,
+    seen: HashSet<Vec<u8>>,
+    last: Option<Vec<u8>>,
+}
+
+impl DistinctReducer {
+    /// Creates a new `DistinctReducer`, tracking all values of a group.
+    pub fn new() -> Self {
+        Self {
+            sorted: false,
+            limit: 100_000,
+            seen: HashSet::new(),
+            last: None,
+        }
+    }
+
+    /// Creates a new `DistinctReducer`, assuming values are sorted.
+    pub fn sorted() -> Self {
+        Self {
+            sorted: true,
+            ..Self::new()
+        }
+    }
+
+    /// Returns whether a value is distinct within the current group.
+    fn distinct(&mut self, key: &[u8], value: &[u8]) -> bool {
+        if self.sorted {
+            if self.last.as_deref() == Some(value) {
+                return false;
+            }
+            let last = self.last.get_or_insert_with(Vec::new);
+            last.clear();
+            last.extend_from_slice(value);
+            return true;
+        }
+
+        if self.seen.contains(value) {
+            return false;
+        }
+
+        self.seen.insert(value.to_vec());
+
+        if self.seen.len() == self.limit + 1 {
+            log_warn!(
+                "more than {} distinct values for key {:?}, consider sorted input",
+                self.limit,
+                String::from_utf8_lossy(key)
+            );
+        }
+
+        true
+    }
+
+    /// Resets the tracked values, ready for the next group.
+    fn reset(&mut self) {
+        self.seen.clear();
+        self.last = None;
+    }
+}
+
+/// `Reducer` implementation to emit the distinct values of each group.
+impl Reducer for DistinctReducer {
+    /// Setup handler by reading the warning limit from the `Configuration`.
+    fn setup(&mut self, ctx: &mut Context) {
+        let limit = ctx
+            .get::<Configuration>()
+            .and_then(|conf| conf.get_i64("efflux.reduce.distinct.warn.values"));
+
+        if let Some(limit) = limit {
+            self.limit = limit.max(0) as usize;
+        }
+    }
+
+    /// Reduction handler by filtering the buffered values.
+    fn reduce(&mut self, key: &[u8], values: &[&[u8]], ctx: &mut Context) {
+        for value in values {
+            // errors are surfaced by the lifecycle
+            if self.distinct(key, value) && ctx.write(key, value).is_err() {
+                break;
+            }
+        }
+        self.reset();
+    }
+
+    /// Reduction handler by filtering the streamed values.
+    fn reduce_streaming(&mut self, key: &[u8], values: &mut Values<'_, '_>, ctx: &mut Context) {
+        while let Some(value) = values.next_value() {
+            // errors are surfaced by the lifecycle
+            if self.distinct(key, value) && ctx.write(key, value).is_err() {
+                break;
+            }
+        }
+        self.reset();
+    }
+}
+
+/// Default implementation for `DistinctReducer`.
+impl Default for DistinctReducer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Reducer to emit an aggregate of the values of each key per time window.
+///
+/// Values are assigned to fixed windows of `size` units using the timestamp
+/// extracted via the provided closure (values without a timestamp are skipped),
+/// and folded into an aggregate via the provided closure. Each window is emitted
+/// as the window start and the aggregate, separated by the output delimiter.
+///
+/// Only the aggregate of the current window is held in memory, so values must
+/// be sorted by time within each group (e.g. via `Reducer::value_comparator`,
+/// or a secondary sort on the input). Out of order values start a new window,
+/// meaning that the same window may be emitted more than once.
+///
+/// ```rust
+/// use efflux::aggregate::WindowedReducer;
+///
+/// // counts the values of each key per minute, using seconds as timestamps
+/// let reducer = WindowedReducer::new(
+///     60,
+///     |value: &[u8]| std::str::from_utf8(value).ok()?.parse().ok(),
+///     |count: &mut usize, _value: &[u8]| *count += 1,
+/// );
+/// ```
+pub struct WindowedReducer<T, F, A>
+where
+    T: FnMut(&[u8]) -> Option<u64>,
+    F: FnMut(&mut A, &[u8]),
+    A: Default + Display,
+{
+    size: u64,
+    timestamp: T,
+    fold: F,
+    window: Option<(u64, A)>,
+}
+
+impl<T, F, A> WindowedReducer<T, F, A>
+where
+    T: FnMut(&[u8]) -> Option<u64>,
+    F: FnMut(&mut A, &[u8]),
+    A: Default + Display,
+{
+    /// Creates a new `WindowedReducer` using windows of `size` units.
+    ///
+    /// A `size` of `0` is treated as a size of `1`.
+    pub fn new(size: u64, timestamp: T, fold: F) -> Self {
+        Self {
+            size: size.max(1),
+            timestamp,
+            fold,
+            window: None,
+        }
+    }
+
+    /// Folds a value into its window, emitting the previous window if needed.
+    ///
+    /// Returns `false` if the previous window could not be written.
+    fn offer(&mut self, key: &[u8], value: &[u8], ctx: &mut Context) -> bool {
+        let timestamp = match (self.timestamp)(value) {
+            Some(timestamp) => timestamp,
+            None => return true,
+        };
+
+        let start = timestamp - timestamp % self.size;
+
+        if self.window.as_ref().is_some_and(|(open, _)| *open != start) && !self.emit(key, ctx) {
+            return false;
+        }
+
+        let (_, aggregate) = self.window.get_or_insert_with(|| (start, A::default()));
+
+        (self.fold)(aggregate, value);
+
+        true
+    }
+
+    /// Emits the current window (if any), returning `false` on write errors.
+    fn emit(&mut self, key: &[u8], ctx: &mut Context) -> bool {
+        let (start, aggregate) = match self.window.take() {
+            Some(window) => window,
+            None => return true,
+        };
+
+        let mut value = start.to_string().into_bytes();
+        value.extend_from_slice(ctx.delimiters().output());
+        value.extend_from_slice(aggregate.to_string().as_bytes());
+
+        // errors are surfaced by the lifecycle
+        ctx.write(key, value).is_ok()
+    }
+}
+
+/// `Reducer` implementation to emit the windowed aggregates of each group.
+impl<T, F, A> Reducer for WindowedReducer<T, F, A>
+where
+    T: FnMut(&[u8]) -> Option<u64>,
+    F: FnMut(&mut A, &[u8]),
+    A: Default + Display,
+{
+    /// Reduction handler by windowing the buffered values.
+    fn reduce(&mut self, key: &[u8], values: &[&[u8]], ctx: &mut Context) {
+        for value in values {
+            if !self.offer(key, value, ctx) {
+                break;
+            }
+        }
+        self.emit(key, ctx);
+    }
+
+    /// Reduction handler by windowing the streamed values.
+    fn reduce_streaming(&mut self, key: &[u8], values: &mut Values<'_, '_>, ctx: &mut Context) {
+        while let Some(value) = values.next_value() {
+            if !self.offer(key, value, ctx) {
+                break;
+            }
+        }
+        self.emit(key, ctx);
+    }
+}
+
+/// Value structure ranked by a sort key within a `TopNReducer`.
+///
+/// Greater values are better; the sequence number ranks earlier values above
+/// later values with an equal sort key, to keep output stable.
+struct Ranked<K> {
+    key: K,
+    seq: u64,
+    ascending: bool,
+    value: Vec<u8>,
+}
+
+/// `Ord` implementation for `Ranked`, respecting the sort direction.
+impl<K> Ord for Ranked<K>
+where
+    K: Ord,
+{
+    fn cmp(&self, other: &Self) -> Ordering {
+        let ordering = if self.ascending {
+            other.key.cmp(&self.key)
+        } else {
+            self.key.cmp(&other.key)
+        };
+        ordering.then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+/// `PartialOrd` implementation for `Ranked`, delegating to `Ord`.
+impl<K> PartialOrd for Ranked<K>
+where
+    K: Ord,
+{
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// `PartialEq` implementation for `Ranked`, delegating to `Ord`.
+impl<K> PartialEq for Ranked<K>
+where
+    K: Ord,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+/// `Eq` implementation for `Ranked`.
+impl<K> Eq for Ranked<K> where K: Ord {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::execute;
+    use crate::reducer::ReducerLifecycle;
+
+    #[test]
+    fn test_integer_sums() {
+        let mut ctx = Context::buffered();
+        let input = &b"a\t1\na\t-5\na\t9000000000\nb\tx\nb\t2\n"[..];
+
+        let reducer = SumReducer::default();
+        execute(input, ReducerLifecycle::new(reducer), &mut ctx).unwrap();
+
+        assert_eq!(
+            ctx.take_output(),
+            vec![
+                (b"a".to_vec(), b"8999999996".to_vec()),
+                (b"b".to_vec(), b"2".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_float_sums() {
+        let mut ctx = Context::buffered();
+        let input = &b"a\t1e20\na\t1.5\na\t-1e20\nb\t0.1\nb\t0.2\n"[..];
+
+        let reducer = FloatSumReducer::default();
+        execute(input, ReducerLifecycle::new(reducer), &mut ctx).unwrap();
+
+        assert_eq!(
+            ctx.take_output(),
+            vec![
+                (b"a".to_vec(), b"1.5".to_vec()),
+                (b"b".to_vec(), b"0.30000000000000004".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_failed_sums() {
+        let mut ctx = Context::buffered();
+        let input = &b"a\t1\na\tx\n"[..];
+
+        ctx.insert(ErrorPolicy::Fail);
+
+        let reducer = SumReducer::default();
+        let result = execute(input, ReducerLifecycle::new(reducer), &mut ctx);
+
+        assert_eq!(result.unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_overflowing_sums() {
+        let input = &b"a\t9223372036854775807\na\t1\nb\t2\n"[..];
+
+        let mut ctx = Context::buffered();
+        let reducer = SumReducer::default();
+        execute(input, ReducerLifecycle::new(reducer), &mut ctx).unwrap();
+
+        assert_eq!(ctx.take_output(), vec![(b"b".to_vec(), b"2".to_vec())]);
+
+        let mut ctx = Context::buffered();
+        ctx.insert(ErrorPolicy::Fail);
+
+        let reducer = SumReducer::default();
+        let result = execute(input, ReducerLifecycle::new(reducer), &mut ctx);
+
+        assert_eq!(result.unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(i64::sum(vec![i64::MIN, -1].into_iter()), None);
+        assert_eq!(u32::sum(vec![1, 2].into_iter()), Some(3));
+    }
+
+    #[test]
+    fn test_running_stats() {
+        let mut stats = RunningStats::new();
+
+        assert_eq!(stats.variance(), 0.0);
+
+        for value in [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0] {
+            stats.push(value);
+        }
+
+        assert_eq!(stats.count(), 8);
+        assert_eq!(stats.mean(), 5.0);
+        assert_eq!(stats.m2(), 32.0);
+        assert!((stats.variance() - 32.0 / 7.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_stats_values() {
+        let mut ctx = Context::buffered();
+        let input = &b"a\t1\na\t2\na\t3\nb\tx\nc\t1.5\n"[..];
+
+        let reducer = StatsReducer::default();
+        execute(input, ReducerLifecycle::new(reducer), &mut ctx).unwrap();
+
+        assert_eq!(
+            ctx.take_output(),
+            vec![
+                (b"a".to_vec(), b"3\t2\t1".to_vec()),
+                (b"c".to_vec(), b"1\t1.5\t0".to_vec()),
+            ]
+        );
+
+        ctx.get_mut::<Configuration>()
+            .unwrap()
+            .insert("efflux.reduce.stats.format", "json");
+
+        let reducer = StatsReducer::default();
+        execute(
+            &b"a\t1\na\t2\n"[..],
+            ReducerLifecycle::new(reducer),
+            &mut ctx,
+        )
+        .unwrap();
+
+        assert_eq!(
+            ctx.take_output(),
+            vec![(
+                b"a".to_vec(),
+                b"{\"count\":2,\"mean\":1.5,\"variance\":0.5}".to_vec()
+            )]
+        );
+
+        let reducer = Typed(Stats::with_format(StatsFormat::Fields));
+        execute(&b"a\t1\n"[..], ReducerLifecycle::new(reducer), &mut ctx).unwrap();
+
+        assert_eq!(
+            ctx.take_output(),
+            vec![(b"a".to_vec(), b"1\t1\t0".to_vec())]
+        );
+    }
+
+    #[test]
+    fn test_non_finite_stats() {
+        let input = &b"a\t1\na\tNaN\na\tinf\na\t3\nb\t-inf\n"[..];
+
+        let mut ctx = Context::buffered();
+        let reducer = Typed(Stats::with_format(StatsFormat::Json));
+        execute(input, ReducerLifecycle::new(reducer), &mut ctx).unwrap();
+
+        assert_eq!(
+            ctx.take_output(),
+            vec![(
+                b"a".to_vec(),
+                b"{\"count\":2,\"mean\":2,\"variance\":2}".to_vec()
+            )]
+        );
+
+        let mut ctx = Context::buffered();
+        ctx.insert(ErrorPolicy::Fail);
+
+        let reducer = StatsReducer::default();
+        let result = execute(input, ReducerLifecycle::new(reducer), &mut ctx);
+
+        assert_eq!(result.unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+
+        let mut ctx = Context::buffered();
+        let reducer = Typed(Stats::with_format(StatsFormat::Json));
+        execute(
+            &b"a\t1e308\na\t-1e308\n"[..],
+            ReducerLifecycle::new(reducer),
+            &mut ctx,
+        )
+        .unwrap();
+
+        assert_eq!(
+            ctx.take_output(),
+            vec![(
+                b"a".to_vec(),
+                b"{\"count\":2,\"mean\":null,\"variance\":null}".to_vec()
+            )]
+        );
+    }
+
+    #[test]
+    fn test_distinct_values() {
+        let input = &b"a\tx\na\ty\na\tx\na\ty\nb\tx\nb\tx\n"[..];
+
+        let mut ctx = Context::buffered();
+        let reducer = DistinctReducer::new();
+        execute(input, ReducerLifecycle::new(reducer), &mut ctx).unwrap();
+
+        assert_eq!(
+            ctx.take_output(),
+            vec![
+                (b"a".to_vec(), b"x".to_vec()),
+                (b"a".to_vec(), b"y".to_vec()),
+                (b"b".to_vec(), b"x".to_vec()),
+            ]
+        );
+
+        let mut ctx = Context::buffered();
+        let reducer = DistinctReducer::sorted();
+        execute(input, ReducerLifecycle::new(reducer), &mut ctx).unwrap();
+
+        assert_eq!(
+            ctx.take_output(),
+            vec![
+                (b"a".to_vec(), b"x".to_vec()),
+                (b"a".to_vec(), b"y".to_vec()),
+                (b"a".to_vec(), b"x".to_vec()),
+                (b"a".to_vec(), b"y".to_vec()),
+                (b"b".to_vec(), b"x".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_top_n_values() {
+        let input = &b"a\t3\na\t10\na\t1\na\t7\na\t10x\nb\t2\n"[..];
+        let parse = |value: &[u8]| -> u64 {
+            std::str::from_utf8(value)
+                .ok()
+                .and_then(|v| v.trim_end_matches('x').parse().ok())
+                .unwrap_or(0)
+        };
+
+        let mut ctx = Context::buffered();
+        execute(
+            input,
+            ReducerLifecycle::new(TopNReducer::new(3, parse)),
+            &mut ctx,
+        )
+        .unwrap();
+
+        assert_eq!(
+            ctx.take_output(),
+            vec![
+                (b"a".to_vec(), b"10".to_vec()),
+                (b"a".to_vec(), b"10x".to_vec()),
+                (b"a".to_vec(), b"7".to_vec()),
+                (b"b".to_vec(), b"2".to_vec()),
+            ]
+        );
+
+        let mut ctx = Context::buffered();
+        execute(
+            input,
+            ReducerLifecycle::new(TopNReducer::ascending(2, parse)),
+            &mut ctx,
+        )
+        .unwrap();
+
+        assert_eq!(
+            ctx.take_output(),
+            vec![
+                (b"a".to_vec(), b"1".to_vec()),
+                (b"a".to_vec(), b"3".to_vec()),
+                (b"b".to_vec(), b"2".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_windowed_values() {
+        let input = &b"a\t5\na\t12\na\t19\na\tx\na\t20\na\t3\nb\t41\n"[..];
+        let timestamp =
+            |value: &[u8]| -> Option<u64> { std::str::from_utf8(value).ok()?.parse().ok() };
+        let counter = |count: &mut usize, _: &[u8]| *count += 1;
+
+        let mut ctx = Context::buffered();
+        let reducer = WindowedReducer::new(10, timestamp, counter);
+        execute(input, ReducerLifecycle::new(reducer), &mut ctx).unwrap();
+
+        assert_eq!(
+            ctx.take_output(),
+            vec![
+                (b"a".to_vec(), b"0\t1".to_vec()),
+                (b"a".to_vec(), b"10\t2".to_vec()),
+                (b"a".to_vec(), b"20\t1".to_vec()),
+                (b"a".to_vec(), b"0\t1".to_vec()),
+                (b"b".to_vec(), b"40\t1".to_vec()),
+            ]
+        );
+
+        let mut ctx = Context::buffered();
+        let mut reducer = WindowedReducer::new(0, timestamp, counter);
+        reducer.reduce(b"c", &[b"1", b"1", b"2"], &mut ctx);
+
+        assert_eq!(
+            ctx.take_output(),
+            vec![
+                (b"c".to_vec(), b"1\t2".to_vec()),
+                (b"c".to_vec(), b"2\t1".to_vec()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,727 @@
+//! Compression codec bindings for input and output streams.
+//!
+//! Codecs are enabled via Cargo features, and are detected automatically
+//! from the header of the input stream when enabled. Without any codec
+//! features enabled the input stream is always passed through untouched.
+//!
+//! The name of the input file is never used to select a codec, as Hadoop
+//! input formats will typically have decompressed the input already (e.g.
+//! for files ending in `.gz`); input without a matching header is passed
+//! through, with a message logged when the file name suggests a codec.
+//!
+//! The current set of codec features is as follows:
+//!
+//! - `bzip2`
+//! - `gzip`
+//! - `snappy`
+//! - `zstd`
+//!
+//! Snappy has two stream formats; the framed format is detected from the
+//! stream header, whereas the block format used by the Hadoop `SnappyCodec`
+//! has no header, and so is only used when `efflux.input.codec` is set to
+//! `snappy` (or the class name).
+//!
+//! Output is only compressed when a codec is selected in the job
+//! `Configuration`, using the following keys:
+//!
+//! - `efflux.output.codec`: the codec to compress output with, either by
+//!   name (e.g. `zstd`) or by Hadoop class name. Disabled by default.
+//! - `efflux.output.codec.level`: the compression level to use, defaulting
+//!   to the default level of the selected codec.
+//!
+//! Compressed output is only complete once `CompressWriter::finish` has been
+//! called, which the lifecycle runners do once all output has been written.
+use std::io::{self, BufRead, Read, Write};
+
+use crate::context::Configuration;
+
+/// Wraps an input reader in any decompression required by the stream.
+///
+/// Detection is based on the header bytes of the input, along with the
+/// `efflux.input.codec` key for formats without a header. Any input which
+/// does not match an enabled codec is returned without modification.
+pub fn decode<'a, R>(reader: R, conf: &Configuration) -> io::Result<Box<dyn BufRead + 'a>>
+where
+    R: BufRead + 'a,
+{
+    Ok(Box::new(DecompressReader::with_configuration(
+        reader, conf,
+    )?))
+}
+
+/// Reader which transparently decompresses an input stream.
+///
+/// The codec is selected by peeking at the header of the stream (which is
+/// buffered, rather than consumed) and matching against the magic bytes of
+/// each enabled codec. Streams which don't match are passed through as-is.
+pub struct DecompressReader<'a> {
+    inner: Box<dyn BufRead + 'a>,
+    codec: Option<&'static str>,
+}
+
+impl<'a> DecompressReader<'a> {
+    /// Creates a new `DecompressReader`, detecting the codec from the header.
+    pub fn new<R>(reader: R) -> io::Result<Self>
+    where
+        R: BufRead + 'a,
+    {
+        Self::detect(reader, None)
+    }
+
+    /// Creates a new `DecompressReader` using a `Configuration`.
+    ///
+    /// In addition to the stream header, this considers the `efflux.input.codec`
+    /// key, which is required to decode the headerless Hadoop snappy format.
+    pub fn with_configuration<R>(reader: R, conf: &Configuration) -> io::Result<Self>
+    where
+        R: BufRead + 'a,
+    {
+        Self::detect(reader, Some(conf))
+    }
+
+    /// Returns the name of the codec used to decompress the stream, if any.
+    #[inline]
+    pub fn codec(&self) -> Option<&'static str> {
+        self.codec
+    }
+
+    /// Detects the codec of a stream, wrapping it in the matching decoder.
+    fn detect<R>(reader: R, conf: Option<&Configuration>) -> io::Result<Self>
+    where
+        R: BufRead + 'a,
+    {
+        #[cfg(any(
+            feature = "bzip2",
+            feature = "gzip",
+            feature = "snappy",
+            feature = "zstd"
+        ))]
+        let mut reader = reader;
+
+        #[cfg(feature = "gzip")]
+        {
+            if is_gzip(&mut reader) {
+                let decoder = flate2::bufread::MultiGzDecoder::new(reader);
+                return Ok(Self::wrap(decoder, "gzip"));
+            }
+        }
+
+        #[cfg(feature = "zstd")]
+        {
+            if is_zstd(&mut reader) {
+                let decoder = zstd::stream::read::Decoder::with_buffer(reader)?;
+                return Ok(Self::wrap(decoder, "zstd"));
+            }
+        }
+
+        #[cfg(feature = "bzip2")]
+        {
+            if is_bzip2(&mut reader) {
+                let decoder = bzip2::bufread::MultiBzDecoder::new(reader);
+                return Ok(Self::wrap(decoder, "bzip2"));
+            }
+        }
+
+        #[cfg(feature = "snappy")]
+        {
+            if is_snappy_framed(&mut reader) {
+                let decoder = snap::read::FrameDecoder::new(reader);
+                return Ok(Self::wrap(decoder, "snappy"));
+            }
+
+            if is_snappy_blocks(conf) {
+                let decoder = SnappyBlockDecoder::new(reader);
+                return Ok(Self::wrap(decoder, "snappy"));
+            }
+        }
+
+        // the file name is only a hint, as the input is usually decompressed
+        #[cfg(any(
+            feature = "bzip2",
+            feature = "gzip",
+            feature = "snappy",
+            feature = "zstd"
+        ))]
+        {
+            if let Some(file) = conf.and_then(input_file).filter(|file| is_compressed(file)) {
+                log_info!("No codec header found for {}, reading as plain input", file);
+            }
+        }
+
+        #[cfg(not(any(
+            feature = "bzip2",
+            feature = "gzip",
+            feature = "snappy",
+            feature = "zstd"
+        )))]
+        let _ = conf;
+
+        Ok(Self {
+            inner: Box::new(reader),
+            codec: None,
+        })
+    }
+
+    /// Wraps a decoder as a `DecompressReader`.
+    #[cfg(any(
+        feature = "bzip2",
+        feature = "gzip",
+        feature = "snappy",
+        feature = "zstd"
+    ))]
+    fn wrap<D>(decoder: D, codec: &'static str) -> Self
+    where
+        D: Read + 'a,
+    {
+        Self {
+            inner: Box::new(io::BufReader::new(decoder)),
+            codec: Some(codec),
+        }
+    }
+}
+
+/// `Read` implementation for `DecompressReader`.
+impl Read for DecompressReader<'_> {
+    #[inline]
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+/// `BufRead` implementation for `DecompressReader`.
+impl BufRead for DecompressReader<'_> {
+    #[inline]
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    #[inline]
+    fn consume(&mut self, amt: usize) {
+        self.inner.consume(amt)
+    }
+}
+
+/// Wraps an output writer in any compression selected by the `Configuration`.
+///
+/// When no codec is selected, writes are passed through to the writer without
+/// modification. Selecting a codec which is unknown (or not enabled) results
+/// in an error. The returned writer must be finished via `finish` once all
+/// output has been written.
+pub fn encode<'a, W>(writer: W, conf: &Configuration) -> io::Result<CompressWriter<'a>>
+where
+    W: Write + 'a,
+{
+    let writer: Box<dyn Write + 'a> = Box::new(writer);
+
+    let codec = match conf.get("efflux.output.codec") {
+        Some(codec) => codec,
+        None => return Ok(CompressWriter::from(writer)),
+    };
+
+    #[cfg(feature = "zstd")]
+    {
+        if codec == "zstd" || codec == "org.apache.hadoop.io.compress.ZStandardCodec" {
+            let level = conf
+                .get_i64("efflux.output.codec.level")
+                .map_or(zstd::DEFAULT_COMPRESSION_LEVEL, |level| level as i32);
+
+            let encoder = zstd::stream::write::Encoder::new(writer, level)?;
+            return Ok(CompressWriter {
+                inner: Encoder::Zstd(Some(encoder)),
+            });
+        }
+    }
+
+    let msg = format!("unsupported output codec: {}", codec);
+    Err(io::Error::new(io::ErrorKind::InvalidInput, msg))
+}
+
+/// Writer which transparently compresses an output stream.
+///
+/// Compressed streams are only complete once `finish` has been called, as
+/// codecs such as zstd write a trailer at the end of the stream. This is not
+/// done when the writer is dropped, as any error writing the trailer would be
+/// lost; an unfinished stream is left truncated instead.
+pub struct CompressWriter<'a> {
+    inner: Encoder<'a>,
+}
+
+/// Encoders available to a `CompressWriter`.
+enum Encoder<'a> {
+    Plain(Box<dyn Write + 'a>),
+    #[cfg(feature = "zstd")]
+    Zstd(Option<zstd::stream::write::Encoder<'static, Box<dyn Write + 'a>>>),
+}
+
+impl CompressWriter<'_> {
+    /// Finishes the output stream, flushing it to the underlying writer.
+    ///
+    /// Once a compressed stream has been finished, any further writes will
+    /// fail. Uncompressed streams are simply flushed, and remain writable.
+    pub fn finish(&mut self) -> io::Result<()> {
+        match &mut self.inner {
+            Encoder::Plain(writer) => writer.flush(),
+            #[cfg(feature = "zstd")]
+            Encoder::Zstd(encoder) => match encoder.take() {
+                Some(encoder) => encoder.finish()?.flush(),
+                None => Ok(()),
+            },
+        }
+    }
+}
+
+/// Conversion from a writer into an uncompressed `CompressWriter`.
+impl<'a> From<Box<dyn Write + 'a>> for CompressWriter<'a> {
+    fn from(writer: Box<dyn Write + 'a>) -> Self {
+        Self {
+            inner: Encoder::Plain(writer),
+        }
+    }
+}
+
+/// `Write` implementation for `CompressWriter`.
+impl Write for CompressWriter<'_> {
+    #[inline]
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut self.inner {
+            Encoder::Plain(writer) => writer.write(buf),
+            #[cfg(feature = "zstd")]
+            Encoder::Zstd(Some(encoder)) => encoder.write(buf),
+            #[cfg(feature = "zstd")]
+            Encoder::Zstd(None) => Err(io::Error::other("output has already been finished")),
+        }
+    }
+
+    #[inline]
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.inner {
+            Encoder::Plain(writer) => writer.flush(),
+            #[cfg(feature = "zstd")]
+            Encoder::Zstd(Some(encoder)) => encoder.flush(),
+            #[cfg(feature = "zstd")]
+            Encoder::Zstd(None) => Ok(()),
+        }
+    }
+}
+
+/// Retrieves the name of the input file provided by Hadoop, if any.
+#[cfg(any(
+    feature = "bzip2",
+    feature = "gzip",
+    feature = "snappy",
+    feature = "zstd"
+))]
+fn input_file(conf: &Configuration) -> Option<&str> {
+    conf.get("mapreduce.map.input.file")
+        .or_else(|| conf.get("map.input.file"))
+}
+
+/// Determines whether an input stream starts with the provided magic bytes.
+///
+/// Errors are ignored, as they will be surfaced by the record reader.
+#[cfg(any(
+    feature = "bzip2",
+    feature = "gzip",
+    feature = "snappy",
+    feature = "zstd"
+))]
+fn has_magic<R>(reader: &mut R, magic: &[u8]) -> bool
+where
+    R: BufRead,
+{
+    match reader.fill_buf() {
+        Ok(header) => header.starts_with(magic),
+        Err(_) => false,
+    }
+}
+
+/// Determines whether a file name has the extension of a compression codec.
+#[cfg(any(
+    feature = "bzip2",
+    feature = "gzip",
+    feature = "snappy",
+    feature = "zstd"
+))]
+fn is_compressed(file: &str) -> bool {
+    [".bz2", ".gz", ".snappy", ".zst"]
+        .iter()
+        .any(|extension| file.ends_with(extension))
+}
+
+/// Determines whether an input stream is compressed using gzip.
+#[cfg(feature = "gzip")]
+fn is_gzip<R>(reader: &mut R) -> bool
+where
+    R: BufRead,
+{
+    has_magic(reader, &[0x1f, 0x8b])
+}
+
+/// Determines whether an input stream is compressed using zstd.
+#[cfg(feature = "zstd")]
+fn is_zstd<R>(reader: &mut R) -> bool
+where
+    R: BufRead,
+{
+    has_magic(reader, &[0x28, 0xb5, 0x2f, 0xfd])
+}
+
+/// Determines whether an input stream is compressed using bzip2.
+#[cfg(feature = "bzip2")]
+fn is_bzip2<R>(reader: &mut R) -> bool
+where
+    R: BufRead,
+{
+    // the magic bytes are followed by the block size, from 1-9
+    (b'1'..=b'9').any(|size| has_magic(reader, &[b'B', b'Z', b'h', size]))
+}
+
+/// Determines whether an input stream is compressed using framed snappy.
+#[cfg(feature = "snappy")]
+fn is_snappy_framed<R>(reader: &mut R) -> bool
+where
+    R: BufRead,
+{
+    has_magic(reader, b"\xff\x06\x00\x00sNaPpY")
+}
+
+/// Determines whether an input stream is compressed using Hadoop snappy blocks.
+#[cfg(feature = "snappy")]
+fn is_snappy_blocks(conf: Option<&Configuration>) -> bool {
+    // the block format has no header, so must be selected explicitly
+    matches!(
+        conf.and_then(|conf| conf.get("efflux.input.codec")),
+        Some("snappy") | Some("org.apache.hadoop.io.compress.SnappyCodec")
+    )
+}
+
+/// Decoder for the block format written by the Hadoop `SnappyCodec`.
+///
+/// Each block is prefixed by its uncompressed length, followed by any
+/// number of compressed chunks each prefixed by their compressed length.
+/// All lengths are written as big endian 32-bit integers.
+#[cfg(feature = "snappy")]
+struct SnappyBlockDecoder<R> {
+    reader: R,
+    decoder: snap::raw::Decoder,
+    compressed: Vec<u8>,
+    decompressed: Vec<u8>,
+    position: usize,
+    remaining: usize,
+}
+
+#[cfg(feature = "snappy")]
+impl<R> SnappyBlockDecoder<R>
+where
+    R: Read,
+{
+    /// Creates a new `SnappyBlockDecoder` around a reader.
+    fn new(reader: R) -> Self {
+        Self {
+            reader,
+            decoder: snap::raw::Decoder::new(),
+            compressed: Vec::new(),
+            decompressed: Vec::new(),
+            position: 0,
+            remaining: 0,
+        }
+    }
+
+    /// Reads a length prefix, returning `None` at the end of the stream.
+    fn read_length(&mut self) -> io::Result<Option<usize>> {
+        let mut length = [0; 4];
+        match self.reader.read_exact(&mut length) {
+            Ok(()) => Ok(Some(u32::from_be_bytes(length) as usize)),
+            Err(ref err) if err.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Decompresses the next chunk of the stream, returning `false` at the end.
+    fn next_chunk(&mut self) -> io::Result<bool> {
+        // start a new block once the current block is exhausted
+        while self.remaining == 0 {
+            match self.read_length()? {
+                Some(length) => self.remaining = length,
+                None => return Ok(false),
+            }
+        }
+
+        let truncated = || io::Error::new(io::ErrorKind::UnexpectedEof, "truncated snappy block");
+        let length = self.read_length()?.ok_or_else(truncated)?;
+
+        self.compressed.resize(length, 0);
+        self.reader.read_exact(&mut self.compressed)?;
+
+        self.decompressed = self
+            .decoder
+            .decompress_vec(&self.compressed)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+
+        self.position = 0;
+        self.remaining = self.remaining.saturating_sub(self.decompressed.len());
+
+        Ok(true)
+    }
+}
+
+#[cfg(feature = "snappy")]
+impl<R> Read for SnappyBlockDecoder<R>
+where
+    R: Read,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.decompressed.len() {
+            if !self.next_chunk()? {
+                return Ok(0);
+            }
+        }
+
+        let available = &self.decompressed[self.position..];
+        let len = available.len().min(buf.len());
+
+        buf[..len].copy_from_slice(&available[..len]);
+        self.position += len;
+
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_passthrough_decoding() {
+        let env = Vec::<(String, String)>::new();
+        let conf = Configuration::with_env(env.into_iter());
+
+        let mut output = Vec::new();
+        let mut reader = decode(&b"plain input\n"[..], &conf).unwrap();
+
+        reader.read_to_end(&mut output).unwrap();
+
+        assert_eq!(output, b"plain input\n");
+    }
+
+    #[test]
+    #[cfg(feature = "gzip")]
+    fn test_gzip_decoding() {
+        use flate2::write::GzEncoder;
+        use flate2::Compression;
+
+        let env = Vec::<(String, String)>::new();
+        let conf = Configuration::with_env(env.into_iter());
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"compressed input\n").unwrap();
+
+        let input = encoder.finish().unwrap();
+
+        let mut output = Vec::new();
+        let mut reader = decode(&input[..], &conf).unwrap();
+
+        reader.read_to_end(&mut output).unwrap();
+
+        assert_eq!(output, b"compressed input\n");
+    }
+
+    #[test]
+    #[cfg(feature = "zstd")]
+    fn test_zstd_decoding() {
+        let env = Vec::<(String, String)>::new();
+        let conf = Configuration::with_env(env.into_iter());
+
+        let input = zstd::encode_all(&b"compressed input\n"[..], 0).unwrap();
+
+        let mut output = Vec::new();
+        let mut reader = decode(&input[..], &conf).unwrap();
+
+        reader.read_to_end(&mut output).unwrap();
+
+        assert_eq!(output, b"compressed input\n");
+    }
+
+    #[test]
+    fn test_passthrough_encoding() {
+        let env = Vec::<(String, String)>::new();
+        let conf = Configuration::with_env(env.into_iter());
+
+        let mut output = Vec::new();
+
+        encode(&mut output, &conf)
+            .unwrap()
+            .write_all(b"plain output\n")
+            .unwrap();
+
+        assert_eq!(output, b"plain output\n");
+
+        let env = vec![("efflux.output.codec", "unknown")];
+        let conf = Configuration::with_env(env.into_iter());
+
+        let err = encode(Vec::new(), &conf).err().unwrap();
+
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(err.to_string(), "unsupported output codec: unknown");
+    }
+
+    #[test]
+    #[cfg(feature = "zstd")]
+    fn test_zstd_encoding() {
+        let env = vec![
+            (
+                "efflux.output.codec",
+                "org.apache.hadoop.io.compress.ZStandardCodec",
+            ),
+            ("efflux.output.codec.level", "19"),
+        ];
+        let conf = Configuration::with_env(env.into_iter());
+
+        let mut output = Vec::new();
+        let mut writer = encode(&mut output, &conf).unwrap();
+
+        writer.write_all(b"compressed output\n").unwrap();
+        writer.finish().unwrap();
+
+        assert!(writer.write_all(b"more output\n").is_err());
+
+        drop(writer);
+
+        let decoded = zstd::decode_all(&output[..]).unwrap();
+
+        assert_eq!(decoded, b"compressed output\n");
+
+        let mut writer = encode(TestClosed, &conf).unwrap();
+
+        let _ = writer.write_all(b"compressed output\n");
+
+        assert_eq!(writer.finish().unwrap_err().to_string(), "output closed");
+    }
+
+    #[cfg(feature = "zstd")]
+    struct TestClosed;
+
+    #[cfg(feature = "zstd")]
+    impl Write for TestClosed {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            Err(io::Error::other("output closed"))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "snappy")]
+    fn test_snappy_framed_decoding() {
+        let env = Vec::<(String, String)>::new();
+        let conf = Configuration::with_env(env.into_iter());
+
+        let mut encoder = snap::write::FrameEncoder::new(Vec::new());
+        encoder.write_all(b"compressed input\n").unwrap();
+
+        let input = encoder.into_inner().unwrap();
+
+        let mut output = Vec::new();
+        let mut reader = decode(&input[..], &conf).unwrap();
+
+        reader.read_to_end(&mut output).unwrap();
+
+        assert_eq!(output, b"compressed input\n");
+    }
+
+    #[test]
+    #[cfg(feature = "snappy")]
+    fn test_snappy_block_decoding() {
+        let mut encoder = snap::raw::Encoder::new();
+        let mut input = Vec::new();
+
+        // a block containing two chunks, followed by a single chunk block
+        for chunks in [vec![&b"compressed "[..], b"input\n"], vec![b"more\n"]] {
+            let length = chunks.iter().map(|chunk| chunk.len()).sum::<usize>();
+            input.extend((length as u32).to_be_bytes());
+
+            for chunk in chunks {
+                let compressed = encoder.compress_vec(chunk).unwrap();
+                input.extend((compressed.len() as u32).to_be_bytes());
+                input.extend(compressed);
+            }
+        }
+
+        let env = vec![("efflux.input.codec", "snappy")];
+        let conf = Configuration::with_env(env.into_iter());
+
+        let mut output = Vec::new();
+        let mut reader = decode(&input[..], &conf).unwrap();
+
+        reader.read_to_end(&mut output).unwrap();
+
+        assert_eq!(output, b"compressed input\nmore\n");
+
+        let env = Vec::<(String, String)>::new();
+        let conf = Configuration::with_env(env.into_iter());
+
+        let mut output = Vec::new();
+        let mut reader = decode(&input[..], &conf).unwrap();
+
+        reader.read_to_end(&mut output).unwrap();
+
+        assert_eq!(output, input);
+    }
+
+    #[test]
+    #[cfg(feature = "bzip2")]
+    fn test_bzip2_decoding() {
+        use bzip2::write::BzEncoder;
+        use bzip2::Compression;
+
+        let mut encoder = BzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"compressed input\n").unwrap();
+
+        let input = encoder.finish().unwrap();
+
+        let mut output = Vec::new();
+        let mut reader = DecompressReader::new(&input[..]).unwrap();
+
+        assert_eq!(reader.codec(), Some("bzip2"));
+
+        reader.read_to_end(&mut output).unwrap();
+
+        assert_eq!(output, b"compressed input\n");
+    }
+
+    #[test]
+    fn test_decompressed_file_passthrough() {
+        let files = ["part-0.bz2", "part-0.gz", "part-0.snappy", "part-0.zst"];
+
+        for file in files {
+            let env = vec![("mapreduce.map.input.file", file)];
+            let conf = Configuration::with_env(env.into_iter());
+
+            let mut output = Vec::new();
+            let mut reader =
+                DecompressReader::with_configuration(&b"plain input\n"[..], &conf).unwrap();
+
+            assert_eq!(reader.codec(), None);
+
+            reader.read_to_end(&mut output).unwrap();
+
+            assert_eq!(output, b"plain input\n");
+        }
+    }
+
+    #[test]
+    fn test_decompress_passthrough() {
+        let input = &b"BZh plain input\n"[..];
+
+        let mut output = Vec::new();
+        let mut reader = DecompressReader::new(input).unwrap();
+
+        assert_eq!(reader.codec(), None);
+
+        reader.read_to_end(&mut output).unwrap();
+
+        assert_eq!(output, input);
+    }
+}
@@ -0,0 +1,288 @@
+//! Exposed structures based on the combination stage.
+//!
+//! This module offers the `Combiner` trait, which represents a local
+//! aggregation of map output before it's shuffled to the reducers. As
+//! the grouping logic is identical, any `Reducer` can act as a combiner
+//! and the `CombinerLifecycle` simply wraps the reduction lifecycle.
+//!
+//! For jobs which aggregate heavily (e.g. word counts), combining within the
+//! mapper itself via `InMapperCombiner` avoids writing each pair at all.
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::io;
+
+use crate::context::{Configuration, Context, Delimiters};
+use crate::error::EffluxError;
+use crate::io::{Input, Lifecycle};
+use crate::reducer::{Reducer, ReducerLifecycle};
+
+/// Trait to represent the combination stage of MapReduce.
+///
+/// Combiners receive grouped map output and emit partial aggregates, which
+/// is typically used to cut down on the volume of data being shuffled. This
+/// is implemented for all `Reducer` types, so there is nothing to implement.
+pub trait Combiner: Reducer {}
+
+/// Enables all `Reducer` types to act as `Combiner` types.
+impl<R> Combiner for R where R: Reducer {}
+
+/// Lifecycle structure to represent a combination.
+pub(crate) struct CombinerLifecycle<C>
+where
+    C: Combiner,
+{
+    inner: ReducerLifecycle<C>,
+}
+
+/// Basic creation for `CombinerLifecycle`
+impl<C> CombinerLifecycle<C>
+where
+    C: Combiner,
+{
+    /// Constructs a new `CombinerLifecycle` instance.
+    pub(crate) fn new(combiner: C) -> Self {
+        Self {
+            inner: ReducerLifecycle::new(combiner),
+        }
+    }
+}
+
+/// `Lifecycle` implementation for the combination stage.
+impl<C> Lifecycle for CombinerLifecycle<C>
+where
+    C: Combiner,
+{
+    /// Creates all required state for the lifecycle.
+    ///
+    /// As combiners run on the map side, the delimiters are replaced to
+    /// make sure that map output is used for both input and output.
+    #[inline]
+    fn on_start(&mut self, ctx: &mut Context) {
+        let delim = Delimiters::combine(ctx.configuration());
+        ctx.insert(delim);
+        self.inner.on_start(ctx);
+    }
+
+    /// Passes the input stream through to the inner reduction lifecycle.
+    #[inline]
+    fn on_stream(&mut self, input: &mut Input<'_>, ctx: &mut Context) -> io::Result<()> {
+        self.inner.on_stream(input, ctx)
+    }
+
+    /// Passes each entry through to the inner reduction lifecycle.
+    #[inline]
+    fn on_entry(&mut self, input: &[u8], ctx: &mut Context) {
+        self.inner.on_entry(input, ctx);
+    }
+
+    /// Passes any skipped failures through to the inner reduction lifecycle.
+    #[inline]
+    fn on_error(&mut self, err: &EffluxError, ctx: &mut Context) {
+        self.inner.on_error(err, ctx);
+    }
+
+    /// Finalizes the lifecycle by emitting any leftover pairs.
+    #[inline]
+    fn on_end(&mut self, ctx: &mut Context) {
+        self.inner.on_end(ctx);
+    }
+}
+
+/// Default number of keys buffered by an `InMapperCombiner`.
+const DEFAULT_COMBINE_KEYS: usize = 10_000;
+
+/// Aggregator to combine values by key within a `Mapper`.
+///
+/// Values are combined into a single aggregate per key using the provided
+/// closure, and the aggregates are only written once the number of buffered
+/// keys exceeds the limit, or when `flush` is called (which should happen in
+/// `Mapper::cleanup`). The limit is read from `efflux.map.combine.max.keys`
+/// in the `Configuration` (defaulting to `10000`), unless set via `with_limit`.
+///
+/// ```rust
+/// use efflux::combiner::InMapperCombiner;
+/// use efflux::prelude::*;
+///
+/// struct WordCounter(InMapperCombiner<usize, fn(&mut usize, usize)>);
+///
+/// impl Mapper for WordCounter {
+///     fn map(&mut self, _key: usize, value: &[u8], ctx: &mut Context) {
+///         for word in value.split(|b| *b == b' ') {
+///             self.0.insert(word, 1, ctx);
+///         }
+///     }
+///
+///     fn cleanup(&mut self, ctx: &mut Context) {
+///         let _ = self.0.flush(ctx);
+///     }
+/// }
+///
+/// let counter = WordCounter(InMapperCombiner::new(|total, count| *total += count));
+/// ```
+pub struct InMapperCombiner<V, F>
+where
+    F: FnMut(&mut V, V),
+{
+    combine: F,
+    limit: Option<usize>,
+    entries: HashMap<Vec<u8>, V>,
+}
+
+impl<V, F> InMapperCombiner<V, F>
+where
+    V: Display,
+    F: FnMut(&mut V, V),
+{
+    /// Creates a new `InMapperCombiner` using the provided aggregation.
+    pub fn new(combine: F) -> Self {
+        Self {
+            combine,
+            limit: None,
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Sets the number of keys to buffer before flushing.
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Returns the number of keys currently buffered.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Determines whether any keys are currently buffered.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Combines a value into the aggregate of a key.
+    ///
+    /// If this results in more keys than the limit, all buffered aggregates
+    /// are flushed to the `Context`. Any write errors are surfaced by the
+    /// lifecycle, as with `Context::write`.
+    pub fn insert(&mut self, key: &[u8], value: V, ctx: &mut Context) {
+        match self.entries.get_mut(key) {
+            Some(aggregate) => (self.combine)(aggregate, value),
+            None => {
+                self.entries.insert(key.to_vec(), value);
+            }
+        }
+
+        let limit = *self.limit.get_or_insert_with(|| {
+            ctx.get::<Configuration>()
+                .and_then(|conf| conf.get_i64("efflux.map.combine.max.keys"))
+                .map_or(DEFAULT_COMBINE_KEYS, |limit| limit.max(0) as usize)
+        });
+
+        if self.entries.len() > limit {
+            let _ = self.flush(ctx);
+        }
+    }
+
+    /// Writes all buffered aggregates to the `Context`, removing them.
+    pub fn flush(&mut self, ctx: &mut Context) -> Result<(), EffluxError> {
+        for (key, aggregate) in self.entries.drain() {
+            ctx.write(&key, aggregate.to_string())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::execute;
+    use crate::local::Buffer;
+    use crate::mapper::{Mapper, MapperLifecycle};
+
+    #[test]
+    fn test_combiner_lifecycle() {
+        let env = vec![
+            ("mapreduce.task.ismap", "true"),
+            ("stream.map.input.field.separator", ":"),
+            ("stream.map.output.field.separator", "|"),
+        ];
+
+        let buffer = Buffer::default();
+        let mut ctx = Context::with_output(Box::new(buffer.clone()));
+
+        ctx.insert(Configuration::with_env(env.into_iter()));
+
+        let combiner = |key: &[u8], values: &[&[u8]], ctx: &mut Context| {
+            let sum = values
+                .iter()
+                .map(|value| std::str::from_utf8(value).unwrap())
+                .map(|value| value.parse::<usize>().unwrap())
+                .sum::<usize>();
+
+            ctx.write_fmt(std::str::from_utf8(key).unwrap(), sum)
+                .unwrap();
+        };
+
+        let input = &b"one|1\none|2\ntwo|1\n"[..];
+
+        execute(input, CombinerLifecycle::new(combiner), &mut ctx).unwrap();
+
+        assert_eq!(buffer.take(), b"one|3\ntwo|1\n");
+    }
+
+    #[test]
+    fn test_in_mapper_combining() {
+        let cases = vec![(None, 3), (Some("1"), 5)];
+
+        for (limit, writes) in cases {
+            let mut ctx = Context::buffered();
+
+            if let Some(limit) = limit {
+                ctx.get_mut::<Configuration>()
+                    .unwrap()
+                    .insert("efflux.map.combine.max.keys", limit);
+            }
+
+            let combiner = InMapperCombiner::new(|total: &mut usize, count| *total += count);
+            let input = &b"one two\ntwo three\nthree three\n"[..];
+
+            execute(input, MapperLifecycle::new(TestCounter(combiner)), &mut ctx).unwrap();
+
+            let mut output = ctx.take_output();
+
+            assert_eq!(output.len(), writes);
+
+            // flushes happen in an arbitrary order, so sum the partials
+            let mut totals = HashMap::new();
+            for (key, value) in output.drain(..) {
+                let value = std::str::from_utf8(&value).unwrap();
+                *totals.entry(key).or_insert(0) += value.parse::<usize>().unwrap();
+            }
+
+            assert_eq!(totals.len(), 3);
+            assert_eq!(totals[&b"one".to_vec()], 1);
+            assert_eq!(totals[&b"two".to_vec()], 2);
+            assert_eq!(totals[&b"three".to_vec()], 3);
+        }
+    }
+
+    struct TestCounter<F>(InMapperCombiner<usize, F>)
+    where
+        F: FnMut(&mut usize, usize);
+
+    impl<F> Mapper for TestCounter<F>
+    where
+        F: FnMut(&mut usize, usize),
+    {
+        fn map(&mut self, _key: usize, value: &[u8], ctx: &mut Context) {
+            for word in value.split(|b| *b == b' ') {
+                self.0.insert(word, 1, ctx);
+            }
+        }
+
+        fn cleanup(&mut self, ctx: &mut Context) {
+            self.0.flush(ctx).unwrap();
+        }
+    }
+}
@@ -1,15 +1,24 @@
 //! Module to provide representation of the Hadoop `Configuration` class.
+use std::borrow::Cow;
 use std::collections::HashMap;
 use std::env;
+use std::io::{self, Read};
+use std::str::FromStr;
 
 /// Configuration struct to represent a Hadoop configuration.
 ///
 /// Internally this is simply a `String` -> `String` map, as
 /// we don't have enough information to parse with. The struct
 /// implementation exists as a compatibility layer.
+///
+/// Keys are stored with dots replaced by underscores (as Hadoop does when
+/// passing keys through the environment), but the original form of each
+/// shimmed key is retained and can be retrieved via `iter_original`.
 #[derive(Debug, Default)]
 pub struct Configuration {
     inner: HashMap<String, String>,
+    originals: HashMap<String, String>,
+    case_insensitive: bool,
 }
 
 impl Configuration {
@@ -19,15 +28,28 @@ impl Configuration {
     }
 
     /// Constructs a new `Configuration` using a custom input.
+    ///
+    /// Any keys containing uppercase characters are ignored, as Hadoop
+    /// never provides uppercase keys. See `with_env_opts` to keep them.
     pub fn with_env<I, T>(pairs: I) -> Self
+    where
+        T: Into<String>,
+        I: Iterator<Item = (T, T)>,
+    {
+        Self::with_env_opts(pairs, false)
+    }
+
+    /// Constructs a new `Configuration` using a custom input and options.
+    ///
+    /// If `keep_uppercase` is set, keys containing uppercase characters
+    /// will be kept (e.g. custom environment variables like `MY_PARAM`).
+    pub fn with_env_opts<I, T>(pairs: I, keep_uppercase: bool) -> Self
     where
         T: Into<String>,
         I: Iterator<Item = (T, T)>,
     {
         // create container
-        let mut conf = Self {
-            inner: HashMap::new(),
-        };
+        let mut conf = Self::default();
 
         // iterate all pairs
         for (key, val) in pairs {
@@ -35,7 +57,7 @@ impl Configuration {
             let val = val.into();
 
             // hadoop never has uppercased values
-            if key.chars().any(|c| c.is_uppercase()) {
+            if !keep_uppercase && key.chars().any(|c| c.is_uppercase()) {
                 continue;
             }
 
@@ -46,17 +68,244 @@ impl Configuration {
         conf
     }
 
+    /// Constructs a new `Configuration` from a Hadoop XML configuration.
+    ///
+    /// This reads all `<property>` elements (as found in files such as
+    /// `mapred-site.xml`), inserting each `<name>` and `<value>` pair. Any
+    /// properties without a `<value>` are treated as having an empty value.
+    pub fn from_xml_reader<R>(mut reader: R) -> io::Result<Self>
+    where
+        R: Read,
+    {
+        // read the entire document
+        let mut xml = String::new();
+        reader.read_to_string(&mut xml)?;
+
+        // create container
+        let mut conf = Self::default();
+        let xml = strip_comments(&xml);
+        let mut rest = &xml[..];
+
+        // iterate all property elements
+        while let Some((property, remaining)) = find_element(rest, "property") {
+            let name = match find_element(property, "name") {
+                Some((name, _)) if !name.trim().is_empty() => name.trim(),
+                _ => return Err(invalid_xml("property is missing a name")),
+            };
+
+            let value = find_element(property, "value")
+                .map(|(value, _)| value)
+                .unwrap_or("");
+
+            conf.insert(unescape(name)?, unescape(value)?);
+            rest = remaining;
+        }
+
+        Ok(conf)
+    }
+
+    /// Constructs a new `Configuration` from a JSON object of strings.
+    ///
+    /// All keys are inserted via `insert`, and so are shimmed in the same
+    /// way as any other key. This requires the `serde` feature.
+    #[cfg(feature = "serde")]
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        let pairs = serde_json::from_str::<HashMap<String, String>>(json)?;
+        Ok(Self::with_env_opts(pairs.into_iter(), true))
+    }
+
+    /// Serializes the `Configuration` into a JSON object of strings.
+    ///
+    /// Keys are written in their original (inserted) form, so the output can
+    /// be read back via `from_json`, and sorted to ensure stable output. This
+    /// requires the `serde` feature.
+    #[cfg(feature = "serde")]
+    pub fn to_json(&self) -> String {
+        let pairs = self
+            .iter_original()
+            .collect::<std::collections::BTreeMap<_, _>>();
+        serde_json::to_string(&pairs).expect("string maps always serialize")
+    }
+
     /// Retrieves a potential `Configuration` value.
+    ///
+    /// When case insensitive lookups are enabled, an exact match of the key
+    /// always takes precedence. Otherwise the key is compared against all keys
+    /// ignoring case; if many keys match, the value of the (lexicographically)
+    /// smallest stored key is returned, to keep lookups deterministic.
     pub fn get(&self, key: &str) -> Option<&str> {
         // shimming for hadoop
-        let opt = if key.contains('.') {
-            self.inner.get(&key.replace(".", "_"))
+        let key = if key.contains('.') {
+            Cow::Owned(key.replace(".", "_"))
         } else {
-            self.inner.get(key)
+            Cow::Borrowed(key)
         };
 
-        // better than &String
-        opt.map(|s| s.as_ref())
+        if let Some(val) = self.inner.get(key.as_ref()) {
+            return Some(val);
+        }
+
+        if !self.case_insensitive {
+            return None;
+        }
+
+        // fall back to a case folded match
+        self.inner
+            .iter()
+            .filter(|(stored, _)| stored.eq_ignore_ascii_case(&key))
+            .min_by(|(left, _), (right, _)| left.cmp(right))
+            .map(|(_, val)| val.as_ref())
+    }
+
+    /// Retrieves a `Configuration` value, falling back to a default.
+    pub fn get_or<'a>(&'a self, key: &str, default: &'a str) -> &'a str {
+        self.get(key).unwrap_or(default)
+    }
+
+    /// Retrieves and parses a potential `Configuration` value.
+    ///
+    /// This will return `None` if the key does not exist, or if the
+    /// value cannot be parsed into the requested type.
+    pub fn get_as<T>(&self, key: &str) -> Option<T>
+    where
+        T: FromStr,
+    {
+        self.get(key).and_then(|val| val.parse().ok())
+    }
+
+    /// Retrieves a potential `Configuration` value as a `bool`.
+    ///
+    /// Values of `true` and `false` are matched case-insensitively, with
+    /// `1` and `0` also being accepted. Any other value returns `None`.
+    pub fn get_bool(&self, key: &str) -> Option<bool> {
+        match self.get(key)? {
+            "1" => Some(true),
+            "0" => Some(false),
+            val if val.eq_ignore_ascii_case("true") => Some(true),
+            val if val.eq_ignore_ascii_case("false") => Some(false),
+            _ => None,
+        }
+    }
+
+    /// Retrieves a potential `Configuration` value as an `i64`.
+    ///
+    /// Underscore separators (e.g. `1_000`) are ignored, and the binary
+    /// size suffixes supported by Hadoop (`k`, `m`, `g`, `t`, `p`, `e`)
+    /// are applied case-insensitively (e.g. `64k` returns `65536`).
+    pub fn get_i64(&self, key: &str) -> Option<i64> {
+        let val = self.get(key)?.trim().replace('_', "");
+
+        // apply any binary size suffixes
+        let shift = match val.chars().last()?.to_ascii_lowercase() {
+            'k' => 10,
+            'm' => 20,
+            'g' => 30,
+            't' => 40,
+            'p' => 50,
+            'e' => 60,
+            _ => 0,
+        };
+
+        // strip the suffix when present
+        let digits = match shift {
+            0 => &val[..],
+            _ => &val[..val.len() - 1],
+        };
+
+        digits.parse::<i64>().ok()?.checked_mul(1 << shift)
+    }
+
+    /// Retrieves a potential `Configuration` value as an `f64`.
+    ///
+    /// Underscore separators (e.g. `1_000.5`) are ignored when parsing.
+    pub fn get_f64(&self, key: &str) -> Option<f64> {
+        self.get(key)?.trim().replace('_', "").parse().ok()
+    }
+
+    /// Retrieves a potential `Configuration` value as a list of values.
+    ///
+    /// Values are split on commas and each element is trimmed of whitespace.
+    /// Empty elements (e.g. from `a,,b` or a trailing comma) are dropped, so
+    /// an empty value will result in an empty list rather than `None`.
+    pub fn get_list(&self, key: &str) -> Option<Vec<&str>> {
+        let list = self
+            .get(key)?
+            .split(',')
+            .map(str::trim)
+            .filter(|val| !val.is_empty())
+            .collect();
+
+        Some(list)
+    }
+
+    /// Returns whether lookups via `get` ignore the case of keys.
+    #[inline]
+    pub fn is_case_insensitive(&self) -> bool {
+        self.case_insensitive
+    }
+
+    /// Sets whether lookups via `get` ignore the case of keys.
+    ///
+    /// This is disabled by default, as Hadoop keys are always lowercase.
+    #[inline]
+    pub fn set_case_insensitive(&mut self, case_insensitive: bool) {
+        self.case_insensitive = case_insensitive;
+    }
+
+    /// Returns an iterator over all keys in the `Configuration`.
+    ///
+    /// Keys are returned in their stored form, meaning that any dots will
+    /// have been replaced with underscores (e.g. `mapreduce_task_ismap`).
+    /// There is no guarantee as to the ordering of the keys.
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.inner.keys().map(|key| key.as_ref())
+    }
+
+    /// Returns an iterator over all key/value pairs in the `Configuration`.
+    ///
+    /// Keys are returned in the same (underscored) form as with `keys`, and
+    /// there is no guarantee as to the ordering of the pairs.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.inner
+            .iter()
+            .map(|(key, val)| (key.as_ref(), val.as_ref()))
+    }
+
+    /// Returns an iterator over all key/value pairs, using their original keys.
+    ///
+    /// Keys are returned in the form they were inserted, so a key inserted as
+    /// `mapred.job.id` is returned with its dots (unlike `iter`). Again, there
+    /// is no guarantee as to the ordering of the pairs.
+    pub fn iter_original(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.inner.iter().map(move |(key, val)| {
+            let key = self.originals.get(key).unwrap_or(key);
+            (key.as_ref(), val.as_ref())
+        })
+    }
+
+    /// Returns an iterator over all key/value pairs with a key prefix.
+    ///
+    /// The prefix is shimmed in the same way as keys (so `mapreduce.` will
+    /// match `mapreduce_task_ismap`), and keys are returned in the same
+    /// (underscored) form as with `iter`, again without any ordering.
+    pub fn with_prefix<'a>(&'a self, prefix: &str) -> impl Iterator<Item = (&'a str, &'a str)> {
+        let prefix = prefix.replace(".", "_");
+        self.iter().filter(move |(key, _)| key.starts_with(&prefix))
+    }
+
+    /// Merges another `Configuration` into this `Configuration`.
+    ///
+    /// Values in `other` take precedence, overriding any existing values for
+    /// the same key. As both sides are already shimmed, dotted and underscored
+    /// forms of the same key (e.g. `mapred.job.id`) are treated as equal.
+    pub fn merge(&mut self, other: Configuration) {
+        // original keys are taken from the side the value is taken from
+        for key in other.inner.keys() {
+            self.originals.remove(key);
+        }
+
+        self.originals.extend(other.originals);
+        self.inner.extend(other.inner);
     }
 
     /// Inserts a key/value pair into the `Configuration`.
@@ -65,16 +314,164 @@ impl Configuration {
         T: Into<String>,
     {
         // convert to String
-        let mut key_str = key.into();
+        let key_str = key.into();
 
-        // hadoop compatibility
+        // hadoop compatibility, retaining the original key
         if key_str.contains('.') {
-            key_str = key_str.replace(".", "_");
+            let shimmed = key_str.replace(".", "_");
+            self.originals.insert(shimmed.clone(), key_str);
+            self.inner.insert(shimmed, val.into());
+            return;
         }
 
         // insert into the internal mapping
+        self.originals.remove(&key_str);
         self.inner.insert(key_str, val.into());
     }
+
+    /// Removes a key from the `Configuration`, returning the value if present.
+    ///
+    /// The key is shimmed in the same way as with `insert`, so a dotted key
+    /// will remove the underscored form stored internally. Unlike `get`, this
+    /// always requires an exact match, regardless of case sensitivity.
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        let key = if key.contains('.') {
+            Cow::Owned(key.replace(".", "_"))
+        } else {
+            Cow::Borrowed(key)
+        };
+
+        self.originals.remove(key.as_ref());
+        self.inner.remove(key.as_ref())
+    }
+
+    /// Returns the number of keys in the `Configuration`.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    /// Returns whether the `Configuration` contains no keys.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+}
+
+/// Removes all comments from an XML document.
+fn strip_comments(xml: &str) -> String {
+    let mut output = String::with_capacity(xml.len());
+    let mut rest = xml;
+
+    while let Some(start) = rest.find("<!--") {
+        output.push_str(&rest[..start]);
+        rest = match rest[start..].find("-->") {
+            Some(end) => &rest[start + end + 3..],
+            None => "",
+        };
+    }
+
+    output.push_str(rest);
+    output
+}
+
+/// Finds the first XML element with the provided tag name.
+///
+/// The contents of the element are returned, alongside the remainder
+/// of the document following the element. Self-closing elements are
+/// treated as having empty contents.
+fn find_element<'a>(xml: &'a str, tag: &str) -> Option<(&'a str, &'a str)> {
+    let open = format!("<{}", tag);
+    let close = format!("</{}>", tag);
+    let mut offset = 0;
+
+    loop {
+        let start = xml[offset..].find(&open)? + offset + open.len();
+        let after = &xml[start..];
+
+        // skip tags which only share a prefix (e.g. <properties>)
+        match after.chars().next()? {
+            '>' | '/' => (),
+            c if c.is_whitespace() => (),
+            _ => {
+                offset = start;
+                continue;
+            }
+        }
+
+        // find the end of the opening tag
+        let end = after.find('>')?;
+
+        // self-closing elements have no contents
+        if after[..end].ends_with('/') {
+            return Some(("", &after[end + 1..]));
+        }
+
+        // find the closing tag
+        let body = &after[end + 1..];
+        let close_idx = body.find(&close)?;
+
+        return Some((&body[..close_idx], &body[close_idx + close.len()..]));
+    }
+}
+
+/// Unescapes XML text content, including entities and CDATA sections.
+fn unescape(text: &str) -> io::Result<String> {
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(idx) = rest.find(['&', '<']) {
+        output.push_str(&rest[..idx]);
+        rest = &rest[idx..];
+
+        // CDATA sections are copied as-is
+        if let Some(cdata) = rest.strip_prefix("<![CDATA[") {
+            let end = cdata
+                .find("]]>")
+                .ok_or_else(|| invalid_xml("unterminated CDATA section"))?;
+
+            output.push_str(&cdata[..end]);
+            rest = &cdata[end + 3..];
+            continue;
+        }
+
+        // any other markup is invalid inside text
+        if rest.starts_with('<') {
+            return Err(invalid_xml("unexpected markup in text"));
+        }
+
+        // parse the entity between & and ;
+        let end = rest
+            .find(';')
+            .ok_or_else(|| invalid_xml("unterminated entity"))?;
+
+        let entity = match &rest[1..end] {
+            "lt" => '<',
+            "gt" => '>',
+            "amp" => '&',
+            "quot" => '"',
+            "apos" => '\'',
+            other => {
+                let code = match other.strip_prefix("#x") {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                    None => other.strip_prefix('#').and_then(|dec| dec.parse().ok()),
+                };
+                code.and_then(std::char::from_u32)
+                    .ok_or_else(|| invalid_xml("unknown entity"))?
+            }
+        };
+
+        output.push(entity);
+        rest = &rest[end + 1..];
+    }
+
+    output.push_str(rest);
+    Ok(output)
+}
+
+/// Creates an `io::Error` to represent an invalid XML document.
+fn invalid_xml(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
 }
 
 #[cfg(test)]
@@ -96,6 +493,96 @@ mod tests {
         assert_eq!(conf.inner.get("mapred_job_id"), Some(&"123".to_owned()));
     }
 
+    #[test]
+    fn test_config_uppercase_keys() {
+        let env = vec![("FAKE_VAR", "1"), ("mapred.job.id", "123")];
+        let conf = Configuration::with_env_opts(env.into_iter(), true);
+
+        assert_eq!(conf.get("FAKE_VAR"), Some("1"));
+        assert_eq!(conf.get("mapred.job.id"), Some("123"));
+    }
+
+    #[test]
+    fn test_config_from_xml() {
+        let xml = r#"<?xml version="1.0"?>
+            <configuration>
+              <!-- <property><name>commented</name><value>1</value></property> -->
+              <property>
+                <name>mapred.job.id</name>
+                <value>123</value>
+              </property>
+              <property>
+                <name>stream.map.output.field.separator</name>
+                <value>&lt;&amp;&#62;</value>
+                <description>escaped</description>
+              </property>
+              <property final="true">
+                <name>mapred.job.name</name>
+                <value><![CDATA[a <raw> name]]></value>
+              </property>
+              <property>
+                <name>mapred.empty</name>
+                <value/>
+              </property>
+            </configuration>
+        "#;
+
+        let conf = Configuration::from_xml_reader(xml.as_bytes()).unwrap();
+
+        assert_eq!(conf.get("commented"), None);
+        assert_eq!(conf.get("mapred.job.id"), Some("123"));
+        assert_eq!(conf.get("stream.map.output.field.separator"), Some("<&>"));
+        assert_eq!(conf.get("mapred.job.name"), Some("a <raw> name"));
+        assert_eq!(conf.get("mapred.empty"), Some(""));
+    }
+
+    #[test]
+    fn test_invalid_xml() {
+        let xml = "<configuration><property><value>1</value></property></configuration>";
+        let conf = Configuration::from_xml_reader(xml.as_bytes());
+
+        assert_eq!(conf.unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        let xml = "<property><name>key</name><value>&unknown;</value></property>";
+        let conf = Configuration::from_xml_reader(xml.as_bytes());
+
+        assert_eq!(conf.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_config_json() {
+        let json = r#"{"mapred.job.id":"123","stream_map_key":"abc"}"#;
+        let conf = Configuration::from_json(json).unwrap();
+
+        assert_eq!(conf.get("mapred.job.id"), Some("123"));
+        assert_eq!(conf.get("stream.map.key"), Some("abc"));
+        assert_eq!(
+            conf.to_json(),
+            r#"{"mapred.job.id":"123","stream_map_key":"abc"}"#
+        );
+
+        let conf = Configuration::from_json(&conf.to_json()).unwrap();
+
+        assert_eq!(conf.get("mapred.job.id"), Some("123"));
+        assert_eq!(conf.to_json(), json);
+        assert!(Configuration::from_json("[]").is_err());
+    }
+
+    #[test]
+    fn test_config_merging() {
+        let base = vec![("mapred.job.id", "123"), ("mapred.job.name", "base")];
+        let over = vec![("mapred_job_id", "456"), ("mapred.reduce.tasks", "2")];
+
+        let mut conf = Configuration::with_env(base.into_iter());
+
+        conf.merge(Configuration::with_env(over.into_iter()));
+
+        assert_eq!(conf.get("mapred.job.id"), Some("456"));
+        assert_eq!(conf.get("mapred.job.name"), Some("base"));
+        assert_eq!(conf.get("mapred.reduce.tasks"), Some("2"));
+    }
+
     #[test]
     fn test_retrieval_shimming() {
         let env = vec![("mapred.job.id", "123"), ("mapred_job_id", "123")];
@@ -105,6 +592,162 @@ mod tests {
         assert_eq!(conf.get("mapred_job_id"), Some("123"));
     }
 
+    #[test]
+    fn test_case_insensitive_retrieval() {
+        let env = vec![("Mapred.Job.Id", "123"), ("MAPRED_JOB_NAME", "job")];
+        let mut conf = Configuration::with_env_opts(env.into_iter(), true);
+
+        assert_eq!(conf.get("mapred.job.id"), None);
+        assert!(!conf.is_case_insensitive());
+
+        conf.set_case_insensitive(true);
+
+        assert_eq!(conf.get("mapred.job.id"), Some("123"));
+        assert_eq!(conf.get("mapred.job.name"), Some("job"));
+
+        conf.insert("mapred.job.name", "exact");
+        conf.insert("MAPRED_job_NAME", "folded");
+
+        assert_eq!(conf.get("mapred.job.name"), Some("exact"));
+        assert_eq!(conf.get("Mapred.Job.Name"), Some("job"));
+    }
+
+    #[test]
+    fn test_default_retrieval() {
+        let env = vec![("mapred.job.id", "123")];
+        let conf = Configuration::with_env(env.into_iter());
+
+        assert_eq!(conf.get_or("mapred.job.id", "456"), "123");
+        assert_eq!(conf.get_or("mapred_job_id", "456"), "123");
+        assert_eq!(conf.get_or("mapred.job.name", "456"), "456");
+    }
+
+    #[test]
+    fn test_typed_retrieval() {
+        let env = vec![("mapred.reduce.tasks", "12"), ("mapred_job_name", "job")];
+        let conf = Configuration::with_env(env.into_iter());
+
+        assert_eq!(conf.get_as::<usize>("mapred.reduce.tasks"), Some(12));
+        assert_eq!(conf.get_as::<usize>("mapred_job_name"), None);
+        assert_eq!(conf.get_as::<usize>("mapred.missing"), None);
+        assert_eq!(
+            conf.get_as::<String>("mapred.job.name"),
+            Some("job".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_boolean_retrieval() {
+        let env = vec![
+            ("flag.one", "true"),
+            ("flag.two", "FALSE"),
+            ("flag.three", "1"),
+            ("flag.four", "0"),
+            ("flag.five", "yes"),
+        ];
+        let conf = Configuration::with_env(env.into_iter());
+
+        assert_eq!(conf.get_bool("flag.one"), Some(true));
+        assert_eq!(conf.get_bool("flag_two"), Some(false));
+        assert_eq!(conf.get_bool("flag.three"), Some(true));
+        assert_eq!(conf.get_bool("flag.four"), Some(false));
+        assert_eq!(conf.get_bool("flag.five"), None);
+        assert_eq!(conf.get_bool("flag.missing"), None);
+    }
+
+    #[test]
+    fn test_numeric_retrieval() {
+        let env = vec![
+            ("num.int", "12"),
+            ("num.neg", "-3"),
+            ("num.sep", "1_000"),
+            ("num.kilo", "64k"),
+            ("num.mega", "2M"),
+            ("num.float", "1.5"),
+            ("num.text", "abc"),
+            ("num.over", "8e"),
+        ];
+        let conf = Configuration::with_env(env.into_iter());
+
+        assert_eq!(conf.get_i64("num.int"), Some(12));
+        assert_eq!(conf.get_i64("num_neg"), Some(-3));
+        assert_eq!(conf.get_i64("num.sep"), Some(1000));
+        assert_eq!(conf.get_i64("num.kilo"), Some(65536));
+        assert_eq!(conf.get_i64("num.mega"), Some(2097152));
+        assert_eq!(conf.get_i64("num.float"), None);
+        assert_eq!(conf.get_i64("num.text"), None);
+        assert_eq!(conf.get_i64("num.over"), None);
+        assert_eq!(conf.get_i64("num.missing"), None);
+
+        assert_eq!(conf.get_f64("num.float"), Some(1.5));
+        assert_eq!(conf.get_f64("num_int"), Some(12.0));
+        assert_eq!(conf.get_f64("num.sep"), Some(1000.0));
+        assert_eq!(conf.get_f64("num.text"), None);
+        assert_eq!(conf.get_f64("num.missing"), None);
+    }
+
+    #[test]
+    fn test_list_retrieval() {
+        let env = vec![
+            ("mapreduce.job.cache.files", " a.txt, b.txt ,c.txt,"),
+            ("efflux.empty.list", ""),
+            ("efflux.sparse.list", "a,,b"),
+        ];
+
+        let conf = Configuration::with_env(env.into_iter());
+
+        assert_eq!(
+            conf.get_list("mapreduce.job.cache.files"),
+            Some(vec!["a.txt", "b.txt", "c.txt"])
+        );
+        assert_eq!(conf.get_list("efflux.empty.list"), Some(vec![]));
+        assert_eq!(conf.get_list("efflux_sparse_list"), Some(vec!["a", "b"]));
+        assert_eq!(conf.get_list("efflux.missing.list"), None);
+    }
+
+    #[test]
+    fn test_config_iteration() {
+        let env = vec![("mapred.job.id", "123"), ("stream_map_key", "abc")];
+        let conf = Configuration::with_env(env.into_iter());
+
+        let mut keys = conf.keys().collect::<Vec<_>>();
+        let mut pairs = conf.iter().collect::<Vec<_>>();
+
+        keys.sort_unstable();
+        pairs.sort_unstable();
+
+        assert_eq!(keys, vec!["mapred_job_id", "stream_map_key"]);
+        assert_eq!(
+            pairs,
+            vec![("mapred_job_id", "123"), ("stream_map_key", "abc")]
+        );
+    }
+
+    #[test]
+    fn test_prefix_iteration() {
+        let env = vec![
+            ("mapreduce.task.ismap", "true"),
+            ("mapreduce.job.id", "123"),
+            ("stream.map.key", "abc"),
+        ];
+        let conf = Configuration::with_env(env.into_iter());
+
+        let mut pairs = conf.with_prefix("mapreduce.").collect::<Vec<_>>();
+
+        pairs.sort_unstable();
+
+        assert_eq!(
+            pairs,
+            vec![
+                ("mapreduce_job_id", "123"),
+                ("mapreduce_task_ismap", "true")
+            ]
+        );
+        assert_eq!(conf.with_prefix("stream_").count(), 1);
+        assert_eq!(conf.with_prefix("mapred.").count(), 0);
+        assert_eq!(conf.with_prefix("efflux").count(), 0);
+    }
+
     #[test]
     fn test_insertion_shimming() {
         let env = Vec::<(String, String)>::new();
@@ -114,4 +757,56 @@ mod tests {
 
         assert_eq!(conf.get("mapred_job_id"), Some("123"));
     }
+
+    #[test]
+    fn test_original_keys() {
+        let env = vec![("mapred.job.id", "123"), ("efflux_key", "value")];
+        let mut conf = Configuration::with_env(env.into_iter());
+
+        let mut pairs = conf.iter_original().collect::<Vec<_>>();
+        pairs.sort();
+
+        assert_eq!(
+            pairs,
+            vec![("efflux_key", "value"), ("mapred.job.id", "123")]
+        );
+
+        // the most recent form of a key is retained
+        conf.insert("mapred_job_id", "456");
+        conf.insert("efflux.key", "other");
+
+        let mut other = Configuration::default();
+        other.insert("mapred.job.id", "789");
+        conf.merge(other);
+
+        let mut pairs = conf.iter_original().collect::<Vec<_>>();
+        pairs.sort();
+
+        assert_eq!(
+            pairs,
+            vec![("efflux.key", "other"), ("mapred.job.id", "789")]
+        );
+
+        conf.remove("mapred_job_id");
+        conf.insert("mapred_job_id", "000");
+
+        assert_eq!(
+            conf.iter_original().find(|(_, val)| *val == "000"),
+            Some(("mapred_job_id", "000"))
+        );
+    }
+
+    #[test]
+    fn test_config_removal() {
+        let env = vec![("mapred.job.id", "123"), ("efflux_key", "value")];
+        let mut conf = Configuration::with_env(env.into_iter());
+
+        assert_eq!(conf.len(), 2);
+        assert_eq!(conf.remove("mapred.job.id"), Some("123".to_owned()));
+        assert_eq!(conf.remove("mapred_job_id"), None);
+        assert_eq!(conf.remove("efflux_key"), Some("value".to_owned()));
+
+        assert_eq!(conf.len(), 0);
+        assert!(conf.is_empty());
+    }
 }
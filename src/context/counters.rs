@@ -0,0 +1,212 @@
+//! Counter bindings to aggregate Hadoop counters before reporting.
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+/// Counters structure to aggregate counter updates in memory.
+///
+/// Rather than writing a line to the task logs on every update (as the
+/// `update_counter!` macro does), updates are aggregated by group and
+/// label and then reported once using the same `reporter:counter:`
+/// format. Counters are flushed automatically at the end of a stage.
+#[derive(Debug, Default)]
+pub struct Counters {
+    inner: HashMap<(String, String), i64>,
+}
+
+impl Counters {
+    /// Creates a new, empty `Counters`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Increments a counter by the provided amount.
+    pub fn increment<G, L>(&mut self, group: G, label: L, amount: i64)
+    where
+        G: Into<String>,
+        L: Into<String>,
+    {
+        *self.inner.entry((group.into(), label.into())).or_insert(0) += amount;
+    }
+
+    /// Retrieves the current value of a counter, defaulting to `0`.
+    pub fn value(&self, group: &str, label: &str) -> i64 {
+        self.inner
+            .get(&(group.to_owned(), label.to_owned()))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Flushes all counters to the Hadoop task logs, resetting them.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.flush_to(&mut io::stderr())
+    }
+
+    /// Flushes all counters to the provided writer, resetting them.
+    ///
+    /// Counters are written in order of group and label, so that the output
+    /// is deterministic across runs (which makes task logs easier to diff).
+    pub fn flush_to<W>(&mut self, writer: &mut W) -> io::Result<()>
+    where
+        W: Write,
+    {
+        let mut counters = self.inner.drain().collect::<Vec<_>>();
+
+        counters.sort();
+
+        for ((group, label), amount) in counters {
+            writeln!(writer, "reporter:counter:{},{},{}", group, label, amount)?;
+        }
+        Ok(())
+    }
+}
+
+/// Batch of counter updates, aggregated in memory until flushed.
+///
+/// This is intended for counters updated in hot loops; counters are keyed
+/// by group and label, so repeated increments of an existing counter do not
+/// allocate. A batch can be held directly and flushed manually, or stored in
+/// a `Context`, in which case it is flushed automatically at the end of a stage.
+#[derive(Debug, Default)]
+pub struct CounterBatch {
+    inner: HashMap<String, HashMap<String, i64>>,
+}
+
+impl CounterBatch {
+    /// Creates a new, empty `CounterBatch`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Increments a counter by the provided amount.
+    pub fn incr(&mut self, group: &str, label: &str, amount: i64) {
+        let labels = match self.inner.get_mut(group) {
+            Some(labels) => labels,
+            None => self.inner.entry(group.to_owned()).or_default(),
+        };
+
+        match labels.get_mut(label) {
+            Some(value) => *value += amount,
+            None => {
+                labels.insert(label.to_owned(), amount);
+            }
+        }
+    }
+
+    /// Retrieves the current value of a counter, defaulting to `0`.
+    pub fn value(&self, group: &str, label: &str) -> i64 {
+        self.inner
+            .get(group)
+            .and_then(|labels| labels.get(label))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Determines whether the batch contains any counters.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    /// Flushes all counters to the Hadoop task logs, resetting them.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.flush_to(&mut io::stderr())
+    }
+
+    /// Flushes all counters to the provided writer, resetting them.
+    ///
+    /// Counters are written in order of group and label, with each counter
+    /// emitted as a single `reporter:counter:` line.
+    pub fn flush_to<W>(&mut self, writer: &mut W) -> io::Result<()>
+    where
+        W: Write,
+    {
+        let mut counters = self
+            .inner
+            .drain()
+            .flat_map(|(group, labels)| {
+                labels
+                    .into_iter()
+                    .map(move |(label, amount)| (group.clone(), label, amount))
+            })
+            .collect::<Vec<_>>();
+
+        counters.sort();
+
+        for (group, label, amount) in counters {
+            writeln!(writer, "reporter:counter:{},{},{}", group, label, amount)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counter_aggregation() {
+        let mut counters = Counters::new();
+
+        counters.increment("group", "label", 1);
+        counters.increment("group", "label", 2);
+        counters.increment("group", "other", 5);
+
+        assert_eq!(counters.value("group", "label"), 3);
+        assert_eq!(counters.value("group", "other"), 5);
+        assert_eq!(counters.value("group", "missing"), 0);
+    }
+
+    #[test]
+    fn test_counter_flushing() {
+        let mut counters = Counters::new();
+        let mut output = Vec::new();
+
+        counters.increment("group", "label", 1);
+        counters.increment("group", "label", 2);
+        counters.flush_to(&mut output).unwrap();
+
+        assert_eq!(output, b"reporter:counter:group,label,3\n");
+        assert_eq!(counters.value("group", "label"), 0);
+    }
+
+    #[test]
+    fn test_counter_flush_ordering() {
+        let mut counters = Counters::new();
+        let mut output = Vec::new();
+
+        counters.increment("group", "second", 2);
+        counters.increment("other", "label", 3);
+        counters.increment("group", "first", 1);
+        counters.flush_to(&mut output).unwrap();
+
+        assert_eq!(
+            output,
+            &b"reporter:counter:group,first,1\nreporter:counter:group,second,2\nreporter:counter:other,label,3\n"[..]
+        );
+    }
+
+    #[test]
+    fn test_counter_batching() {
+        let mut batch = CounterBatch::new();
+        let mut output = Vec::new();
+
+        assert!(batch.is_empty());
+
+        batch.incr("group", "label", 1);
+        batch.incr("group", "label", 2);
+        batch.incr("group", "other", 5);
+        batch.incr("another", "label", 1);
+
+        assert_eq!(batch.value("group", "label"), 3);
+        assert_eq!(batch.value("group", "missing"), 0);
+        assert_eq!(batch.value("missing", "label"), 0);
+
+        batch.flush_to(&mut output).unwrap();
+
+        assert_eq!(
+            output,
+            &b"reporter:counter:another,label,1\nreporter:counter:group,label,3\nreporter:counter:group,other,5\n"[..]
+        );
+        assert!(batch.is_empty());
+    }
+}
@@ -2,20 +2,23 @@
 use super::conf::Configuration;
 
 /// Delimiters struct to store the input/output separators
-/// for all stages of a MapReduce lifecycle. Once created,
-/// this structure should be considered immutable.
+/// for all stages of a MapReduce lifecycle. These are usually
+/// derived from a job `Configuration`, but can be overridden
+/// programmatically (e.g. when constructing a test `Context`).
 #[derive(Debug)]
 pub struct Delimiters {
     input: Vec<u8>,
     output: Vec<u8>,
+    record: Vec<u8>,
+    key_fields: usize,
 }
 
 impl Delimiters {
     /// Creates a new `Delimiters` from a job `Configuration`.
     pub fn new(conf: &Configuration) -> Self {
         // check to see if this is map/reduce stage
-        let stage = match conf.get("mapreduce.task.ismap") {
-            Some(val) if val == "true" => "map",
+        let stage = match conf.get_bool("mapreduce.task.ismap") {
+            Some(true) => "map",
             _ => "reduce",
         };
 
@@ -23,10 +26,48 @@ impl Delimiters {
         let input_key = format!("stream.{}.input.field.separator", stage);
         let output_key = format!("stream.{}.output.field.separator", stage);
 
+        // custom record separators only apply to the map input
+        let record = match stage {
+            "map" => conf.get("textinputformat.record.delimiter"),
+            _ => None,
+        };
+
         Self {
             // separators are optional, so default to a tab
-            input: conf.get(&input_key).unwrap_or("\t").as_bytes().to_vec(),
-            output: conf.get(&output_key).unwrap_or("\t").as_bytes().to_vec(),
+            input: conf.get_or(&input_key, "\t").as_bytes().to_vec(),
+            output: conf.get_or(&output_key, "\t").as_bytes().to_vec(),
+            // records are optional, so default to a newline (and never empty)
+            record: match record {
+                Some(record) if !record.is_empty() => record.as_bytes().to_vec(),
+                _ => b"\n".to_vec(),
+            },
+            key_fields: key_fields(conf),
+        }
+    }
+
+    /// Creates a new `Delimiters` for a combiner from a job `Configuration`.
+    ///
+    /// Combiners both read and write map output, so the map output separator
+    /// is used in both directions, and records are always newline separated.
+    pub(crate) fn combine(conf: &Configuration) -> Self {
+        let mut delim = Self::new(conf);
+        let output = conf.get_or("stream.map.output.field.separator", "\t");
+
+        delim.set_input(output.as_bytes());
+        delim.set_output(output.as_bytes());
+        delim.record = b"\n".to_vec();
+        delim
+    }
+
+    /// Creates a new `Delimiters` using the provided separators.
+    ///
+    /// All other delimiters are set to their Hadoop defaults.
+    pub fn with_separators(input: &[u8], output: &[u8]) -> Self {
+        Self {
+            input: input.to_vec(),
+            output: output.to_vec(),
+            record: b"\n".to_vec(),
+            key_fields: 1,
         }
     }
 
@@ -41,6 +82,94 @@ impl Delimiters {
     pub fn output(&self) -> &[u8] {
         &self.output
     }
+
+    /// Returns a reference to the record delimiter.
+    #[inline]
+    pub fn record(&self) -> &[u8] {
+        &self.record
+    }
+
+    /// Sets the input delimiter.
+    #[inline]
+    pub fn set_input(&mut self, sep: &[u8]) {
+        self.input = sep.to_vec();
+    }
+
+    /// Sets the output delimiter.
+    #[inline]
+    pub fn set_output(&mut self, sep: &[u8]) {
+        self.output = sep.to_vec();
+    }
+
+    /// Returns the number of leading fields which make up a key.
+    #[inline]
+    pub fn key_fields(&self) -> usize {
+        self.key_fields
+    }
+
+    /// Splits a record into a key/value pair, using the input separator.
+    ///
+    /// The key is made up of the first `key_fields` fields of the record. If
+    /// the record contains too few separators, the entire record is the key
+    /// and `None` is returned as the value, whereas a trailing separator will
+    /// result in an empty value.
+    pub fn split<'a>(&self, record: &'a [u8]) -> (&'a [u8], Option<&'a [u8]>) {
+        split_fields_checked(record, &self.input, self.key_fields)
+    }
+}
+
+/// Retrieves the number of leading fields which make up a key.
+///
+/// This is read from `stream.num.map.output.key.fields`, and is always at
+/// least `1` (which is the default when the key is missing or invalid).
+pub(crate) fn key_fields(conf: &Configuration) -> usize {
+    conf.get_as::<usize>("stream.num.map.output.key.fields")
+        .unwrap_or(1)
+        .max(1)
+}
+
+/// Splits an input into a key/value pair after the Nth delimiter.
+///
+/// If the input contains fewer than `fields` delimiters, the entire
+/// input is treated as the key and the value is empty (as in Hadoop).
+/// This means that `key` and `key\t` both result in an empty value;
+/// use `split_fields_checked` to tell these two cases apart.
+pub(crate) fn split_fields<'a>(
+    input: &'a [u8],
+    delim: &[u8],
+    fields: usize,
+) -> (&'a [u8], &'a [u8]) {
+    let (key, value) = split_fields_checked(input, delim, fields);
+    (key, value.unwrap_or(b""))
+}
+
+/// Splits an input into a key/value pair after the Nth delimiter.
+///
+/// If the input contains fewer than `fields` delimiters, the entire
+/// input is treated as the key and no value is returned.
+pub(crate) fn split_fields_checked<'a>(
+    input: &'a [u8],
+    delim: &[u8],
+    fields: usize,
+) -> (&'a [u8], Option<&'a [u8]>) {
+    let mut offset = 0;
+
+    for field in 1..=fields {
+        // search (quickly) for the next delimiter
+        let n = match twoway::find_bytes(&input[offset..], delim) {
+            Some(n) => offset + n,
+            None => return (input, None),
+        };
+
+        // split the input once we hit the Nth delimiter
+        if field == fields {
+            return (&input[..n], Some(&input[n + delim.len()..]));
+        }
+
+        offset = n + delim.len();
+    }
+
+    (input, None)
 }
 
 #[cfg(test)]
@@ -53,6 +182,7 @@ mod tests {
             ("mapreduce.task.ismap", "true"),
             ("stream.map.input.field.separator", ":"),
             ("stream.map.output.field.separator", "|"),
+            ("textinputformat.record.delimiter", "||"),
         ];
 
         let conf = Configuration::with_env(env.into_iter());
@@ -60,6 +190,7 @@ mod tests {
 
         assert_eq!(delim.input(), b":");
         assert_eq!(delim.output(), b"|");
+        assert_eq!(delim.record(), b"||");
     }
 
     #[test]
@@ -68,6 +199,7 @@ mod tests {
             ("mapreduce.task.ismap", "false"),
             ("stream.reduce.input.field.separator", ":"),
             ("stream.reduce.output.field.separator", "|"),
+            ("textinputformat.record.delimiter", "||"),
         ];
 
         let conf = Configuration::with_env(env.into_iter());
@@ -75,6 +207,26 @@ mod tests {
 
         assert_eq!(delim.input(), b":");
         assert_eq!(delim.output(), b"|");
+        assert_eq!(delim.record(), b"\n");
+    }
+
+    #[test]
+    fn test_combine_delimiters_creation() {
+        let env = vec![
+            ("mapreduce.task.ismap", "true"),
+            ("stream.map.input.field.separator", ":"),
+            ("stream.map.output.field.separator", "|"),
+            ("stream.num.map.output.key.fields", "2"),
+            ("textinputformat.record.delimiter", "||"),
+        ];
+
+        let conf = Configuration::with_env(env.into_iter());
+        let delim = Delimiters::combine(&conf);
+
+        assert_eq!(delim.input(), b"|");
+        assert_eq!(delim.output(), b"|");
+        assert_eq!(delim.record(), b"\n");
+        assert_eq!(delim.key_fields(), 2);
     }
 
     #[test]
@@ -86,5 +238,63 @@ mod tests {
 
         assert_eq!(delim.input(), b"\t");
         assert_eq!(delim.output(), b"\t");
+        assert_eq!(delim.record(), b"\n");
+        assert_eq!(delim.key_fields(), 1);
+    }
+
+    #[test]
+    fn test_delimiter_overrides() {
+        let mut delim = Delimiters::with_separators(b":", b"|");
+
+        assert_eq!(delim.input(), b":");
+        assert_eq!(delim.output(), b"|");
+        assert_eq!(delim.record(), b"\n");
+        assert_eq!(delim.key_fields(), 1);
+
+        delim.set_input(b",");
+        delim.set_output(b"||");
+
+        assert_eq!(delim.input(), b",");
+        assert_eq!(delim.output(), b"||");
+    }
+
+    #[test]
+    fn test_key_fields_creation() {
+        let env = vec![("stream.num.map.output.key.fields", "2")];
+
+        let conf = Configuration::with_env(env.into_iter());
+        let delim = Delimiters::new(&conf);
+
+        assert_eq!(delim.key_fields(), 2);
+    }
+
+    #[test]
+    fn test_splitting_fields() {
+        assert_eq!(
+            split_fields(b"a\tb\tc", b"\t", 1),
+            (&b"a"[..], &b"b\tc"[..])
+        );
+        assert_eq!(
+            split_fields(b"a\tb\tc", b"\t", 2),
+            (&b"a\tb"[..], &b"c"[..])
+        );
+        assert_eq!(
+            split_fields(b"a\tb\tc", b"\t", 3),
+            (&b"a\tb\tc"[..], &b""[..])
+        );
+        assert_eq!(
+            split_fields(b"a||b||c", b"||", 2),
+            (&b"a||b"[..], &b"c"[..])
+        );
+        assert_eq!(split_fields(b"a\t", b"\t", 1), (&b"a"[..], &b""[..]));
+    }
+
+    #[test]
+    fn test_splitting_missing_fields() {
+        let delim = Delimiters::with_separators(b"\t", b"\t");
+
+        assert_eq!(delim.split(b"a\tb"), (&b"a"[..], Some(&b"b"[..])));
+        assert_eq!(delim.split(b"a\t"), (&b"a"[..], Some(&b""[..])));
+        assert_eq!(delim.split(b"a"), (&b"a"[..], None));
     }
 }
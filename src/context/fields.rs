@@ -0,0 +1,288 @@
+//! Field bindings to split records into delimited fields.
+use std::borrow::Cow;
+
+use super::conf::Configuration;
+
+/// Fields structure to control the splitting of records into fields.
+///
+/// The separator is read from `efflux.field.separator` in the job
+/// `Configuration`, defaulting to a tab (which is also used in place of
+/// an empty separator, as it would never advance). Quoting is disabled unless
+/// `efflux.field.quoted` is set to `true`, in which case fields are
+/// parsed using the quoting rules of RFC 4180 (as used by CSV).
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Fields {
+    separator: Vec<u8>,
+    quoted: bool,
+}
+
+impl Fields {
+    /// Creates a new `Fields` from a job `Configuration`.
+    pub fn new(conf: &Configuration) -> Self {
+        Self {
+            separator: conf
+                .get("efflux.field.separator")
+                .filter(|separator| !separator.is_empty())
+                .unwrap_or("\t")
+                .as_bytes()
+                .to_vec(),
+            quoted: conf.get_bool("efflux.field.quoted").unwrap_or(false),
+        }
+    }
+
+    /// Creates a new `Fields` to split quoted, comma separated records.
+    pub fn csv() -> Self {
+        Self {
+            separator: b",".to_vec(),
+            quoted: true,
+        }
+    }
+
+    /// Creates a new `Fields` to split unquoted, tab separated records.
+    pub fn tsv() -> Self {
+        Self {
+            separator: b"\t".to_vec(),
+            quoted: false,
+        }
+    }
+
+    /// Returns a reference to the field separator.
+    #[inline]
+    pub fn separator(&self) -> &[u8] {
+        &self.separator
+    }
+
+    /// Sets the field separator.
+    ///
+    /// An empty separator is replaced with the default tab separator.
+    #[inline]
+    pub fn set_separator(&mut self, separator: &[u8]) {
+        self.separator = match separator {
+            b"" => b"\t".to_vec(),
+            separator => separator.to_vec(),
+        };
+    }
+
+    /// Returns whether quoted fields are respected.
+    #[inline]
+    pub fn quoted(&self) -> bool {
+        self.quoted
+    }
+
+    /// Sets whether quoted fields are respected.
+    #[inline]
+    pub fn set_quoted(&mut self, quoted: bool) {
+        self.quoted = quoted;
+    }
+
+    /// Splits a record into fields.
+    ///
+    /// When quoting is enabled, fields wrapped in double quotes may contain
+    /// the separator, and a pair of double quotes is read as a single quote.
+    /// Fields are only copied when they contain such an escaped quote.
+    pub fn split<'a>(&self, record: &'a [u8]) -> Vec<Cow<'a, [u8]>> {
+        let mut fields = Vec::new();
+        let mut remaining = record;
+
+        loop {
+            let (field, rest) = self.split_first(remaining);
+
+            fields.push(field);
+
+            match rest {
+                Some(rest) => remaining = rest,
+                None => return fields,
+            }
+        }
+    }
+
+    /// Retrieves a single field of a record by index.
+    ///
+    /// This follows the same rules as `split`, but only the fields before
+    /// the requested index are parsed, and no other fields are collected.
+    pub fn field<'a>(&self, record: &'a [u8], index: usize) -> Option<Cow<'a, [u8]>> {
+        let mut remaining = record;
+
+        for _ in 0..index {
+            remaining = self.split_first(remaining).1?;
+        }
+
+        Some(self.split_first(remaining).0)
+    }
+
+    /// Splits the first field from a record, returning any remaining bytes.
+    fn split_first<'a>(&self, record: &'a [u8]) -> (Cow<'a, [u8]>, Option<&'a [u8]>) {
+        // quoted fields must be parsed to find the trailing separator
+        if self.quoted && record.first() == Some(&b'"') {
+            return self.split_quoted(record);
+        }
+
+        match twoway::find_bytes(record, &self.separator) {
+            Some(idx) => (
+                Cow::Borrowed(&record[..idx]),
+                Some(&record[idx + self.separator.len()..]),
+            ),
+            None => (Cow::Borrowed(record), None),
+        }
+    }
+
+    /// Splits a quoted field from the start of a record.
+    ///
+    /// Any bytes between the closing quote and the next separator are kept
+    /// as part of the field, rather than rejecting the record entirely.
+    fn split_quoted<'a>(&self, record: &'a [u8]) -> (Cow<'a, [u8]>, Option<&'a [u8]>) {
+        let mut owned: Option<Vec<u8>> = None;
+        let mut idx = 1;
+
+        while idx < record.len() {
+            if record[idx] != b'"' {
+                if let Some(ref mut owned) = owned {
+                    owned.push(record[idx]);
+                }
+                idx += 1;
+                continue;
+            }
+
+            // a pair of quotes is an escaped quote
+            if record.get(idx + 1) == Some(&b'"') {
+                owned
+                    .get_or_insert_with(|| record[1..idx].to_vec())
+                    .push(b'"');
+                idx += 2;
+                continue;
+            }
+
+            // closing quote, so find the end of the field
+            let field = match owned {
+                Some(owned) => Cow::Owned(owned),
+                None => Cow::Borrowed(&record[1..idx]),
+            };
+
+            let trailing = &record[idx + 1..];
+
+            return match twoway::find_bytes(trailing, &self.separator) {
+                Some(0) => (field, Some(&trailing[self.separator.len()..])),
+                Some(end) => {
+                    let mut field = field.into_owned();
+                    field.extend_from_slice(&trailing[..end]);
+                    (
+                        Cow::Owned(field),
+                        Some(&trailing[end + self.separator.len()..]),
+                    )
+                }
+                None if trailing.is_empty() => (field, None),
+                None => {
+                    let mut field = field.into_owned();
+                    field.extend_from_slice(trailing);
+                    (Cow::Owned(field), None)
+                }
+            };
+        }
+
+        // unterminated quotes run to the end of the record
+        match owned {
+            Some(owned) => (Cow::Owned(owned), None),
+            None => (Cow::Borrowed(&record[1..]), None),
+        }
+    }
+}
+
+/// Default implementation for `Fields`.
+impl Default for Fields {
+    /// Splits unquoted, tab separated records by default.
+    fn default() -> Self {
+        Self::tsv()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unquoted_fields() {
+        let fields = Fields::tsv();
+
+        assert_eq!(
+            fields.split(b"one\t\"two\tthree\"\t"),
+            vec![&b"one"[..], &b"\"two"[..], &b"three\""[..], &b""[..]]
+        );
+    }
+
+    #[test]
+    fn test_quoted_fields() {
+        let fields = Fields::csv();
+        let split = fields.split(b"one,\"two, three\",\"say \"\"hi\"\"\",,\"\"");
+
+        assert_eq!(
+            split,
+            vec![
+                &b"one"[..],
+                &b"two, three"[..],
+                &b"say \"hi\""[..],
+                &b""[..],
+                &b""[..],
+            ]
+        );
+
+        assert!(matches!(split[1], Cow::Borrowed(_)));
+        assert!(matches!(split[2], Cow::Owned(_)));
+    }
+
+    #[test]
+    fn test_field_lookup() {
+        let fields = Fields::csv();
+        let record = b"one,\"two, three\",\"say \"\"hi\"\"\"";
+
+        assert_eq!(fields.field(record, 0).unwrap(), &b"one"[..]);
+        assert_eq!(fields.field(record, 1).unwrap(), &b"two, three"[..]);
+        assert_eq!(fields.field(record, 2).unwrap(), &b"say \"hi\""[..]);
+        assert_eq!(fields.field(record, 3), None);
+    }
+
+    #[test]
+    fn test_malformed_quoted_fields() {
+        let fields = Fields::csv();
+
+        assert_eq!(
+            fields.split(b"\"one\"two,\"three"),
+            vec![&b"onetwo"[..], &b"three"[..]]
+        );
+    }
+
+    #[test]
+    fn test_fields_configuration() {
+        let env = vec![
+            ("efflux.field.separator", "|"),
+            ("efflux.field.quoted", "true"),
+        ];
+
+        let conf = Configuration::with_env(env.into_iter());
+        let fields = Fields::new(&conf);
+
+        assert_eq!(fields.separator(), b"|");
+        assert!(fields.quoted());
+        assert_eq!(fields.split(b"\"a|b\"|c"), vec![&b"a|b"[..], &b"c"[..]]);
+
+        let conf = Configuration::with_env(Vec::<(String, String)>::new().into_iter());
+
+        assert_eq!(Fields::new(&conf), Fields::default());
+    }
+
+    #[test]
+    fn test_empty_separator() {
+        let env = vec![("efflux.field.separator", "")];
+        let conf = Configuration::with_env(env.into_iter());
+
+        let mut fields = Fields::new(&conf);
+
+        assert_eq!(fields.separator(), b"\t");
+        assert_eq!(fields.split(b"a\tb"), vec![&b"a"[..], &b"b"[..]]);
+
+        fields.set_separator(b"|");
+        fields.set_separator(b"");
+
+        assert_eq!(fields.separator(), b"\t");
+        assert_eq!(fields.split(b"a|b"), vec![&b"a|b"[..]]);
+    }
+}
@@ -0,0 +1,46 @@
+//! Line bindings to provide line numbers for `Mapper` stages.
+
+/// Line structure to allow tracking of the current line number.
+///
+/// This is maintained by the `Mapper` lifecycle, and is incremented
+/// once for every record passed to the `Mapper`. Line numbers start
+/// at `1`, so a value of `0` means no lines have been processed yet.
+#[derive(Debug, Default)]
+pub struct LineNumber(u64);
+
+impl LineNumber {
+    /// Creates a new `LineNumber` from line `0`.
+    pub fn new() -> LineNumber {
+        LineNumber(0)
+    }
+
+    /// Returns the current line number.
+    #[inline]
+    pub fn current(&self) -> u64 {
+        self.0
+    }
+
+    /// Increments the line number, moving to the next line.
+    ///
+    /// The newly incremented line number is then returned, for convenience.
+    #[inline]
+    pub fn increment(&mut self) -> u64 {
+        self.0 += 1;
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_increment() {
+        let mut line = LineNumber::new();
+
+        assert_eq!(line.current(), 0);
+        assert_eq!(line.increment(), 1);
+        assert_eq!(line.increment(), 2);
+        assert_eq!(line.current(), 2);
+    }
+}
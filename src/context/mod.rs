@@ -46,63 +46,153 @@
 //! current set of `Contextual` types added are as follows:
 //!
 //! - `Configuration`
+//! - `CounterBatch`
+//! - `Counters`
 //! - `Delimiters`
+//! - `ErrorPolicy`
+//! - `LineNumber`
 //! - `Offset`
+//! - `Records`
+//! - `ValueOrder`
 //!
 //! The most interesting of these types is the `Configuration` type, as it
 //! represents the job configuration provided by Hadoop.
 use std::any::{Any, TypeId};
+#[cfg(feature = "csv")]
+use std::borrow::Cow;
 use std::collections::HashMap;
-use std::fmt::Display;
+use std::fmt::{self, Debug, Display};
 use std::io::{self, Write};
+use std::mem;
+use std::sync::OnceLock;
+
+use crate::codec::CompressWriter;
+use crate::error::EffluxError;
+use crate::local::{split_pairs, Buffer};
+use crate::mapper::DefaultOutput;
+#[cfg(feature = "serde")]
+use crate::typed::reject;
+#[cfg(feature = "serde")]
+use serde::{de::DeserializeOwned, Serialize};
 
 mod conf;
+mod counters;
 mod delim;
+#[cfg(feature = "csv")]
+mod fields;
+mod line;
 mod offset;
+mod order;
+mod policy;
+mod records;
 
 pub use self::conf::Configuration;
+pub use self::counters::{CounterBatch, Counters};
 pub use self::delim::Delimiters;
+pub(crate) use self::delim::{split_fields, split_fields_checked};
+#[cfg(feature = "csv")]
+pub use self::fields::Fields;
+pub use self::line::LineNumber;
 pub use self::offset::Offset;
+pub use self::order::ValueOrder;
+pub use self::policy::ErrorPolicy;
+pub use self::records::Records;
 
 /// Marker trait to represent types which can be added to a `Context`.
+///
+/// With the `derive` feature enabled, this can be implemented for a type
+/// via `#[derive(Contextual)]` rather than writing out the empty impl.
 pub trait Contextual: Any {}
 
+#[cfg(feature = "derive")]
+pub use efflux_derive::Contextual;
+
 // all internal contextual types
 impl Contextual for Configuration {}
+impl Contextual for CounterBatch {}
+impl Contextual for Counters {}
 impl Contextual for Delimiters {}
+impl Contextual for ErrorPolicy {}
+#[cfg(feature = "csv")]
+impl Contextual for Fields {}
+impl Contextual for LineNumber {}
 impl Contextual for Offset {}
+impl Contextual for Records {}
+impl Contextual for ValueOrder {}
 
 /// Context structure to represent a Hadoop job context.
 ///
 /// This acts as an arbitrarily-typed bag, allowing for easy storage
 /// of random types between iterations of the stage. See the module
 /// documentation for further details and examples.
-#[derive(Debug, Default)]
 pub struct Context {
     data: HashMap<TypeId, Box<dyn Any>>,
+    output: CompressWriter<'static>,
+    outputs: HashMap<String, Box<dyn Write>>,
+    error: Option<io::Error>,
+    skipped: Vec<EffluxError>,
+    captured: Option<Buffer>,
 }
 
 impl Context {
     /// Creates a new `Context`.
     pub fn new() -> Self {
+        Self::with_output(Box::new(io::stdout()))
+    }
+
+    /// Creates a new `Context` writing to an in-memory buffer.
+    ///
+    /// The buffered output can be retrieved as key/value pairs by calling
+    /// `take_output`, which is useful when testing a single `map` or `reduce`.
+    pub fn buffered() -> Self {
+        let buffer = Buffer::default();
+        let mut ctx = Self::with_output(Box::new(buffer.clone()));
+        ctx.captured = Some(buffer);
+        ctx
+    }
+
+    /// Creates a new `Context` writing to a custom output sink.
+    ///
+    /// This is useful for capturing the output of a stage in process,
+    /// rather than having it emitted directly to `io::stdout`.
+    pub fn with_output(output: Box<dyn Write>) -> Self {
         // new base container
         let mut ctx = Self {
+            output: CompressWriter::from(output),
+            error: None,
+            skipped: Vec::new(),
             data: HashMap::new(),
+            outputs: HashMap::new(),
+            captured: None,
         };
 
         // construct default types
         let conf = Configuration::new();
         let delim = Delimiters::new(&conf);
+        let order = ValueOrder::new(&conf);
+        let policy = ErrorPolicy::new(&conf);
 
-        // add both
+        // add all
         ctx.insert(conf);
         ctx.insert(delim);
+        ctx.insert(order);
+        ctx.insert(policy);
+        ctx.insert(Records::new());
 
         ctx
     }
 
+    /// Checks whether a `Contextual` type exists in the context.
+    pub fn contains<T>(&self) -> bool
+    where
+        T: Contextual,
+    {
+        let types = TypeId::of::<T>();
+        self.data.contains_key(&types)
+    }
+
     /// Retrieves a potential reference to a `Contextual` type.
-    pub fn get<T: Contextual>(&self) -> Option<&T>
+    pub fn get<T>(&self) -> Option<&T>
     where
         T: Contextual,
     {
@@ -110,6 +200,33 @@ impl Context {
         self.data.get(&types).and_then(|b| b.downcast_ref::<T>())
     }
 
+    /// Retrieves a reference to the job `Configuration`.
+    ///
+    /// A `Configuration` is inserted when a `Context` is created, so this is
+    /// a shorthand for `get::<Configuration>()` which avoids the `Option`.
+    ///
+    /// # Panics
+    ///
+    /// This will panic if the `Configuration` has been removed from the
+    /// context (e.g. via `take` or `remove`).
+    #[inline]
+    pub fn configuration(&self) -> &Configuration {
+        self.get::<Configuration>()
+            .expect("Configuration has been removed from the Context")
+    }
+
+    /// Retrieves a reference to the stage `Delimiters`.
+    ///
+    /// `Delimiters` are inserted when a `Context` is created, so this is a
+    /// shorthand for `get::<Delimiters>()` which avoids the `Option`. If the
+    /// `Delimiters` have been removed from the context (e.g. via `take`), the
+    /// Hadoop defaults are used instead (i.e. tab separated, newline records).
+    #[inline]
+    pub fn delimiters(&self) -> &Delimiters {
+        self.get::<Delimiters>()
+            .unwrap_or_else(|| default_delimiters())
+    }
+
     /// Retrieves a potential mutable reference to a `Contextual` type.
     pub fn get_mut<T>(&mut self) -> Option<&mut T>
     where
@@ -121,6 +238,23 @@ impl Context {
             .and_then(|b| b.downcast_mut::<T>())
     }
 
+    /// Retrieves a mutable reference to a `Contextual` type, inserting if missing.
+    ///
+    /// The provided function is only called when the type does not already
+    /// exist in the context, allowing for lazy creation of default state.
+    pub fn get_or_insert_with<T, F>(&mut self, f: F) -> &mut T
+    where
+        T: Contextual,
+        F: FnOnce() -> T,
+    {
+        let types = TypeId::of::<T>();
+        self.data
+            .entry(types)
+            .or_insert_with(|| Box::new(f()))
+            .downcast_mut::<T>()
+            .unwrap()
+    }
+
     /// Inserts a `Contextual` type into the context.
     pub fn insert<T>(&mut self, t: T)
     where
@@ -142,40 +276,481 @@ impl Context {
             .map(|t| *t)
     }
 
+    /// Removes a `Contextual` type from the context.
+    ///
+    /// Returns `true` if a value of the type was present and removed.
+    pub fn remove<T>(&mut self) -> bool
+    where
+        T: Contextual,
+    {
+        let types = TypeId::of::<T>();
+        self.data.remove(&types).is_some()
+    }
+
+    /// Removes all user inserted `Contextual` types from the context.
+    ///
+    /// The internal types listed in the module documentation are always
+    /// preserved, as they're required for the current stage to function.
+    pub fn clear_user_state(&mut self) {
+        let internal = internal_types();
+        self.data.retain(|types, _| internal.contains(types));
+    }
+
+    /// Increments a job counter by the provided amount.
+    ///
+    /// Unlike `update_counter!`, updates are aggregated in memory and are
+    /// only reported to Hadoop once the current stage has finished.
+    pub fn incr_counter<G, L>(&mut self, group: G, label: L, amount: i64)
+    where
+        G: Into<String>,
+        L: Into<String>,
+    {
+        self.get_or_insert_with(Counters::new)
+            .increment(group, label, amount);
+    }
+
+    /// Retrieves the current value of a job counter, defaulting to `0`.
+    ///
+    /// This only includes updates made via `incr_counter` which have not
+    /// yet been reported to Hadoop at the end of the current stage.
+    pub fn counter_value(&self, group: &str, label: &str) -> i64 {
+        self.get::<Counters>()
+            .map(|counters| counters.value(group, label))
+            .unwrap_or(0)
+    }
+
+    /// Returns the line number of the current record in a `Mapper` stage.
+    ///
+    /// Line numbers start at `1`; `0` is returned outside of a `Mapper`, or
+    /// before the first record has been passed to the `Mapper`.
+    pub fn line_number(&self) -> u64 {
+        self.get::<LineNumber>().map_or(0, LineNumber::current)
+    }
+
+    /// Returns the byte offset of the current record in a `Mapper` stage.
+    ///
+    /// This is the same offset passed as the key to `Mapper::map`, but it's
+    /// never truncated (as the key may be on 32-bit targets). `0` is returned
+    /// outside of a `Mapper`, or before the first record has been read.
+    pub fn offset(&self) -> u64 {
+        self.get::<Offset>().map_or(0, Offset::current)
+    }
+
+    /// Returns the number of records processed by the current stage.
+    pub fn record_count(&self) -> u64 {
+        self.get::<Records>().map(Records::count).unwrap_or(0)
+    }
+
+    /// Returns the name of the file being processed by the current stage.
+    ///
+    /// This reads `mapreduce.map.input.file`, falling back to the older
+    /// `map.input.file` key, and is only set by Hadoop for the map stage.
+    pub fn input_file(&self) -> Option<&str> {
+        let conf = self.get::<Configuration>()?;
+
+        conf.get("mapreduce.map.input.file")
+            .or_else(|| conf.get("map.input.file"))
+    }
+
+    /// Returns the partition number of the current task.
+    ///
+    /// This reads `mapreduce.task.partition`, falling back to the older
+    /// `mapred.task.partition` key. For a `Reducer`, this is the partition
+    /// of the keys being received (e.g. `2` for a `part-00002` output file).
+    /// Returns `None` when unset, or when the value is not a valid number.
+    pub fn partition(&self) -> Option<usize> {
+        let conf = self.get::<Configuration>()?;
+
+        conf.get("mapreduce.task.partition")
+            .or_else(|| conf.get("mapred.task.partition"))
+            .and_then(|partition| partition.trim().parse().ok())
+    }
+
+    /// Parses a JSON value into a type, using the configured `ErrorPolicy`.
+    ///
+    /// Values which fail to parse are skipped (returning `None`) unless the
+    /// policy is `ErrorPolicy::Fail`, in which case an `EffluxError::Parse` is
+    /// returned and also stored to be surfaced by the lifecycle runner, as with
+    /// `write`. This requires the `serde` feature.
+    #[cfg(feature = "serde")]
+    pub fn parse_json<T>(&mut self, value: &[u8]) -> Result<Option<T>, EffluxError>
+    where
+        T: DeserializeOwned,
+    {
+        if let Ok(parsed) = serde_json::from_slice(value) {
+            return Ok(Some(parsed));
+        }
+
+        let policy = self.get::<ErrorPolicy>().copied().unwrap_or_default();
+
+        reject(value, policy, &mut self.skipped).map_err(|err| {
+            let msg = err.to_string();
+            self.set_error(err);
+            EffluxError::Parse(msg)
+        })
+    }
+
+    /// Splits a record into fields, using the `Fields` of this `Context`.
+    ///
+    /// If no `Fields` have been inserted, they're created from the job
+    /// `Configuration` on first use. This requires the `csv` feature.
+    #[cfg(feature = "csv")]
+    pub fn parse_fields<'a>(&mut self, record: &'a [u8]) -> Vec<Cow<'a, [u8]>> {
+        if !self.contains::<Fields>() {
+            let fields = self
+                .get::<Configuration>()
+                .map(Fields::new)
+                .unwrap_or_default();
+            self.insert(fields);
+        }
+        self.get::<Fields>().unwrap().split(record)
+    }
+
     /// Writes a key/value pair to the stage output.
+    ///
+    /// Any error which occurs during the write is returned, but is also
+    /// stored internally to be surfaced by the lifecycle runner once control
+    /// returns. As such, callers unable to handle an error may ignore it.
+    ///
+    /// The exception is an `ErrorKind::BrokenPipe`, which is stored but not
+    /// returned; this is expected when a downstream consumer closes the output
+    /// early, and the runner will halt the stage and exit cleanly instead.
+    ///
+    /// Both the key and value may be any byte-like type, such as `&[u8]`,
+    /// `&str`, `String` or `Vec<u8>`, avoiding conversions at call sites.
     #[inline]
-    pub fn write(&mut self, key: &[u8], val: &[u8]) {
-        // grab a reference to the context output delimiters
-        let out = self.get::<Delimiters>().unwrap().output();
+    pub fn write<K, V>(&mut self, key: K, val: V) -> Result<(), EffluxError>
+    where
+        K: AsRef<[u8]>,
+        V: AsRef<[u8]>,
+    {
+        let result = self.try_write(None, key.as_ref(), None, val.as_ref());
+        self.record(result)
+    }
 
-        // lock the stdout buffer
-        let stdout = io::stdout();
-        let mut lock = stdout.lock();
+    /// Writes a key/value pair to the stage output using a custom separator.
+    ///
+    /// This only applies the separator to the current pair, rather than
+    /// changing the separator configured in the `Delimiters` type.
+    #[inline]
+    pub fn write_with<K, V, S>(&mut self, key: K, val: V, sep: S) -> Result<(), EffluxError>
+    where
+        K: AsRef<[u8]>,
+        V: AsRef<[u8]>,
+        S: AsRef<[u8]>,
+    {
+        let result = self.try_write(None, key.as_ref(), Some(sep.as_ref()), val.as_ref());
+        self.record(result)
+    }
 
-        // write the pair and newline
-        lock.write_all(key).unwrap();
-        lock.write_all(out).unwrap();
-        lock.write_all(val).unwrap();
-        lock.write_all(b"\n").unwrap();
+    /// Writes a key to the stage output, without a separator or value.
+    #[inline]
+    pub fn write_key<K>(&mut self, key: K) -> Result<(), EffluxError>
+    where
+        K: AsRef<[u8]>,
+    {
+        let result = self.try_write(None, key.as_ref(), Some(b""), b"");
+        self.record(result)
+    }
+
+    /// Writes a key/value pair to a named output.
+    ///
+    /// Named outputs must first be registered via `register_output`, which
+    /// allows a single stage to route records into several datasets (much
+    /// like the `MultipleOutputs` class in Hadoop). Writing to a name which
+    /// has not been registered will result in an `ErrorKind::NotFound`.
+    #[inline]
+    pub fn write_named<K, V>(&mut self, name: &str, key: K, val: V) -> Result<(), EffluxError>
+    where
+        K: AsRef<[u8]>,
+        V: AsRef<[u8]>,
+    {
+        let result = self.try_write(Some(name), key.as_ref(), None, val.as_ref());
+        self.record(result)
+    }
+
+    /// Registers a named output sink, to be written to via `write_named`.
+    ///
+    /// Registering an output with an existing name will replace the
+    /// existing output sink, which is then returned to the caller.
+    pub fn register_output<N>(&mut self, name: N, output: Box<dyn Write>) -> Option<Box<dyn Write>>
+    where
+        N: Into<String>,
+    {
+        self.outputs.insert(name.into(), output)
     }
 
     /// Writes a key/value formatted pair to the stage output.
     ///
     /// This is a simple sugar API around `write` which allows callers to
     /// provide a type which implements `Display` to serialize automatically.
+    /// Both values are formatted directly into the output, rather than being
+    /// allocated as intermediate `String` values.
     #[inline]
-    pub fn write_fmt<K, V>(&mut self, key: K, val: V)
+    pub fn write_fmt<K, V>(&mut self, key: K, val: V) -> Result<(), EffluxError>
+    where
+        K: Display,
+        V: Display,
+    {
+        let result = self.try_write_fmt(&key, &val);
+        self.record(result)
+    }
+
+    /// Writes a key/value pair to the stage output, serializing the value as JSON.
+    ///
+    /// Values are serialized in compact form, so are guaranteed not to contain
+    /// any newlines. Serialization errors are handled the same way as errors
+    /// during a write. This requires the `serde` feature.
+    #[cfg(feature = "serde")]
+    pub fn write_json<K, V>(&mut self, key: K, val: &V) -> Result<(), EffluxError>
+    where
+        K: Display,
+        V: Serialize + ?Sized,
+    {
+        let json = match serde_json::to_vec(val) {
+            Ok(json) => json,
+            Err(err) => return self.record(Err(err.into())),
+        };
+        self.write(key.to_string(), json)
+    }
+
+    /// Writes many key/value pairs to the stage output.
+    ///
+    /// Each pair is framed identically to a call to `write`, and writing
+    /// will stop on the first error encountered (which is then returned).
+    pub fn write_many<I, K, V>(&mut self, pairs: I) -> Result<(), EffluxError>
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<[u8]>,
+        V: AsRef<[u8]>,
+    {
+        for (key, val) in pairs {
+            self.write(key, val)?;
+        }
+        Ok(())
+    }
+
+    /// Writes many key/value formatted pairs to the stage output.
+    ///
+    /// Each pair is formatted identically to a call to `write_fmt`, but the
+    /// output delimiter is only looked up once for all pairs. Writing will
+    /// stop on the first error encountered (which is then returned).
+    pub fn write_iter<I, K, V>(&mut self, pairs: I) -> Result<(), EffluxError>
     where
+        I: IntoIterator<Item = (K, V)>,
         K: Display,
         V: Display,
     {
-        self.write(key.to_string().as_bytes(), val.to_string().as_bytes());
+        let out = output_delimiter(&self.data);
+        let mut result = Ok(());
+
+        for (key, val) in pairs {
+            result = write_pair(&mut self.output, out, &key, &val);
+            if result.is_err() {
+                break;
+            }
+        }
+
+        self.record(result)
+    }
+
+    /// Flushes any buffered output to the underlying sink.
+    ///
+    /// This is called automatically at the end of a lifecycle, but can be
+    /// called manually if output must be flushed at a particular point.
+    pub fn flush(&mut self) -> io::Result<()> {
+        for output in self.outputs.values_mut() {
+            output.flush()?;
+        }
+        self.output.flush()
+    }
+
+    /// Flushes all output, finishing any compressed output stream.
+    ///
+    /// This is called at the end of a lifecycle in place of `flush`, as
+    /// compressed output is only complete once finished (see the `codec`
+    /// module). Compressed output cannot be written to once finished.
+    pub(crate) fn finish(&mut self) -> io::Result<()> {
+        for output in self.outputs.values_mut() {
+            output.flush()?;
+        }
+        self.output.finish()
+    }
+
+    /// Takes all pairs written to the output of a buffered `Context`.
+    ///
+    /// Pairs are split using the configured output delimiter, with any output
+    /// without a delimiter treated as a key with an empty value. If this is not
+    /// a `Context` created via `buffered`, no pairs will ever be returned.
+    pub fn take_output(&mut self) -> Vec<(Vec<u8>, Vec<u8>)> {
+        let output = match &self.captured {
+            Some(buffer) => buffer.take(),
+            None => return Vec::new(),
+        };
+
+        let delim = self
+            .get::<Delimiters>()
+            .map_or(&b"\t"[..], Delimiters::output);
+        split_pairs(&output, delim, 1)
+    }
+
+    /// Replaces the output sink used by this `Context`.
+    pub(crate) fn set_output(&mut self, output: Box<dyn Write>) {
+        self.set_compressed_output(CompressWriter::from(output));
+    }
+
+    /// Replaces the output sink used by this `Context` with a compressed sink.
+    pub(crate) fn set_compressed_output(&mut self, output: CompressWriter<'static>) {
+        self.output = output;
+        self.captured = None;
+    }
+
+    /// Takes the first error which occurred whilst writing, if any.
+    pub(crate) fn take_error(&mut self) -> Option<io::Error> {
+        self.error.take()
+    }
+
+    /// Returns the errors skipped under the `ErrorPolicy`, to be passed to
+    /// the `on_error` hook of the lifecycle.
+    pub(crate) fn skipped_mut(&mut self) -> &mut Vec<EffluxError> {
+        &mut self.skipped
+    }
+
+    /// Takes all errors skipped under the `ErrorPolicy`.
+    pub(crate) fn take_skipped(&mut self) -> Vec<EffluxError> {
+        mem::take(&mut self.skipped)
+    }
+
+    /// Stores an error to be surfaced by the lifecycle, unless one exists.
+    pub(crate) fn set_error(&mut self, err: io::Error) {
+        self.error.get_or_insert(err);
+    }
+
+    /// Records the error of a write result, if any, before returning it.
+    ///
+    /// Broken pipes are recorded without being returned, to avoid callers
+    /// panicking via `unwrap` when the output is closed by the consumer.
+    fn record(&mut self, result: io::Result<()>) -> Result<(), EffluxError> {
+        match result {
+            Ok(()) => Ok(()),
+            Err(err) if err.kind() == io::ErrorKind::BrokenPipe => {
+                self.set_error(err);
+                Ok(())
+            }
+            Err(err) => {
+                self.set_error(io::Error::new(err.kind(), err.to_string()));
+                Err(EffluxError::Io(err))
+            }
+        }
+    }
+
+    /// Writes a formatted key/value pair to the main output, returning any errors.
+    fn try_write_fmt(&mut self, key: &dyn Display, val: &dyn Display) -> io::Result<()> {
+        let out = output_delimiter(&self.data);
+        write_pair(&mut self.output, out, key, val)
+    }
+
+    /// Writes a key/value pair to an output, returning any errors.
+    ///
+    /// If no name is provided, the main stage output will be used. If no
+    /// separator is provided, the configured output delimiter is used.
+    fn try_write(
+        &mut self,
+        name: Option<&str>,
+        key: &[u8],
+        sep: Option<&[u8]>,
+        val: &[u8],
+    ) -> io::Result<()> {
+        // grab a reference to the context output delimiters
+        let out = match sep {
+            Some(sep) => sep,
+            None => output_delimiter(&self.data),
+        };
+
+        // locate the output to write to
+        let output: &mut dyn Write = match name {
+            None => &mut self.output,
+            Some(name) => self.outputs.get_mut(name).ok_or_else(|| {
+                let msg = format!("output {} has not been registered", name);
+                io::Error::new(io::ErrorKind::NotFound, msg)
+            })?,
+        };
+
+        // write the pair and newline
+        output.write_all(key)?;
+        output.write_all(out)?;
+        output.write_all(val)?;
+        output.write_all(b"\n")
+    }
+}
+
+/// Retrieves the output delimiter from the data of a `Context`.
+///
+/// This takes the data map directly, so that the output of the `Context`
+/// can be borrowed mutably at the same time. If no `Delimiters` exist, the
+/// default output delimiter is used.
+fn output_delimiter(data: &HashMap<TypeId, Box<dyn Any>>) -> &[u8] {
+    data.get(&TypeId::of::<Delimiters>())
+        .and_then(|b| b.downcast_ref::<Delimiters>())
+        .unwrap_or_else(|| default_delimiters())
+        .output()
+}
+
+/// Returns the default `Delimiters`, used when none exist in a `Context`.
+fn default_delimiters() -> &'static Delimiters {
+    static DEFAULTS: OnceLock<Delimiters> = OnceLock::new();
+    DEFAULTS.get_or_init(|| Delimiters::with_separators(b"\t", b"\t"))
+}
+
+/// Writes a formatted key/value pair and newline to an output.
+fn write_pair(
+    output: &mut dyn Write,
+    out: &[u8],
+    key: &dyn Display,
+    val: &dyn Display,
+) -> io::Result<()> {
+    write!(output, "{}", key)?;
+    output.write_all(out)?;
+    write!(output, "{}", val)?;
+    output.write_all(b"\n")
+}
+
+/// Returns the `TypeId` values of all internal `Contextual` types.
+fn internal_types() -> [TypeId; 10] {
+    [
+        TypeId::of::<Configuration>(),
+        TypeId::of::<CounterBatch>(),
+        TypeId::of::<Counters>(),
+        TypeId::of::<DefaultOutput>(),
+        TypeId::of::<Delimiters>(),
+        TypeId::of::<ErrorPolicy>(),
+        TypeId::of::<LineNumber>(),
+        TypeId::of::<Offset>(),
+        TypeId::of::<Records>(),
+        TypeId::of::<ValueOrder>(),
+    ]
+}
+
+/// Debug implementation for `Context`, omitting the output sink.
+impl Debug for Context {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Context").field("data", &self.data).finish()
+    }
+}
+
+/// Default implementation for `Context`, writing to `io::stdout`.
+impl Default for Context {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::cell::RefCell;
+    use std::io::BufWriter;
+    use std::rc::Rc;
 
     #[test]
     fn test_context_creation() {
@@ -183,6 +758,43 @@ mod tests {
 
         assert!(ctx.get::<Configuration>().is_some());
         assert!(ctx.get::<Delimiters>().is_some());
+        assert!(ctx.get::<ErrorPolicy>().is_some());
+        assert!(ctx.get::<Records>().is_some());
+        assert!(ctx.get::<ValueOrder>().is_some());
+    }
+
+    #[test]
+    fn test_internal_accessors() {
+        let mut ctx = Context::new();
+
+        ctx.insert(Delimiters::with_separators(b":", b"|"));
+        ctx.get_mut::<Configuration>()
+            .unwrap()
+            .insert("key", "value");
+
+        assert_eq!(ctx.configuration().get("key"), Some("value"));
+        assert_eq!(ctx.delimiters().input(), b":");
+        assert_eq!(ctx.delimiters().output(), b"|");
+    }
+
+    #[test]
+    fn test_missing_delimiters() {
+        let mut ctx = Context::buffered();
+
+        assert!(ctx.remove::<Delimiters>());
+
+        ctx.write(b"key", b"value").unwrap();
+        ctx.write_fmt("number", 1).unwrap();
+
+        assert_eq!(ctx.delimiters().input(), b"\t");
+        assert_eq!(ctx.delimiters().record(), b"\n");
+        assert_eq!(
+            ctx.take_output(),
+            vec![
+                (b"key".to_vec(), b"value".to_vec()),
+                (b"number".to_vec(), b"1".to_vec()),
+            ]
+        );
     }
 
     #[test]
@@ -195,6 +807,17 @@ mod tests {
         assert!(ctx.get::<TestStruct>().is_some());
     }
 
+    #[test]
+    fn test_context_contains() {
+        let mut ctx = Context::new();
+
+        assert!(!ctx.contains::<TestStruct>());
+
+        ctx.insert(TestStruct(0));
+
+        assert!(ctx.contains::<TestStruct>());
+    }
+
     #[test]
     fn test_mutable_references() {
         let mut ctx = Context::new();
@@ -214,6 +837,19 @@ mod tests {
         assert_eq!(iref.unwrap().0, 1);
     }
 
+    #[test]
+    fn test_get_or_insert_with() {
+        let mut ctx = Context::new();
+
+        ctx.get_or_insert_with(|| TestStruct(1)).0 += 1;
+        ctx.get_or_insert_with(|| TestStruct(5)).0 += 1;
+
+        let iref = ctx.get::<TestStruct>();
+
+        assert!(iref.is_some());
+        assert_eq!(iref.unwrap().0, 3);
+    }
+
     #[test]
     fn test_taking_values() {
         let mut ctx = Context::new();
@@ -228,6 +864,339 @@ mod tests {
         assert!(take.is_none());
     }
 
+    #[test]
+    fn test_removing_values() {
+        let mut ctx = Context::new();
+        let val = TestStruct(0);
+
+        ctx.insert(val);
+
+        assert!(ctx.remove::<TestStruct>());
+        assert!(ctx.get::<TestStruct>().is_none());
+        assert!(!ctx.remove::<TestStruct>());
+    }
+
+    #[test]
+    fn test_clearing_user_state() {
+        let mut ctx = Context::new();
+
+        ctx.insert(Offset::new());
+        ctx.insert(CounterBatch::new());
+        ctx.insert(TestStruct(0));
+        ctx.clear_user_state();
+
+        assert!(ctx.get::<TestStruct>().is_none());
+        assert!(ctx.get::<CounterBatch>().is_some());
+        assert!(ctx.get::<Configuration>().is_some());
+        assert!(ctx.get::<Delimiters>().is_some());
+        assert!(ctx.get::<Offset>().is_some());
+    }
+
+    #[test]
+    fn test_incrementing_counters() {
+        let mut ctx = Context::new();
+
+        ctx.incr_counter("group", "label", 1);
+        ctx.incr_counter("group", "label", 2);
+
+        assert_eq!(ctx.counter_value("group", "label"), 3);
+        assert_eq!(ctx.counter_value("group", "other"), 0);
+    }
+
+    #[test]
+    fn test_input_file_lookup() {
+        let mut ctx = Context::new();
+
+        let env = vec![("map.input.file", "old.txt")];
+        ctx.insert(Configuration::with_env(env.into_iter()));
+
+        assert_eq!(ctx.input_file(), Some("old.txt"));
+
+        let env = vec![
+            ("map.input.file", "old.txt"),
+            ("mapreduce.map.input.file", "new.txt"),
+        ];
+        ctx.insert(Configuration::with_env(env.into_iter()));
+
+        assert_eq!(ctx.input_file(), Some("new.txt"));
+
+        ctx.insert(Configuration::with_env(
+            Vec::<(String, String)>::new().into_iter(),
+        ));
+
+        assert_eq!(ctx.input_file(), None);
+    }
+
+    #[test]
+    fn test_partition_lookup() {
+        let cases = vec![
+            (vec![("mapred.task.partition", "1")], Some(1)),
+            (
+                vec![
+                    ("mapred.task.partition", "1"),
+                    ("mapreduce.task.partition", "2"),
+                ],
+                Some(2),
+            ),
+            (vec![("mapreduce.task.partition", "two")], None),
+            (vec![], None),
+        ];
+
+        for (env, partition) in cases {
+            let mut ctx = Context::new();
+
+            ctx.insert(Configuration::with_env(env.into_iter()));
+
+            assert_eq!(ctx.partition(), partition);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_parsing_json() {
+        let mut ctx = Context::new();
+
+        let parsed = ctx.parse_json::<Vec<u32>>(b"[1,2,3]").unwrap();
+        assert_eq!(parsed, Some(vec![1, 2, 3]));
+
+        let parsed = ctx.parse_json::<Vec<u32>>(b"{broken").unwrap();
+        assert_eq!(parsed, None);
+        assert!(ctx.take_error().is_none());
+
+        ctx.insert(ErrorPolicy::Fail);
+
+        let parsed = ctx.parse_json::<Vec<u32>>(b"{broken");
+        assert!(matches!(parsed.unwrap_err(), EffluxError::Parse(_)));
+        assert!(ctx.take_error().is_some());
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_writing_json() {
+        let mut ctx = Context::buffered();
+        let mut map = std::collections::BTreeMap::new();
+
+        map.insert("count", 3);
+        map.insert("total", 10);
+
+        ctx.write_json("key", &map).unwrap();
+        ctx.write_json(1, "line\nbreak").unwrap();
+
+        assert_eq!(
+            ctx.take_output(),
+            vec![
+                (b"key".to_vec(), br#"{"count":3,"total":10}"#.to_vec()),
+                (b"1".to_vec(), br#""line\nbreak""#.to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "csv")]
+    fn test_parsing_fields() {
+        let mut ctx = Context::new();
+
+        assert_eq!(ctx.parse_fields(b"a\tb,c"), vec![&b"a"[..], &b"b,c"[..]]);
+
+        ctx.insert(Fields::csv());
+
+        assert_eq!(ctx.parse_fields(b"\"a,b\",c"), vec![&b"a,b"[..], &b"c"[..]]);
+    }
+
+    #[test]
+    fn test_buffered_output() {
+        let mut ctx = Context::buffered();
+
+        ctx.write(b"one", b"1").unwrap();
+        ctx.write_key(b"two").unwrap();
+
+        assert_eq!(
+            ctx.take_output(),
+            vec![
+                (b"one".to_vec(), b"1".to_vec()),
+                (b"two".to_vec(), b"".to_vec()),
+            ]
+        );
+        assert_eq!(ctx.take_output(), vec![]);
+    }
+
+    #[test]
+    fn test_emitting_pairs() {
+        let mut ctx = Context::buffered();
+
+        emit!(ctx, "key", 1).unwrap();
+        emit!(&mut ctx, 2.5, "value").unwrap();
+
+        assert_eq!(
+            ctx.take_output(),
+            vec![
+                (b"key".to_vec(), b"1".to_vec()),
+                (b"2.5".to_vec(), b"value".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_custom_output() {
+        let buffer = TestBuffer::default();
+        let mut ctx = Context::with_output(Box::new(buffer.clone()));
+
+        ctx.write(b"key", b"value").unwrap();
+        ctx.write_fmt("number", 1).unwrap();
+
+        assert_eq!(&*buffer.0.borrow(), b"key\tvalue\nnumber\t1\n");
+    }
+
+    #[test]
+    fn test_writing_byte_types() {
+        let buffer = TestBuffer::default();
+        let mut ctx = Context::with_output(Box::new(buffer.clone()));
+
+        ctx.write(b"bytes", &b"1"[..]).unwrap();
+        ctx.write("str", String::from("2")).unwrap();
+        ctx.write(vec![b'v'], vec![b'3']).unwrap();
+
+        assert_eq!(&*buffer.0.borrow(), b"bytes\t1\nstr\t2\nv\t3\n");
+    }
+
+    #[test]
+    fn test_writing_many() {
+        let buffer = TestBuffer::default();
+        let mut ctx = Context::with_output(Box::new(buffer.clone()));
+
+        ctx.write_many(vec![("one", "1"), ("two", "2")]).unwrap();
+
+        assert_eq!(&*buffer.0.borrow(), b"one\t1\ntwo\t2\n");
+    }
+
+    #[test]
+    fn test_writing_iterators() {
+        let buffer = TestBuffer::default();
+        let mut ctx = Context::with_output(Box::new(buffer.clone()));
+
+        let totals = [1, 2, 3].iter().scan(0, |total, value| {
+            *total += value;
+            Some((value * 10, *total))
+        });
+
+        ctx.write_iter(totals).unwrap();
+
+        assert_eq!(&*buffer.0.borrow(), b"10\t1\n20\t3\n30\t6\n");
+    }
+
+    #[test]
+    fn test_custom_separators() {
+        let buffer = TestBuffer::default();
+        let mut ctx = Context::with_output(Box::new(buffer.clone()));
+
+        ctx.write_with(b"header", b"line", b" ").unwrap();
+        ctx.write(b"key", b"value").unwrap();
+
+        assert_eq!(&*buffer.0.borrow(), b"header line\nkey\tvalue\n");
+    }
+
+    #[test]
+    fn test_writing_keys() {
+        let buffer = TestBuffer::default();
+        let mut ctx = Context::with_output(Box::new(buffer.clone()));
+
+        ctx.write_key(b"first").unwrap();
+        ctx.write_key(b"second").unwrap();
+
+        assert_eq!(&*buffer.0.borrow(), b"first\nsecond\n");
+    }
+
+    #[test]
+    fn test_named_outputs() {
+        let buffer = TestBuffer::default();
+        let rejects = TestBuffer::default();
+        let mut ctx = Context::with_output(Box::new(buffer.clone()));
+
+        ctx.register_output("rejects", Box::new(rejects.clone()));
+
+        ctx.write(b"valid", b"1").unwrap();
+        ctx.write_named("rejects", b"invalid", b"2").unwrap();
+
+        let result = ctx.write_named("missing", b"key", b"value");
+
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::NotFound);
+        assert_eq!(&*buffer.0.borrow(), b"valid\t1\n");
+        assert_eq!(&*rejects.0.borrow(), b"invalid\t2\n");
+    }
+
+    #[test]
+    fn test_flushing_output() {
+        let buffer = TestBuffer::default();
+        let writer = BufWriter::new(buffer.clone());
+        let mut ctx = Context::with_output(Box::new(writer));
+
+        ctx.write(b"key", b"value").unwrap();
+
+        assert!(buffer.0.borrow().is_empty());
+
+        ctx.flush().unwrap();
+
+        assert_eq!(&*buffer.0.borrow(), b"key\tvalue\n");
+    }
+
+    #[test]
+    fn test_formatted_writes() {
+        let buffer = TestBuffer::default();
+        let mut ctx = Context::with_output(Box::new(buffer.clone()));
+
+        ctx.write_fmt("key", 1).unwrap();
+        ctx.get_mut::<Delimiters>().unwrap().set_output(b"|");
+        ctx.write_fmt(2.5, format_args!("{}-{}", "a", "b")).unwrap();
+
+        assert_eq!(&*buffer.0.borrow(), b"key\t1\n2.5|a-b\n");
+    }
+
+    #[test]
+    fn test_storing_write_errors() {
+        let mut ctx = Context::with_output(Box::new(TestFailure));
+
+        // broken pipes are stored, but not returned
+        let result = ctx.write_fmt("key", "value");
+
+        assert!(result.is_ok());
+        assert!(ctx.take_error().is_some());
+
+        let result = ctx.write(b"key", b"value");
+
+        assert!(result.is_ok());
+
+        let err = ctx.take_error();
+
+        assert!(err.is_some());
+        assert_eq!(err.unwrap().kind(), io::ErrorKind::BrokenPipe);
+        assert!(ctx.take_error().is_none());
+    }
+
     struct TestStruct(usize);
     impl Contextual for TestStruct {}
+
+    #[derive(Clone, Default)]
+    struct TestBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for TestBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    struct TestFailure;
+
+    impl Write for TestFailure {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            Err(io::Error::new(io::ErrorKind::BrokenPipe, "closed"))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
 }
@@ -4,9 +4,11 @@
 ///
 /// This offers little more than abstraction over the byte
 /// offset being tracked manually; however the strong typing
-/// allows `Offset` to be added to a `Context`.
+/// allows `Offset` to be added to a `Context`. When present in
+/// a `Context`, this is set by the lifecycle runners to the byte
+/// offset of each record as it's read (as in Hadoop).
 #[derive(Debug, Default)]
-pub struct Offset(usize);
+pub struct Offset(u64);
 
 impl Offset {
     /// Creates a new `Offset` from index `0`.
@@ -14,11 +16,23 @@ impl Offset {
         Offset(0)
     }
 
-    /// Shifts the inner offset by the provided shift value.Reducer
+    /// Returns the current offset, without shifting.
+    #[inline]
+    pub fn current(&self) -> u64 {
+        self.0
+    }
+
+    /// Sets the inner offset to the provided value.
+    #[inline]
+    pub(crate) fn set(&mut self, offset: u64) {
+        self.0 = offset;
+    }
+
+    /// Shifts the inner offset by the provided shift value.
     ///
     /// The newly shifted offset is then returned, for convenience.
     #[inline]
-    pub fn shift(&mut self, shift: usize) -> usize {
+    pub fn shift(&mut self, shift: u64) -> u64 {
         self.0 += shift;
         self.0
     }
@@ -39,5 +53,6 @@ mod tests {
         assert_eq!(one, 1);
         assert_eq!(two, 2);
         assert_eq!(ten, 10);
+        assert_eq!(offset.current(), 10);
     }
 }
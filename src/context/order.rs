@@ -0,0 +1,183 @@
+//! Ordering bindings to support a secondary sort of reduce values.
+use std::cmp::Ordering;
+
+use super::conf::Configuration;
+use super::delim;
+
+/// Ordering struct to sort the values of a group during reduction.
+///
+/// This is configured using the same keys as the `KeyFieldBasedComparator`
+/// in Hadoop; `mapreduce.partition.keycomparator.options` provides the field
+/// (e.g. `-k2,2nr`) and `mapreduce.map.output.key.field.separator` provides
+/// the delimiter between fields (defaulting to a tab).
+///
+/// Fields are numbered from `1` across the entire record, so the first field
+/// after the key fields (see `stream.num.map.output.key.fields`) is the first
+/// field of the value. Fields within the key are already ordered by Hadoop,
+/// so values will only be sorted when the field falls within the value.
+#[derive(Clone, Debug, Default)]
+pub struct ValueOrder {
+    field: Option<usize>,
+    numeric: bool,
+    reverse: bool,
+    delim: Vec<u8>,
+}
+
+impl ValueOrder {
+    /// Creates a new `ValueOrder` from a job `Configuration`.
+    pub fn new(conf: &Configuration) -> Self {
+        let mut order = Self {
+            delim: conf
+                .get_or("mapreduce.map.output.key.field.separator", "\t")
+                .as_bytes()
+                .to_vec(),
+            ..Self::default()
+        };
+
+        let options = match conf.get("mapreduce.partition.keycomparator.options") {
+            Some(options) => options,
+            None => return order,
+        };
+
+        for option in options.split_whitespace() {
+            // global flags apply to the field
+            if option == "-n" || option == "-r" {
+                order.numeric |= option == "-n";
+                order.reverse |= option == "-r";
+                continue;
+            }
+
+            // only the first field definition is used
+            let spec = match option.strip_prefix("-k") {
+                Some(spec) if order.field.is_none() => spec,
+                _ => continue,
+            };
+
+            // strip any end position, as only a single field is supported
+            let start = spec.split(',').next().unwrap_or_default();
+            let digits = start.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+            let digits = digits.split('.').next().unwrap_or_default();
+
+            // flags can be attached to either position
+            order.numeric |= spec.contains('n');
+            order.reverse |= spec.contains('r');
+            order.field = digits.parse().ok();
+        }
+
+        // convert the record field into a value field
+        let fields = delim::key_fields(conf);
+
+        order.field = order
+            .field
+            .filter(|field| *field > fields)
+            .map(|field| field - fields);
+
+        order
+    }
+
+    /// Returns whether values should be sorted.
+    #[inline]
+    pub fn is_enabled(&self) -> bool {
+        self.field.is_some()
+    }
+
+    /// Compares two values using the configured field.
+    ///
+    /// Missing fields are treated as empty, and numeric fields which cannot
+    /// be parsed are treated as `0` (matching the behaviour of Hadoop).
+    pub fn compare(&self, left: &[u8], right: &[u8]) -> Ordering {
+        let field = match self.field {
+            Some(field) => field,
+            None => return Ordering::Equal,
+        };
+
+        let left = self.extract(left, field);
+        let right = self.extract(right, field);
+
+        let ordering = if self.numeric {
+            let left = parse_number(left);
+            let right = parse_number(right);
+
+            left.partial_cmp(&right).unwrap_or(Ordering::Equal)
+        } else {
+            left.cmp(right)
+        };
+
+        if self.reverse {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    }
+
+    /// Extracts the field at the given position (starting at `1`).
+    fn extract<'a>(&self, mut value: &'a [u8], field: usize) -> &'a [u8] {
+        // skip all preceding fields
+        for _ in 1..field {
+            match twoway::find_bytes(value, &self.delim) {
+                Some(n) => value = &value[n + self.delim.len()..],
+                None => return &[],
+            }
+        }
+
+        // trim any following fields
+        match twoway::find_bytes(value, &self.delim) {
+            Some(n) => &value[..n],
+            None => value,
+        }
+    }
+}
+
+/// Parses a field as a number, defaulting to `0`.
+fn parse_number(field: &[u8]) -> f64 {
+    std::str::from_utf8(field)
+        .ok()
+        .and_then(|field| field.trim().parse().ok())
+        .unwrap_or(0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_order_creation() {
+        let env = vec![("mapreduce.partition.keycomparator.options", "-k2,2nr")];
+
+        let conf = Configuration::with_env(env.into_iter());
+        let order = ValueOrder::new(&conf);
+
+        assert!(order.is_enabled());
+        assert_eq!(order.compare(b"1", b"10"), Ordering::Greater);
+        assert_eq!(order.compare(b"10", b"1"), Ordering::Less);
+    }
+
+    #[test]
+    fn test_order_within_key() {
+        let env = vec![
+            ("mapreduce.partition.keycomparator.options", "-k2,2"),
+            ("stream.num.map.output.key.fields", "2"),
+        ];
+
+        let conf = Configuration::with_env(env.into_iter());
+        let order = ValueOrder::new(&conf);
+
+        assert!(!order.is_enabled());
+        assert_eq!(order.compare(b"b", b"a"), Ordering::Equal);
+    }
+
+    #[test]
+    fn test_order_field_extraction() {
+        let env = vec![
+            ("mapreduce.partition.keycomparator.options", "-k3 -n"),
+            ("mapreduce.map.output.key.field.separator", ","),
+        ];
+
+        let conf = Configuration::with_env(env.into_iter());
+        let order = ValueOrder::new(&conf);
+
+        assert_eq!(order.compare(b"z,2", b"a,10"), Ordering::Less);
+        assert_eq!(order.compare(b"z,10,c", b"a,10"), Ordering::Equal);
+        assert_eq!(order.compare(b"z", b"a,-1"), Ordering::Greater);
+    }
+}
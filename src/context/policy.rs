@@ -0,0 +1,73 @@
+//! Error policy bindings to control the handling of failed records.
+use super::conf::Configuration;
+
+/// Policy enum to determine how failed records are handled.
+///
+/// The policy is selected via the `efflux.read.error.policy` key in
+/// the job `Configuration`, using one of the values `skip`, `fail` or
+/// `count`. If the key is missing or unrecognised, `Skip` is used.
+///
+/// This applies both to records which cannot be read from the input,
+/// and to values which cannot be parsed by the typed adapters (or by
+/// `Context::parse_json`).
+///
+/// Failures which are skipped (under either `Skip` or `Count`) are passed
+/// to the `on_error` handler of the current stage as an `EffluxError`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ErrorPolicy {
+    /// Skips the failed record and continues execution.
+    Skip,
+    /// Fails execution on the first failed record.
+    Fail,
+    /// Increments the `efflux` group counter before skipping.
+    Count,
+}
+
+impl ErrorPolicy {
+    /// Creates a new `ErrorPolicy` from a job `Configuration`.
+    pub fn new(conf: &Configuration) -> Self {
+        match conf.get("efflux.read.error.policy") {
+            Some("fail") => ErrorPolicy::Fail,
+            Some("count") => ErrorPolicy::Count,
+            _ => ErrorPolicy::Skip,
+        }
+    }
+}
+
+/// Default implementation for `ErrorPolicy`.
+impl Default for ErrorPolicy {
+    /// Skips failed records by default.
+    fn default() -> Self {
+        ErrorPolicy::Skip
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_policy_creation() {
+        let policies = vec![
+            ("skip", ErrorPolicy::Skip),
+            ("fail", ErrorPolicy::Fail),
+            ("count", ErrorPolicy::Count),
+            ("other", ErrorPolicy::Skip),
+        ];
+
+        for (value, policy) in policies {
+            let env = vec![("efflux.read.error.policy", value)];
+            let conf = Configuration::with_env(env.into_iter());
+
+            assert_eq!(ErrorPolicy::new(&conf), policy);
+        }
+    }
+
+    #[test]
+    fn test_policy_defaults() {
+        let env = Vec::<(String, String)>::new();
+        let conf = Configuration::with_env(env.into_iter());
+
+        assert_eq!(ErrorPolicy::new(&conf), ErrorPolicy::Skip);
+    }
+}
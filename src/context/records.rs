@@ -0,0 +1,57 @@
+//! Record bindings to track the number of processed records.
+
+/// Records structure to allow tracking of processed records.
+///
+/// This is maintained automatically by the lifecycle runners, and
+/// is incremented once for every record passed through a stage. If
+/// `efflux.counter.records` is set to `true` in the configuration,
+/// the final count is emitted as the `efflux` group counter `records`.
+#[derive(Debug, Default)]
+pub struct Records(u64);
+
+impl Records {
+    /// Creates a new `Records` from a count of `0`.
+    pub fn new() -> Records {
+        Records(0)
+    }
+
+    /// Returns the number of records processed.
+    #[inline]
+    pub fn count(&self) -> u64 {
+        self.0
+    }
+
+    /// Increments the inner count by a single record.
+    ///
+    /// The newly incremented count is then returned, for convenience.
+    #[inline]
+    pub fn increment(&mut self) -> u64 {
+        self.0 += 1;
+        self.0
+    }
+
+    /// Increments the inner count by many records.
+    ///
+    /// The newly incremented count is then returned, for convenience.
+    #[inline]
+    pub fn add(&mut self, count: u64) -> u64 {
+        self.0 += count;
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_records_increment() {
+        let mut records = Records::new();
+
+        assert_eq!(records.count(), 0);
+        assert_eq!(records.increment(), 1);
+        assert_eq!(records.increment(), 2);
+        assert_eq!(records.count(), 2);
+        assert_eq!(records.add(3), 5);
+    }
+}
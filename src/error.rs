@@ -0,0 +1,114 @@
+//! Error types exposed by the `efflux` crate.
+//!
+//! Failures within a stage are returned as an `EffluxError`; this covers the
+//! `try_run_lifecycle` runners, writes via a `Context` (e.g. `Context::write`)
+//! and `Context::parse_json`. The same type is also passed to the `on_error`
+//! hooks for any failures which are skipped under the current `ErrorPolicy`
+//! (e.g. records which failed to parse). Lower level IO operations (such as
+//! `Context::flush`, or the functions of the `codec` module) still return an
+//! `io::Error`.
+//!
+//! Conversions to and from `io::Error` are provided, so any `EffluxError`
+//! can still be propagated via `?` from a function returning `io::Result`.
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::io;
+use std::str::Utf8Error;
+
+/// Error type representing a failure within a stage.
+#[derive(Debug)]
+pub enum EffluxError {
+    /// Failure when reading or writing a record.
+    Io(io::Error),
+    /// Failure when decoding bytes as UTF-8.
+    Utf8(Utf8Error),
+    /// Failure when parsing a value, containing the message.
+    Parse(String),
+    /// Panic caught whilst handling a record, containing the message.
+    Panic(String),
+}
+
+impl EffluxError {
+    /// Returns the `io::ErrorKind` which best represents this error.
+    ///
+    /// Parse and decoding errors are treated as invalid data.
+    pub fn kind(&self) -> io::ErrorKind {
+        match self {
+            EffluxError::Io(err) => err.kind(),
+            EffluxError::Utf8(_) | EffluxError::Parse(_) => io::ErrorKind::InvalidData,
+            EffluxError::Panic(_) => io::ErrorKind::Other,
+        }
+    }
+}
+
+/// `Display` implementation for `EffluxError`.
+impl Display for EffluxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EffluxError::Io(err) => write!(f, "{}", err),
+            EffluxError::Utf8(err) => write!(f, "{}", err),
+            EffluxError::Parse(msg) | EffluxError::Panic(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+/// `Error` implementation for `EffluxError`.
+impl Error for EffluxError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            EffluxError::Io(err) => Some(err),
+            EffluxError::Utf8(err) => Some(err),
+            EffluxError::Parse(_) | EffluxError::Panic(_) => None,
+        }
+    }
+}
+
+/// Conversion from an `io::Error` into an `EffluxError`.
+impl From<io::Error> for EffluxError {
+    fn from(err: io::Error) -> Self {
+        EffluxError::Io(err)
+    }
+}
+
+/// Conversion from a `Utf8Error` into an `EffluxError`.
+impl From<Utf8Error> for EffluxError {
+    fn from(err: Utf8Error) -> Self {
+        EffluxError::Utf8(err)
+    }
+}
+
+/// Conversion from an `EffluxError` into an `io::Error`.
+impl From<EffluxError> for io::Error {
+    fn from(err: EffluxError) -> Self {
+        match err {
+            EffluxError::Io(err) => err,
+            err => io::Error::new(err.kind(), err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_conversions() {
+        let err = EffluxError::from(io::Error::new(io::ErrorKind::NotFound, "missing"));
+
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert_eq!(err.to_string(), "missing");
+        assert_eq!(io::Error::from(err).kind(), io::ErrorKind::NotFound);
+
+        let bytes = vec![0xff];
+        let err = EffluxError::from(std::str::from_utf8(&bytes).unwrap_err());
+
+        assert!(matches!(err, EffluxError::Utf8(_)));
+        assert!(err.source().is_some());
+
+        let err = EffluxError::Parse("failed to parse value".into());
+        let err = io::Error::from(err);
+
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "failed to parse value");
+    }
+}
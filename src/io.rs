@@ -2,29 +2,132 @@
 //!
 //! Provides lifecycles for Hadoop Streaming IO, to allow the rest
 //! of this crate to be a little more ignorant of how inputs flow.
-use bytelines::*;
-use std::io::{self, BufReader};
+//!
+//! The lifecycle runners respect several `efflux` keys set in the job
+//! `Configuration`, in addition to the standard Hadoop keys:
+//!
+//! - `efflux.counter.records`: emits the record count as a counter when `true`.
+//! - `efflux.input.strip.cr`: strips a trailing `\r` from records when `true`.
+//!   This happens after the record separator is removed, so it applies to the
+//!   bytes preceding any custom separator (and to a final unterminated record).
+//! - `efflux.output.buffer.bytes`: capacity of the output buffer (default 64KiB).
+//! - `efflux.read.error.policy`: how read errors are handled (see `ErrorPolicy`).
+//! - `efflux.status.interval`: emits a task status every N records read, to keep
+//!   long running tasks from hitting `mapreduce.task.timeout`. Disabled by default.
+//!   As the status is only emitted as records are read, a stage which spends a
+//!   long time without reading input (e.g. a CPU heavy reduction of a single
+//!   group) will not emit a status; use `update_status!` directly for this.
+//! - `efflux.task.catch.panics`: catches panics whilst handling each record when
+//!   `true`, applying the `ErrorPolicy` rather than aborting the task. As stage
+//!   state may be left partially updated by a panic, this is disabled by default.
+//! - `efflux.task.broken.pipe.exit.code`: exit code used when the output is
+//!   closed by a downstream consumer (default `0`).
+//! - `efflux.task.exit.code`: exit code used when a runner fails (default `1`).
+use std::collections::VecDeque;
+use std::env;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::iter;
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
+use std::process;
+
+use crate::codec;
+use crate::context::{
+    Configuration, Context, CounterBatch, Counters, ErrorPolicy, Offset, Records,
+};
+use crate::error::EffluxError;
 
-use crate::context::Context;
+/// Default capacity of the buffer used for stage output.
+const DEFAULT_OUTPUT_BUFFER: usize = 64 * 1024;
+
+/// Default exit code used when a runner fails.
+const DEFAULT_EXIT_CODE: i32 = 1;
+
+/// Default exit code used when the output is closed by the consumer.
+const DEFAULT_BROKEN_PIPE_EXIT_CODE: i32 = 0;
 
 /// Lifecycle trait to allow hooking into IO streams.
 ///
 /// This will be implemented by all stages of MapReduce (e.g. to
 /// appropriately handle buffering for the reduction stage). All
-/// trait methods default to noop, as they're all optional.
+/// trait methods default to noop, as they're all optional, except
+/// for `on_stream` which defaults to feeding records to `on_entry`.
 pub trait Lifecycle {
     /// Startup hook for the IO stream.
     fn on_start(&mut self, _ctx: &mut Context) {}
 
+    /// Stream hook for the IO stream to pull records from the input.
+    ///
+    /// The default implementation passes every record through to the
+    /// `on_entry` hook, halting on the first error. This can be overridden
+    /// by stages which need to control how far ahead they read the input.
+    ///
+    /// If an `Offset` exists in the `Context`, it's set to the offset of
+    /// each record before the record is passed through to `on_entry`.
+    fn on_stream(&mut self, input: &mut Input<'_>, ctx: &mut Context) -> io::Result<()> {
+        stream_entries(self, input, ctx)
+    }
+
     /// Entry hook for the IO stream to handle input values.
     fn on_entry(&mut self, _input: &[u8], _ctx: &mut Context) {}
 
+    /// Error hook for failures skipped by the IO stream.
+    ///
+    /// This is called for each failure (e.g. a read or parse error) which is
+    /// skipped rather than halting the stage, i.e. when the `ErrorPolicy` is
+    /// either `ErrorPolicy::Skip` or `ErrorPolicy::Count`.
+    fn on_error(&mut self, _err: &EffluxError, _ctx: &mut Context) {}
+
     /// Finalization hook for the IO stream.
     fn on_end(&mut self, _ctx: &mut Context) {}
 }
 
+/// `Lifecycle` implementation for boxed lifecycles.
+impl<L> Lifecycle for Box<L>
+where
+    L: Lifecycle + ?Sized,
+{
+    #[inline]
+    fn on_start(&mut self, ctx: &mut Context) {
+        (**self).on_start(ctx)
+    }
+
+    #[inline]
+    fn on_stream(&mut self, input: &mut Input<'_>, ctx: &mut Context) -> io::Result<()> {
+        (**self).on_stream(input, ctx)
+    }
+
+    #[inline]
+    fn on_entry(&mut self, input: &[u8], ctx: &mut Context) {
+        (**self).on_entry(input, ctx)
+    }
+
+    #[inline]
+    fn on_error(&mut self, err: &EffluxError, ctx: &mut Context) {
+        (**self).on_error(err, ctx)
+    }
+
+    #[inline]
+    fn on_end(&mut self, ctx: &mut Context) {
+        (**self).on_end(ctx)
+    }
+}
+
 /// Executes an IO `Lifecycle` against `io::stdin`.
-pub fn run_lifecycle<L>(mut lifecycle: L)
+///
+/// Any errors which occur during execution are logged, before the
+/// process exits with a non-zero exit code. Any output written prior
+/// to the failure is flushed first, and the exit code can be set via
+/// `efflux.task.exit.code` (defaulting to `1`).
+///
+/// Panics are not errors unless `efflux.task.catch.panics` is enabled,
+/// in which case a panic under `ErrorPolicy::Fail` exits with the same
+/// exit code. Otherwise a panic will abort the task with exit code `101`.
+///
+/// If the output is closed by a downstream consumer, the stage is halted
+/// and the process exits cleanly using `efflux.task.broken.pipe.exit.code`.
+pub fn run_lifecycle<L>(lifecycle: L)
 where
     L: Lifecycle,
 {
@@ -32,20 +135,969 @@ where
     let stdin = io::stdin();
     let stdin_lock = stdin.lock();
 
+    // delegate to the generic reader
+    run_lifecycle_with(stdin_lock, lifecycle);
+}
+
+/// Executes an IO `Lifecycle` against `io::stdin` using a provided `Context`.
+///
+/// This allows the `Configuration` (and any other types, such as the
+/// `Delimiters`) to be prepared ahead of time, rather than being read from
+/// the environment. Output is written to the output of the `Context`. Any
+/// errors are logged, before the process exits with a non-zero exit code.
+pub fn run_lifecycle_with_context<L>(ctx: Context, lifecycle: L)
+where
+    L: Lifecycle,
+{
+    exit_on_error(Ok(ctx), |ctx| {
+        // lock stdin for perf
+        let stdin = io::stdin();
+        let stdin_lock = stdin.lock();
+
+        execute(stdin_lock, lifecycle, ctx).map_err(EffluxError::Io)
+    });
+}
+
+/// Executes an IO `Lifecycle` against a provided `BufRead` source.
+///
+/// This is the same flow as `run_lifecycle`, but allows the caller to
+/// provide the input stream (which is typically useful for testing).
+pub fn run_lifecycle_with<R, L>(reader: R, lifecycle: L)
+where
+    R: BufRead,
+    L: Lifecycle,
+{
+    exit_on_error(stdout_context(), |ctx| {
+        execute(reader, lifecycle, ctx).map_err(EffluxError::Io)
+    });
+}
+
+/// Executes an IO `Lifecycle` against files named in the process arguments.
+///
+/// This is the same flow as `run_lifecycle`, except that each argument is
+/// treated as a file path to read input from. If no arguments are provided,
+/// this will fall back to reading input from `io::stdin`.
+pub fn run_lifecycle_with_args<L>(lifecycle: L)
+where
+    L: Lifecycle,
+{
+    exit_on_error(stdout_context(), |ctx| execute_args(lifecycle, ctx));
+}
+
+/// Executes an IO `Lifecycle` against `io::stdin`, returning any errors.
+#[inline]
+pub fn try_run_lifecycle<L>(lifecycle: L) -> Result<(), EffluxError>
+where
+    L: Lifecycle,
+{
+    // lock stdin for perf
+    let stdin = io::stdin();
+    let stdin_lock = stdin.lock();
+
+    // delegate to the generic reader
+    try_run_lifecycle_with(stdin_lock, lifecycle)
+}
+
+/// Executes an IO `Lifecycle` against `io::stdin` using a provided `Context`,
+/// returning any errors.
+pub fn try_run_lifecycle_with_context<L>(mut ctx: Context, lifecycle: L) -> Result<(), EffluxError>
+where
+    L: Lifecycle,
+{
+    // lock stdin for perf
+    let stdin = io::stdin();
+    let stdin_lock = stdin.lock();
+
+    execute(stdin_lock, lifecycle, &mut ctx).map_err(EffluxError::Io)
+}
+
+/// Executes an IO `Lifecycle` against a provided `BufRead` source.
+///
+/// Errors when reading from the source, or when writing to the output
+/// of the `Context`, will halt execution and be returned to the caller.
+pub fn try_run_lifecycle_with<R, L>(reader: R, lifecycle: L) -> Result<(), EffluxError>
+where
+    R: BufRead,
+    L: Lifecycle,
+{
+    execute(reader, lifecycle, &mut stdout_context()?).map_err(EffluxError::Io)
+}
+
+/// Executes an IO `Lifecycle` against files named in the process arguments.
+///
+/// Files are processed in the order they're provided, and all files are
+/// opened before execution begins. If no arguments are provided, input
+/// will be read from `io::stdin` instead.
+pub fn try_run_lifecycle_with_args<L>(lifecycle: L) -> Result<(), EffluxError>
+where
+    L: Lifecycle,
+{
+    execute_args(lifecycle, &mut stdout_context()?)
+}
+
+/// Executes an IO `Lifecycle` against files named in the process arguments,
+/// using the provided `Context`.
+fn execute_args<L>(lifecycle: L, ctx: &mut Context) -> Result<(), EffluxError>
+where
+    L: Lifecycle,
+{
+    // skip the binary path
+    let paths = env::args_os().skip(1).collect::<Vec<_>>();
+
+    // fall back to stdin
+    if paths.is_empty() {
+        let stdin = io::stdin();
+        let stdin_lock = stdin.lock();
+
+        return execute(stdin_lock, lifecycle, ctx).map_err(EffluxError::Io);
+    }
+
+    // open all files ahead of time
+    let mut readers = Vec::with_capacity(paths.len());
+    for path in paths {
+        readers.push(BufReader::new(File::open(path)?));
+    }
+
+    execute_all(readers, lifecycle, ctx).map_err(EffluxError::Io)
+}
+
+/// Executes an IO `Lifecycle` against a reader using the provided `Context`.
+#[inline]
+pub(crate) fn execute<R, L>(reader: R, lifecycle: L, ctx: &mut Context) -> io::Result<()>
+where
+    R: BufRead,
+    L: Lifecycle,
+{
+    execute_all(iter::once(reader), lifecycle, ctx)
+}
+
+/// Executes an IO `Lifecycle` against many readers using the provided `Context`.
+///
+/// Readers are consumed in order, with each reader terminating its final
+/// record (rather than the final record running into the next reader).
+fn execute_all<I, R, L>(readers: I, mut lifecycle: L, ctx: &mut Context) -> io::Result<()>
+where
+    I: IntoIterator<Item = R>,
+    R: BufRead,
+    L: Lifecycle,
+{
+    // fire the startup hooks
+    lifecycle.on_start(ctx);
+    check_error(ctx)?;
+
+    // decompress the input streams if necessary
+    let mut decoded = Vec::new();
+    for reader in readers {
+        decoded.push(codec::decode(reader, ctx.configuration())?);
+    }
+
+    // read all inputs as raw bytes, and fire the stream hooks
+    let mut input = Input::new(decoded, ctx);
+
+    lifecycle.on_stream(&mut input, ctx)?;
+    dispatch_errors(&mut lifecycle, Some(&mut input), ctx);
+    check_error(ctx)?;
+
+    // fire the finalization hooks
+    lifecycle.on_end(ctx);
+    dispatch_errors(&mut lifecycle, None, ctx);
+    check_error(ctx)?;
+
+    // optionally emit the record count as a counter
+    if let Some(true) = ctx
+        .get::<Configuration>()
+        .and_then(|conf| conf.get_bool("efflux.counter.records"))
+    {
+        update_counter!("efflux", "records", ctx.record_count());
+    }
+
+    // report any aggregated counters
+    if let Some(counters) = ctx.get_mut::<Counters>() {
+        counters.flush()?;
+    }
+
+    if let Some(batch) = ctx.get_mut::<CounterBatch>() {
+        batch.flush()?;
+    }
+
+    // flush any buffered output, finishing any compressed stream
+    ctx.finish()
+}
+
+/// Record based input for an IO `Lifecycle`.
+///
+/// Records are read lazily from many readers (in order), using a single
+/// internal buffer to avoid allocations. As such, each record is only
+/// available until the next record is read from the `Input`.
+///
+/// Read errors are handled using the `ErrorPolicy` stored in the `Context`;
+/// when using `ErrorPolicy::Fail` the returned error will contain the index
+/// of the failed record (starting from `0`). As a reader may fail repeatedly,
+/// two sequential read failures are treated as the end of that reader.
+pub struct Input<'a> {
+    readers: VecDeque<Box<dyn BufRead + 'a>>,
+    separator: Vec<u8>,
+    policy: ErrorPolicy,
+    strip_cr: bool,
+    buffer: Vec<u8>,
+    pending: bool,
+    failed: bool,
+    index: usize,
+    offset: u64,
+    position: u64,
+    records: u64,
+    heartbeat: Option<u64>,
+    status: Box<dyn Write + 'a>,
+    skipped: Vec<EffluxError>,
+}
+
+impl<'a> Input<'a> {
+    /// Constructs a new `Input` from a set of readers and a `Context`.
+    pub(crate) fn new(readers: Vec<Box<dyn BufRead + 'a>>, ctx: &Context) -> Self {
+        // fetch the record separator and error policy from the context
+        let separator = ctx.delimiters().record().to_vec();
+        let policy = ctx.get::<ErrorPolicy>().copied().unwrap_or_default();
+
+        // determine whether carriage returns should be stripped
+        let strip_cr = ctx
+            .get::<Configuration>()
+            .and_then(|conf| conf.get_bool("efflux.input.strip.cr"))
+            .unwrap_or(false);
+
+        // determine how often to report status, if at all
+        let heartbeat = ctx
+            .get::<Configuration>()
+            .and_then(|conf| conf.get_i64("efflux.status.interval"))
+            .filter(|interval| *interval > 0)
+            .map(|interval| interval as u64);
+
+        Self {
+            separator,
+            policy,
+            strip_cr,
+            heartbeat,
+            readers: readers.into(),
+            buffer: Vec::new(),
+            pending: false,
+            failed: false,
+            index: 0,
+            offset: 0,
+            position: 0,
+            records: 0,
+            status: Box::new(io::stderr()),
+            skipped: Vec::new(),
+        }
+    }
+
+    /// Sets the writer used to report the task status, rather than `stderr`.
+    #[cfg(test)]
+    pub(crate) fn with_status<W>(mut self, writer: W) -> Self
+    where
+        W: Write + 'a,
+    {
+        self.status = Box::new(writer);
+        self
+    }
+
+    /// Reads the next record from the input, if any.
+    pub fn next_record(&mut self) -> io::Result<Option<&[u8]>> {
+        if !self.pending && !self.fill()? {
+            return Ok(None);
+        }
+        self.pending = false;
+        Ok(Some(&self.buffer))
+    }
+
+    /// Returns the byte offset of the most recently read record.
+    ///
+    /// This is the position of the first byte of the record within the
+    /// current reader, accounting for the separators (and any carriage
+    /// returns) stripped from all prior records, as in Hadoop.
+    #[inline]
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Peeks at the next record from the input, without consuming it.
+    ///
+    /// The peeked record will be returned by the next call to `next_record`.
+    pub fn peek_record(&mut self) -> io::Result<Option<&[u8]>> {
+        if !self.pending && !self.fill()? {
+            return Ok(None);
+        }
+        self.pending = true;
+        Ok(Some(&self.buffer))
+    }
+
+    /// Reads all remaining input into the provided buffer, ignoring separators.
+    ///
+    /// Readers are read in order, with their contents concatenated. Any record
+    /// which has been peeked but not consumed is discarded.
+    pub(crate) fn read_all(&mut self, buffer: &mut Vec<u8>) -> io::Result<()> {
+        self.pending = false;
+        while let Some(mut reader) = self.readers.pop_front() {
+            reader.read_to_end(buffer)?;
+        }
+        Ok(())
+    }
+
+    /// Fills the internal buffer with the next record from the readers.
+    ///
+    /// The returned flag is `false` once all readers have been exhausted.
+    fn fill(&mut self) -> io::Result<bool> {
+        while let Some(reader) = self.readers.front_mut() {
+            self.buffer.clear();
+
+            match read_record(reader, &self.separator, &mut self.buffer) {
+                // eof, so move on to the next reader
+                Ok(0) => {
+                    self.readers.pop_front();
+                    self.failed = false;
+                    self.position = 0;
+                }
+
+                // successful read, so the buffer is ready
+                Ok(read) => {
+                    self.index += 1;
+                    self.failed = false;
+                    self.offset = self.position;
+                    self.position += read as u64;
+
+                    if self.strip_cr && self.buffer.last() == Some(&b'\r') {
+                        self.buffer.pop();
+                    }
+
+                    self.records += 1;
+
+                    // report progress to avoid task timeouts (best effort)
+                    if self
+                        .heartbeat
+                        .is_some_and(|n| self.records.is_multiple_of(n))
+                    {
+                        let status = format!("processed {} records", self.records);
+                        let _ = writeln!(self.status, "reporter:status:{}", status);
+                    }
+
+                    return Ok(true);
+                }
+
+                // failed reads are handled by the policy
+                Err(err) => {
+                    let index = self.index;
+
+                    self.index += 1;
+
+                    let msg = format!("failed to read record {}: {}", index, err);
+                    let err = io::Error::new(err.kind(), msg);
+
+                    if self.policy == ErrorPolicy::Fail {
+                        return Err(err);
+                    }
+
+                    if self.policy == ErrorPolicy::Count {
+                        update_counter!("efflux", "read_errors", 1);
+                    }
+
+                    // skipped errors are passed to the lifecycle
+                    self.skipped.push(EffluxError::Io(err));
+
+                    // bail on repeated failures
+                    if self.failed {
+                        self.readers.pop_front();
+                        self.failed = false;
+                        self.position = 0;
+                        continue;
+                    }
+
+                    self.failed = true;
+                }
+            }
+        }
+
+        Ok(false)
+    }
+}
+
+/// Creates a new `Context` which writes to a buffered `io::stdout`.
+fn stdout_context() -> io::Result<Context> {
     // create a job context
     let mut ctx = Context::new();
 
-    // fire the startup hooks
-    lifecycle.on_start(&mut ctx);
+    // fetch the output buffer capacity from the configuration
+    let capacity = output_capacity(ctx.configuration());
+
+    // buffer stdout, as the lifecycle will flush at the end
+    let output = BufWriter::with_capacity(capacity, io::stdout());
+
+    // compress the output, if a codec is configured
+    let output = codec::encode(output, ctx.configuration())?;
 
-    // create a line reader used to avoid vec allocations
-    let mut lines = BufReader::new(stdin_lock).byte_lines();
+    ctx.set_compressed_output(output);
+    Ok(ctx)
+}
+
+/// Executes a runner against a `Context`, exiting the process on failure.
+///
+/// Any buffered output is flushed before the error is logged, and the
+/// process exits with the exit code taken from the `Configuration`.
+fn exit_on_error<F>(ctx: io::Result<Context>, runner: F)
+where
+    F: FnOnce(&mut Context) -> Result<(), EffluxError>,
+{
+    let mut ctx = match ctx {
+        Ok(ctx) => ctx,
+        Err(err) => {
+            log_error!("Failed to execute lifecycle: {}", err);
+            process::exit(exit_code(&err.into(), &Configuration::new()));
+        }
+    };
 
-    // read all inputs from stdin, and fire the entry hooks
-    while let Some(Ok(input)) = lines.next() {
-        lifecycle.on_entry(input, &mut ctx);
+    if let Err(err) = runner(&mut ctx) {
+        // a closed output is expected when a consumer stops reading early
+        if err.kind() == io::ErrorKind::BrokenPipe {
+            log_info!("Output closed by consumer, halting lifecycle");
+        } else {
+            // keep any output written before the failure, ignoring further errors
+            let _ = ctx.finish();
+            log_error!("Failed to execute lifecycle: {}", err);
+        }
+
+        let code = match ctx.get::<Configuration>() {
+            Some(conf) => exit_code(&err, conf),
+            None => exit_code(&err, &Configuration::new()),
+        };
+
+        process::exit(code);
     }
+}
 
-    // fire the finalization hooks
-    lifecycle.on_end(&mut ctx);
+/// Retrieves the exit code used for an error from a `Configuration`.
+///
+/// This is read from `efflux.task.exit.code`, defaulting to `1`, unless
+/// the error is a broken pipe, in which case it's read from the key
+/// `efflux.task.broken.pipe.exit.code` instead (defaulting to `0`).
+fn exit_code(err: &EffluxError, conf: &Configuration) -> i32 {
+    let (key, default) = match err.kind() {
+        io::ErrorKind::BrokenPipe => (
+            "efflux.task.broken.pipe.exit.code",
+            DEFAULT_BROKEN_PIPE_EXIT_CODE,
+        ),
+        _ => ("efflux.task.exit.code", DEFAULT_EXIT_CODE),
+    };
+
+    conf.get(key)
+        .and_then(|code| code.parse().ok())
+        .unwrap_or(default)
+}
+
+/// Retrieves the output buffer capacity from a `Configuration`.
+///
+/// This is read from `efflux.output.buffer.bytes`, defaulting to 64KiB.
+fn output_capacity(conf: &Configuration) -> usize {
+    conf.get("efflux.output.buffer.bytes")
+        .and_then(|bytes| bytes.parse().ok())
+        .unwrap_or(DEFAULT_OUTPUT_BUFFER)
+}
+
+/// Passes every record of an `Input` through to the `on_entry` hook of a `Lifecycle`.
+///
+/// This is the default implementation of `Lifecycle::on_stream`, exposed so
+/// that lifecycles which only override streaming conditionally can fall back.
+pub(crate) fn stream_entries<L>(
+    lifecycle: &mut L,
+    input: &mut Input<'_>,
+    ctx: &mut Context,
+) -> io::Result<()>
+where
+    L: Lifecycle + ?Sized,
+{
+    // peek first, as the offset is unavailable once the record is borrowed
+    while input.peek_record()?.is_some() {
+        if let Some(records) = ctx.get_mut::<Records>() {
+            records.increment();
+        }
+
+        if let Some(offset) = ctx.get_mut::<Offset>() {
+            offset.set(input.offset());
+        }
+
+        if let Some(record) = input.next_record()? {
+            lifecycle.on_entry(record, ctx);
+        }
+
+        dispatch_errors(lifecycle, Some(&mut *input), ctx);
+        check_error(ctx)?;
+    }
+    Ok(())
+}
+
+/// Passes any skipped errors through to the `on_error` hook of a `Lifecycle`.
+///
+/// Errors skipped whilst reading the `Input` are passed first, followed by
+/// any errors skipped whilst handling records (e.g. parse errors).
+pub(crate) fn dispatch_errors<L>(
+    lifecycle: &mut L,
+    input: Option<&mut Input<'_>>,
+    ctx: &mut Context,
+) where
+    L: Lifecycle + ?Sized,
+{
+    let mut errors = match input {
+        Some(input) => mem::take(&mut input.skipped),
+        None => Vec::new(),
+    };
+
+    errors.append(&mut ctx.take_skipped());
+
+    for err in &errors {
+        lifecycle.on_error(err, ctx);
+    }
+}
+
+/// Determines whether panics should be caught whilst handling records.
+pub(crate) fn catches_panics(ctx: &Context) -> bool {
+    ctx.get::<Configuration>()
+        .and_then(|conf| conf.get_bool("efflux.task.catch.panics"))
+        .unwrap_or(false)
+}
+
+/// Executes a stage callback, handling any panic using the `ErrorPolicy`.
+///
+/// When not enabled, panics are left to unwind as usual. Otherwise a panic
+/// fails the stage under `ErrorPolicy::Fail`, and is skipped (and passed to
+/// `on_error`) otherwise, after incrementing the `efflux` counter `panics`
+/// under `ErrorPolicy::Count`.
+pub(crate) fn catch_panic<F>(enabled: bool, ctx: &mut Context, f: F)
+where
+    F: FnOnce(&mut Context),
+{
+    if !enabled {
+        return f(ctx);
+    }
+
+    let payload = match panic::catch_unwind(AssertUnwindSafe(|| f(ctx))) {
+        Ok(()) => return,
+        Err(payload) => payload,
+    };
+
+    let reason = payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown panic");
+
+    let msg = format!("panicked whilst handling record: {}", reason);
+
+    match ctx.get::<ErrorPolicy>().copied().unwrap_or_default() {
+        ErrorPolicy::Fail => ctx.set_error(io::Error::other(msg)),
+        policy => {
+            if policy == ErrorPolicy::Count {
+                update_counter!("efflux", "panics", 1);
+            }
+            ctx.skipped_mut().push(EffluxError::Panic(msg));
+        }
+    }
+}
+
+/// Checks a `Context` for any errors which occurred whilst writing.
+#[inline]
+pub(crate) fn check_error(ctx: &mut Context) -> io::Result<()> {
+    match ctx.take_error() {
+        Some(err) => Err(err),
+        None => Ok(()),
+    }
+}
+
+/// Reads a single record from a reader into the provided buffer.
+///
+/// The record is terminated by the provided separator, which is stripped
+/// from the buffer before returning. A final record with no trailing
+/// separator is returned as-is. The number of bytes consumed from the
+/// reader is returned, with `0` signalling the end of the input.
+fn read_record<R>(reader: &mut R, separator: &[u8], buffer: &mut Vec<u8>) -> io::Result<usize>
+where
+    R: BufRead,
+{
+    // scan on the final byte of the separator
+    let last = separator[separator.len() - 1];
+    let mut read = 0;
+
+    loop {
+        let n = reader.read_until(last, buffer)?;
+
+        read += n;
+
+        // a full separator, so strip and return
+        if buffer.ends_with(separator) {
+            buffer.truncate(buffer.len() - separator.len());
+            return Ok(read);
+        }
+
+        // eof, either with or without a record
+        if n == 0 || buffer.last() != Some(&last) {
+            return Ok(read);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::Delimiters;
+    use crate::local::Buffer;
+
+    #[test]
+    fn test_running_with_reader() {
+        let mut entries = Vec::new();
+        let input = &b"first_line\nsecond_line\nthird_line\n"[..];
+
+        run_lifecycle_with(input, TestLifecycle(&mut entries));
+
+        assert_eq!(
+            entries,
+            vec![&b"first_line"[..], b"second_line", b"third_line"]
+        );
+    }
+
+    #[test]
+    fn test_running_with_many_readers() {
+        let mut ctx = Context::new();
+        let mut entries = Vec::new();
+
+        let readers = vec![&b"first\nsecond"[..], &b"third\n"[..]];
+        let result = execute_all(readers, TestLifecycle(&mut entries), &mut ctx);
+
+        assert!(result.is_ok());
+        assert_eq!(entries, vec![&b"first"[..], b"second", b"third"]);
+        assert_eq!(ctx.record_count(), 3);
+    }
+
+    #[test]
+    fn test_running_without_delimiters() {
+        let mut ctx = Context::new();
+        let mut entries = Vec::new();
+
+        ctx.remove::<Delimiters>();
+
+        let result = execute(
+            &b"first\nsecond\n"[..],
+            TestLifecycle(&mut entries),
+            &mut ctx,
+        );
+
+        assert!(result.is_ok());
+        assert_eq!(entries, vec![&b"first"[..], b"second"]);
+    }
+
+    #[test]
+    fn test_flushing_counter_batches() {
+        let mut ctx = Context::new();
+        let mut entries = Vec::new();
+
+        let mut batch = CounterBatch::new();
+        batch.incr("group", "label", 1);
+        ctx.insert(batch);
+
+        execute(&b"first\n"[..], TestLifecycle(&mut entries), &mut ctx).unwrap();
+
+        assert!(ctx.get::<CounterBatch>().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_final_record_handling() {
+        let cases = vec![
+            (&b"a\nb"[..], vec![&b"a"[..], b"b"]),
+            (&b"a\nb\n"[..], vec![&b"a"[..], b"b"]),
+            (&b"a\n\n"[..], vec![&b"a"[..], b""]),
+        ];
+
+        for (input, expected) in cases {
+            let mut entries = Vec::new();
+
+            execute(input, TestLifecycle(&mut entries), &mut Context::new()).unwrap();
+
+            assert_eq!(entries, expected);
+        }
+    }
+
+    #[test]
+    fn test_stripping_carriage_returns() {
+        let mut ctx = Context::new();
+        let mut entries = Vec::new();
+
+        ctx.get_mut::<Configuration>()
+            .unwrap()
+            .insert("efflux.input.strip.cr", "true");
+
+        let input = &b"first\r\nsecond\nthird\r"[..];
+        let result = execute(input, TestLifecycle(&mut entries), &mut ctx);
+
+        assert!(result.is_ok());
+        assert_eq!(entries, vec![&b"first"[..], b"second", b"third"]);
+    }
+
+    #[test]
+    fn test_binary_input() {
+        let mut entries = Vec::new();
+        let input = &b"\xff\xfe\x00\nvalid\n\x80\n"[..];
+
+        run_lifecycle_with(input, TestLifecycle(&mut entries));
+
+        assert_eq!(entries, vec![&b"\xff\xfe\x00"[..], b"valid", b"\x80"]);
+    }
+
+    #[test]
+    fn test_skipping_read_errors() {
+        let mut ctx = Context::new();
+        let mut entries = Vec::new();
+
+        ctx.insert(ErrorPolicy::Skip);
+
+        let reader = io::BufReader::with_capacity(4, TestFailure(b"one\ntwo\n", 0));
+        let result = execute(reader, TestLifecycle(&mut entries), &mut ctx);
+
+        assert!(result.is_ok());
+        assert_eq!(entries, vec![&b"one"[..], b"two"]);
+    }
+
+    #[test]
+    fn test_handling_skipped_errors() {
+        let mut ctx = Context::new();
+        let mut errors = Vec::new();
+
+        ctx.insert(ErrorPolicy::Count);
+
+        let reader = io::BufReader::with_capacity(4, TestFailure(b"one\ntwo\n", 0));
+        let result = execute(reader, TestErrors(&mut errors), &mut ctx);
+
+        assert!(result.is_ok());
+        assert_eq!(errors, vec!["failed to read record 1: invalid"]);
+    }
+
+    #[test]
+    fn test_failing_read_errors() {
+        let mut ctx = Context::new();
+        let mut entries = Vec::new();
+
+        ctx.insert(ErrorPolicy::Fail);
+
+        let reader = io::BufReader::with_capacity(4, TestFailure(b"one\ntwo\n", 0));
+        let result = execute(reader, TestLifecycle(&mut entries), &mut ctx);
+
+        let err = result.unwrap_err();
+
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "failed to read record 1: invalid");
+        assert_eq!(entries, vec![&b"one"[..]]);
+    }
+
+    #[test]
+    fn test_peeking_records() {
+        let readers: Vec<Box<dyn BufRead>> =
+            vec![Box::new(&b"one\ntwo"[..]), Box::new(&b"three\n"[..])];
+        let mut input = Input::new(readers, &Context::new());
+
+        assert_eq!(input.peek_record().unwrap(), Some(&b"one"[..]));
+        assert_eq!(input.peek_record().unwrap(), Some(&b"one"[..]));
+        assert_eq!(input.next_record().unwrap(), Some(&b"one"[..]));
+        assert_eq!(input.next_record().unwrap(), Some(&b"two"[..]));
+        assert_eq!(input.peek_record().unwrap(), Some(&b"three"[..]));
+        assert_eq!(input.next_record().unwrap(), Some(&b"three"[..]));
+        assert_eq!(input.peek_record().unwrap(), None);
+        assert_eq!(input.next_record().unwrap(), None);
+    }
+
+    #[test]
+    fn test_status_heartbeats() {
+        let mut ctx = Context::new();
+
+        ctx.get_mut::<Configuration>()
+            .unwrap()
+            .insert("efflux.status.interval", "2");
+
+        let status = Buffer::default();
+        let readers: Vec<Box<dyn BufRead>> = vec![Box::new(&b"one\ntwo\nthree\nfour\nfive\n"[..])];
+        let mut input = Input::new(readers, &ctx).with_status(status.clone());
+
+        input.peek_record().unwrap();
+        input.peek_record().unwrap();
+        input.next_record().unwrap();
+        input.next_record().unwrap();
+
+        assert_eq!(status.take(), b"reporter:status:processed 2 records\n");
+
+        while input.next_record().unwrap().is_some() {}
+
+        assert_eq!(status.take(), b"reporter:status:processed 4 records\n");
+
+        let status = Buffer::default();
+        let readers: Vec<Box<dyn BufRead>> = vec![Box::new(&b"one\ntwo\n"[..])];
+        let mut input = Input::new(readers, &Context::new()).with_status(status.clone());
+
+        while input.next_record().unwrap().is_some() {}
+
+        assert!(status.take().is_empty());
+    }
+
+    #[test]
+    fn test_record_offsets() {
+        let cases = vec![
+            (&b"one\ntwo\nthree"[..], false, vec![0, 4, 8]),
+            (&b"one\r\ntwo\r\nthree"[..], false, vec![0, 5, 10]),
+            (&b"one\r\ntwo\r\nthree"[..], true, vec![0, 5, 10]),
+        ];
+
+        for (input, strip_cr, expected) in cases {
+            let mut ctx = Context::new();
+
+            ctx.get_mut::<Configuration>().unwrap().insert(
+                "efflux.input.strip.cr",
+                if strip_cr { "true" } else { "false" },
+            );
+
+            let readers: Vec<Box<dyn BufRead>> = vec![Box::new(input)];
+            let mut input = Input::new(readers, &ctx);
+            let mut offsets = Vec::new();
+
+            while input.next_record().unwrap().is_some() {
+                offsets.push(input.offset());
+            }
+
+            assert_eq!(offsets, expected);
+        }
+    }
+
+    #[test]
+    fn test_output_capacity() {
+        let env = vec![("efflux.output.buffer.bytes", "1024")];
+        let conf = Configuration::with_env(env.into_iter());
+
+        assert_eq!(output_capacity(&conf), 1024);
+
+        let env = vec![("efflux.output.buffer.bytes", "invalid")];
+        let conf = Configuration::with_env(env.into_iter());
+
+        assert_eq!(output_capacity(&conf), DEFAULT_OUTPUT_BUFFER);
+    }
+
+    #[test]
+    fn test_exit_code() {
+        let failure = EffluxError::Parse("failure".into());
+        let closed = EffluxError::Io(io::ErrorKind::BrokenPipe.into());
+
+        let env = vec![
+            ("efflux.task.exit.code", "3"),
+            ("efflux.task.broken.pipe.exit.code", "4"),
+        ];
+        let conf = Configuration::with_env(env.into_iter());
+
+        assert_eq!(exit_code(&failure, &conf), 3);
+        assert_eq!(exit_code(&closed, &conf), 4);
+
+        let env = vec![("efflux.task.exit.code", "invalid")];
+        let conf = Configuration::with_env(env.into_iter());
+
+        assert_eq!(exit_code(&failure, &conf), DEFAULT_EXIT_CODE);
+        assert_eq!(exit_code(&closed, &conf), DEFAULT_BROKEN_PIPE_EXIT_CODE);
+    }
+
+    #[test]
+    fn test_closed_output() {
+        let mut ctx = Context::with_output(Box::new(TestClosed));
+        let mut entries = Vec::new();
+
+        let input = &b"first_line\nsecond_line\n"[..];
+        let result = execute(input, TestWriter(&mut entries), &mut ctx);
+
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::BrokenPipe);
+        assert_eq!(entries, vec![b"first_line".to_vec()]);
+    }
+
+    #[test]
+    fn test_reading_records() {
+        let mut reader = &b"one||two|three||four"[..];
+        let mut buffer = Vec::new();
+        let mut records = Vec::new();
+
+        while read_record(&mut reader, b"||", &mut buffer).unwrap() > 0 {
+            records.push(buffer.clone());
+            buffer.clear();
+        }
+
+        assert_eq!(records, vec![&b"one"[..], b"two|three", b"four"]);
+    }
+
+    struct TestFailure<'a>(&'a [u8], usize);
+
+    impl<'a> io::Read for TestFailure<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            // fail on the second call only
+            self.1 += 1;
+            if self.1 == 2 {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid"));
+            }
+            self.0.read(buf)
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "zstd")]
+    fn test_finishing_compressed_output() {
+        let mut ctx = Context::new();
+        let mut entries = Vec::new();
+
+        ctx.get_mut::<Configuration>()
+            .unwrap()
+            .insert("efflux.output.codec", "zstd");
+
+        let buffer = Buffer::default();
+        let output = codec::encode(buffer.clone(), ctx.configuration()).unwrap();
+
+        ctx.set_compressed_output(output);
+
+        execute(&b"one\ntwo\n"[..], TestWriter(&mut entries), &mut ctx).unwrap();
+
+        let decoded = zstd::decode_all(&buffer.take()[..]).unwrap();
+
+        assert_eq!(decoded, b"one\tone\ntwo\ttwo\n");
+    }
+
+    struct TestClosed;
+
+    impl io::Write for TestClosed {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            Err(io::ErrorKind::BrokenPipe.into())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    struct TestErrors<'a>(&'a mut Vec<String>);
+
+    impl<'a> Lifecycle for TestErrors<'a> {
+        fn on_error(&mut self, err: &EffluxError, _ctx: &mut Context) {
+            self.0.push(err.to_string());
+        }
+    }
+
+    struct TestWriter<'a>(&'a mut Vec<Vec<u8>>);
+
+    impl<'a> Lifecycle for TestWriter<'a> {
+        fn on_entry(&mut self, input: &[u8], ctx: &mut Context) {
+            self.0.push(input.to_vec());
+
+            // unwrap is safe, as broken pipes are not surfaced to stages
+            ctx.write(input, input).unwrap();
+        }
+    }
+
+    struct TestLifecycle<'a>(&'a mut Vec<Vec<u8>>);
+
+    impl<'a> Lifecycle for TestLifecycle<'a> {
+        fn on_entry(&mut self, input: &[u8], _ctx: &mut Context) {
+            self.0.push(input.to_vec());
+        }
+    }
 }
@@ -0,0 +1,388 @@
+//! Building blocks for reduce-side joins across datasets.
+//!
+//! A reduce-side join is made up of two parts; a mapping stage which emits
+//! each record under its join key, tagged with the dataset it came from, and
+//! a reduction stage which separates the values of each key by their tag.
+//! This module offers the `TaggedMapper` for the former, and `TaggedValues`
+//! to partition the values of a group for the latter:
+//!
+//! ```rust
+//! use efflux::join::TaggedValues;
+//! use efflux::prelude::*;
+//! use efflux::reducer::Values;
+//!
+//! #[derive(Default)]
+//! struct Joiner(TaggedValues);
+//!
+//! impl Reducer for Joiner {
+//!     fn reduce_streaming(&mut self, key: &[u8], values: &mut Values<'_, '_>, ctx: &mut Context) {
+//!         self.0.clear();
+//!         self.0.extend_from(values);
+//!
+//!         for user in self.0.get(b"users") {
+//!             for order in self.0.get(b"orders") {
+//!                 let _ = ctx.write(user, order);
+//!             }
+//!         }
+//!     }
+//! }
+//! ```
+//!
+//! Tags are written before each value, separated by `TAG_SEPARATOR`. As such,
+//! a tag must never contain the separator itself.
+use std::borrow::Cow;
+
+use crate::context::Context;
+#[cfg(feature = "csv")]
+use crate::context::Fields;
+use crate::mapper::Mapper;
+use crate::reducer::Values;
+
+/// Separator written between a tag and the value it is attached to.
+pub const TAG_SEPARATOR: &[u8] = b"\t";
+
+/// `Mapper` to emit records under a join key, tagged by their dataset.
+///
+/// The join key is the field of each record at the configured index, with
+/// fields separated by a tab (unless changed via `with_separator`). Records
+/// without enough fields are skipped. With the `csv` feature enabled, fields
+/// are split via `Fields`, so quoted keys can be used via `with_fields`.
+///
+/// The tag of a record is derived from the name of the input file, as
+/// provided by `Context::input_file`; when the path contains a pattern
+/// provided to `with_tag`, the associated tag is used, otherwise the final
+/// segment of the path is used as the tag.
+#[derive(Debug)]
+pub struct TaggedMapper {
+    field: usize,
+    #[cfg(feature = "csv")]
+    fields: Fields,
+    #[cfg(not(feature = "csv"))]
+    separator: Vec<u8>,
+    patterns: Vec<(String, String)>,
+    tag: Vec<u8>,
+    buffer: Vec<u8>,
+}
+
+impl TaggedMapper {
+    /// Creates a new `TaggedMapper`, joining on the field at `field`.
+    pub fn new(field: usize) -> Self {
+        Self {
+            field,
+            #[cfg(feature = "csv")]
+            fields: Fields::tsv(),
+            #[cfg(not(feature = "csv"))]
+            separator: b"\t".to_vec(),
+            patterns: Vec::new(),
+            tag: Vec::new(),
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Sets the separator used to split records into fields.
+    pub fn with_separator(mut self, separator: &[u8]) -> Self {
+        #[cfg(feature = "csv")]
+        self.fields.set_separator(separator);
+        #[cfg(not(feature = "csv"))]
+        {
+            self.separator = separator.to_vec();
+        }
+        self
+    }
+
+    /// Sets the `Fields` used to split records into fields.
+    ///
+    /// This replaces any separator set via `with_separator`, and allows
+    /// the use of quoted fields. This requires the `csv` feature.
+    #[cfg(feature = "csv")]
+    pub fn with_fields(mut self, fields: Fields) -> Self {
+        self.fields = fields;
+        self
+    }
+
+    /// Tags records from input files containing `pattern` with `tag`.
+    ///
+    /// Patterns are checked in the order they were added.
+    pub fn with_tag<P, T>(mut self, pattern: P, tag: T) -> Self
+    where
+        P: Into<String>,
+        T: Into<String>,
+    {
+        self.patterns.push((pattern.into(), tag.into()));
+        self
+    }
+
+    /// Returns the tag assigned to records of the current input file.
+    #[inline]
+    pub fn tag(&self) -> &[u8] {
+        &self.tag
+    }
+
+    /// Locates the join key within a record, if it has enough fields.
+    #[cfg(feature = "csv")]
+    fn key<'a>(&self, record: &'a [u8]) -> Option<Cow<'a, [u8]>> {
+        self.fields.field(record, self.field)
+    }
+
+    /// Locates the join key within a record, if it has enough fields.
+    #[cfg(not(feature = "csv"))]
+    fn key<'a>(&self, record: &'a [u8]) -> Option<Cow<'a, [u8]>> {
+        let mut remaining = record;
+
+        for _ in 0..self.field {
+            let idx = twoway::find_bytes(remaining, &self.separator)?;
+            remaining = &remaining[idx + self.separator.len()..];
+        }
+
+        match twoway::find_bytes(remaining, &self.separator) {
+            Some(idx) => Some(Cow::Borrowed(&remaining[..idx])),
+            None => Some(Cow::Borrowed(remaining)),
+        }
+    }
+}
+
+/// `Mapper` implementation to emit tagged records.
+impl Mapper for TaggedMapper {
+    /// Setup handler by resolving the tag of the current input file.
+    fn setup(&mut self, ctx: &mut Context) {
+        let file = ctx.input_file().unwrap_or("");
+
+        let tag = self
+            .patterns
+            .iter()
+            .find(|(pattern, _)| file.contains(pattern.as_str()))
+            .map(|(_, tag)| tag.as_str())
+            .unwrap_or_else(|| file.rsplit('/').next().unwrap_or(file));
+
+        self.tag = tag.as_bytes().to_vec();
+    }
+
+    /// Mapping handler by emitting the tagged record under the join key.
+    fn map(&mut self, _key: usize, value: &[u8], ctx: &mut Context) {
+        let key = match self.key(value) {
+            Some(key) => key,
+            None => return,
+        };
+
+        self.buffer.clear();
+        self.buffer.extend_from_slice(&self.tag);
+        self.buffer.extend_from_slice(TAG_SEPARATOR);
+        self.buffer.extend_from_slice(value);
+
+        // errors are surfaced by the lifecycle
+        let _ = ctx.write(key, &self.buffer);
+    }
+}
+
+/// Splits a tagged value into the tag and the original value.
+///
+/// Values without a tag are treated as having an empty tag.
+pub fn split_tag(value: &[u8]) -> (&[u8], &[u8]) {
+    match twoway::find_bytes(value, TAG_SEPARATOR) {
+        Some(idx) => (&value[..idx], &value[idx + TAG_SEPARATOR.len()..]),
+        None => (b"", value),
+    }
+}
+
+/// Values of a reduction group, partitioned into buckets by tag.
+///
+/// Buckets are kept in the order their tags were first seen. This can be
+/// stored on a `Reducer` and cleared between groups; buckets are retained
+/// when cleared, so their allocations are reused by the next group.
+#[derive(Debug, Default)]
+pub struct TaggedValues {
+    buckets: Vec<(Vec<u8>, Vec<Vec<u8>>)>,
+    active: usize,
+}
+
+impl TaggedValues {
+    /// Creates a new, empty `TaggedValues`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a tagged value to the bucket of its tag.
+    pub fn push(&mut self, value: &[u8]) {
+        let (tag, value) = split_tag(value);
+        let active = &mut self.buckets[..self.active];
+
+        if let Some((_, bucket)) = active.iter_mut().find(|(t, _)| t == tag) {
+            bucket.push(value.to_vec());
+            return;
+        }
+
+        // reuse a cleared bucket before allocating a new one
+        match self.buckets.get_mut(self.active) {
+            Some((t, bucket)) => {
+                t.clear();
+                t.extend_from_slice(tag);
+                bucket.push(value.to_vec());
+            }
+            None => self.buckets.push((tag.to_vec(), vec![value.to_vec()])),
+        }
+
+        self.active += 1;
+    }
+
+    /// Adds all remaining tagged values of a streamed group.
+    pub fn extend_from(&mut self, values: &mut Values<'_, '_>) {
+        while let Some(value) = values.next_value() {
+            self.push(value);
+        }
+    }
+
+    /// Retrieves the values stored under a tag.
+    ///
+    /// Tags which have not been seen return an empty slice.
+    pub fn get(&self, tag: &[u8]) -> &[Vec<u8>] {
+        self.buckets[..self.active]
+            .iter()
+            .find(|(t, _)| t == tag)
+            .map(|(_, bucket)| bucket.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Returns an iterator over all tags which have been seen.
+    pub fn tags(&self) -> impl Iterator<Item = &[u8]> {
+        self.buckets[..self.active]
+            .iter()
+            .map(|(tag, _)| tag.as_slice())
+    }
+
+    /// Removes all values, ready for the next group.
+    ///
+    /// Buckets are cleared in place rather than dropped, to reuse them.
+    pub fn clear(&mut self) {
+        for (_, bucket) in &mut self.buckets[..self.active] {
+            bucket.clear();
+        }
+        self.active = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::Configuration;
+    use crate::io::execute;
+    use crate::mapper::MapperLifecycle;
+    use crate::reducer::{Reducer, ReducerLifecycle};
+
+    #[test]
+    fn test_tagged_mapping() {
+        let cases = vec![
+            ("hdfs:///data/users/part-0000", "users"),
+            ("hdfs:///data/orders.csv", "orders.csv"),
+        ];
+
+        for (file, tag) in cases {
+            let mut ctx = Context::buffered();
+
+            ctx.get_mut::<Configuration>()
+                .unwrap()
+                .insert("mapreduce.map.input.file", file);
+
+            let mapper = TaggedMapper::new(1)
+                .with_separator(b",")
+                .with_tag("/users/", "users");
+
+            execute(
+                &b"1,alice\n2\n3,bob,x\n"[..],
+                MapperLifecycle::new(mapper),
+                &mut ctx,
+            )
+            .unwrap();
+
+            assert_eq!(
+                ctx.take_output(),
+                vec![
+                    (b"alice".to_vec(), format!("{}\t1,alice", tag).into_bytes()),
+                    (b"bob".to_vec(), format!("{}\t3,bob,x", tag).into_bytes()),
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn test_tagged_values() {
+        let mut ctx = Context::buffered();
+        let input = &b"1\tusers\talice\n1\torders\tbook\n1\torders\tpen\n2\torders\tcup\n"[..];
+
+        execute(
+            input,
+            ReducerLifecycle::new(TestJoiner::default()),
+            &mut ctx,
+        )
+        .unwrap();
+
+        assert_eq!(
+            ctx.take_output(),
+            vec![
+                (b"alice".to_vec(), b"book".to_vec()),
+                (b"alice".to_vec(), b"pen".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "csv")]
+    fn test_tagged_mapping_quoted() {
+        let mut ctx = Context::buffered();
+        let mapper = TaggedMapper::new(1).with_fields(crate::context::Fields::csv());
+
+        execute(
+            &b"1,\"smith, alice\"\n"[..],
+            MapperLifecycle::new(mapper),
+            &mut ctx,
+        )
+        .unwrap();
+
+        assert_eq!(
+            ctx.take_output(),
+            vec![(b"smith, alice".to_vec(), b"\t1,\"smith, alice\"".to_vec())]
+        );
+    }
+
+    #[test]
+    fn test_tagged_values_clearing() {
+        let mut values = TaggedValues::new();
+
+        values.push(b"users\talice");
+        values.push(b"orders\tbook");
+        values.clear();
+
+        assert_eq!(values.tags().count(), 0);
+        assert!(values.get(b"users").is_empty());
+
+        values.push(b"orders\tpen");
+        values.push(b"orders\tcup");
+
+        assert_eq!(values.tags().collect::<Vec<_>>(), vec![&b"orders"[..]]);
+        assert_eq!(values.get(b"orders"), &[b"pen".to_vec(), b"cup".to_vec()]);
+        assert!(values.get(b"users").is_empty());
+        assert_eq!(values.buckets.len(), 2);
+    }
+
+    #[derive(Default)]
+    struct TestJoiner(TaggedValues);
+
+    impl Reducer for TestJoiner {
+        fn reduce_streaming(
+            &mut self,
+            _key: &[u8],
+            values: &mut Values<'_, '_>,
+            ctx: &mut Context,
+        ) {
+            self.0.clear();
+            self.0.extend_from(values);
+
+            assert!(self.0.tags().all(|tag| tag == b"users" || tag == b"orders"));
+
+            for user in self.0.get(b"users") {
+                for order in self.0.get(b"orders") {
+                    ctx.write(user, order).unwrap();
+                }
+            }
+        }
+    }
+}
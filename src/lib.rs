@@ -10,20 +10,102 @@
 #![doc(html_root_url = "https://docs.rs/efflux/2.0.1")]
 #[macro_use]
 pub mod macros;
+pub mod aggregate;
+pub mod codec;
+pub mod combiner;
 pub mod context;
+pub mod error;
 pub mod io;
+pub mod join;
+pub mod local;
 pub mod mapper;
+pub mod partition;
 pub mod reducer;
+mod spill;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod typed;
 
+use self::combiner::Combiner;
+use self::context::{Configuration, Context};
 use self::mapper::Mapper;
 use self::reducer::Reducer;
 
+use self::combiner::CombinerLifecycle;
 use self::mapper::MapperLifecycle;
 use self::reducer::ReducerLifecycle;
 
-use self::io::run_lifecycle;
+#[cfg(feature = "parallel")]
+pub use self::local::run_local_parallel;
+pub use self::local::{run_local, run_local_partitioned};
+
+use self::io::{
+    run_lifecycle, run_lifecycle_with, run_lifecycle_with_args, run_lifecycle_with_context,
+};
+use std::borrow::Cow;
+use std::io::BufRead;
+use std::str::{self, Utf8Error};
+
+/// Updates a counter for the current job.
+///
+/// This is a function equivalent of the `update_counter!` macro, and writes
+/// the exact same output. This is useful when a group or label is computed at
+/// runtime, or when a counter update needs to be passed around as a value.
+#[inline]
+pub fn counter(group: &str, label: &str, amount: i64) {
+    update_counter!(group, label, amount);
+}
+
+/// Converts a key or value to a `&str`, validating it as UTF-8.
+///
+/// This is a shorthand for `str::from_utf8`, so that stages can surface
+/// invalid input (e.g. via `?` into an `EffluxError`) rather than panicking:
+///
+/// ```rust
+/// assert_eq!(efflux::as_str(b"value"), Ok("value"));
+/// assert!(efflux::as_str(&[0xff]).is_err());
+/// ```
+#[inline]
+pub fn as_str(bytes: &[u8]) -> Result<&str, Utf8Error> {
+    str::from_utf8(bytes)
+}
+
+/// Converts a key or value to a `str`, replacing any invalid UTF-8.
+///
+/// Invalid sequences are replaced with `U+FFFD`, which is useful for dirty
+/// data where a best effort is acceptable. Valid input is not copied:
+///
+/// ```rust
+/// use std::borrow::Cow;
+///
+/// assert_eq!(efflux::as_str_lossy(b"value"), Cow::Borrowed("value"));
+/// assert_eq!(efflux::as_str_lossy(b"val\xffue"), "val\u{FFFD}ue");
+/// ```
+#[inline]
+pub fn as_str_lossy(bytes: &[u8]) -> Cow<'_, str> {
+    String::from_utf8_lossy(bytes)
+}
+
+/// Executes either a `Mapper` or a `Reducer` against the current `stdin`.
+///
+/// The stage is detected using the `mapreduce.task.ismap` key of the job
+/// `Configuration`, allowing a single binary to act as both stages. If the
+/// key is missing (e.g. when running outside of Hadoop), the `Mapper` is used.
+#[inline]
+pub fn run<M, R>(mapper: M, reducer: R)
+where
+    M: Mapper + 'static,
+    R: Reducer + 'static,
+{
+    match Configuration::new().get_bool("mapreduce.task.ismap") {
+        Some(false) => run_reducer(reducer),
+        _ => run_mapper(mapper),
+    }
+}
 
 /// Executes a `Mapper` against the current `stdin`.
+///
+/// Any errors are logged and cause the process to exit with a non-zero code.
 #[inline]
 pub fn run_mapper<M>(mapper: M)
 where
@@ -33,6 +115,8 @@ where
 }
 
 /// Executes a `Reducer` against the current `stdin`.
+///
+/// Any errors are logged and cause the process to exit with a non-zero code.
 #[inline]
 pub fn run_reducer<R>(reducer: R)
 where
@@ -41,6 +125,83 @@ where
     run_lifecycle(ReducerLifecycle::new(reducer));
 }
 
+/// Executes a `Combiner` against the current `stdin`.
+///
+/// Any errors are logged and cause the process to exit with a non-zero code.
+#[inline]
+pub fn run_combiner<C>(combiner: C)
+where
+    C: Combiner + 'static,
+{
+    run_lifecycle(CombinerLifecycle::new(combiner));
+}
+
+/// Executes a `Mapper` against a provided `BufRead` source.
+#[inline]
+pub fn run_mapper_with_reader<M, R>(mapper: M, reader: R)
+where
+    M: Mapper + 'static,
+    R: BufRead,
+{
+    run_lifecycle_with(reader, MapperLifecycle::new(mapper));
+}
+
+/// Executes a `Reducer` against a provided `BufRead` source.
+#[inline]
+pub fn run_reducer_with_reader<R, B>(reducer: R, reader: B)
+where
+    R: Reducer + 'static,
+    B: BufRead,
+{
+    run_lifecycle_with(reader, ReducerLifecycle::new(reducer));
+}
+
+/// Executes a `Mapper` against the current `stdin` using a provided `Context`.
+///
+/// This allows the `Configuration` and `Delimiters` of the stage to be set
+/// up ahead of time, rather than being read from the environment.
+#[inline]
+pub fn run_mapper_with_context<M>(mapper: M, ctx: Context)
+where
+    M: Mapper + 'static,
+{
+    run_lifecycle_with_context(ctx, MapperLifecycle::new(mapper));
+}
+
+/// Executes a `Reducer` against the current `stdin` using a provided `Context`.
+///
+/// This allows the `Configuration` and `Delimiters` of the stage to be set
+/// up ahead of time, rather than being read from the environment.
+#[inline]
+pub fn run_reducer_with_context<R>(reducer: R, ctx: Context)
+where
+    R: Reducer + 'static,
+{
+    run_lifecycle_with_context(ctx, ReducerLifecycle::new(reducer));
+}
+
+/// Executes a `Mapper` against files named in the process arguments.
+///
+/// If no arguments are provided, this will read from `stdin` instead.
+#[inline]
+pub fn run_mapper_with_args<M>(mapper: M)
+where
+    M: Mapper + 'static,
+{
+    run_lifecycle_with_args(MapperLifecycle::new(mapper));
+}
+
+/// Executes a `Reducer` against files named in the process arguments.
+///
+/// If no arguments are provided, this will read from `stdin` instead.
+#[inline]
+pub fn run_reducer_with_args<R>(reducer: R)
+where
+    R: Reducer + 'static,
+{
+    run_lifecycle_with_args(ReducerLifecycle::new(reducer));
+}
+
 // prelude module
 pub mod prelude {
     //! A "prelude" for crates using the `efflux` crate.
@@ -54,7 +215,7 @@ pub mod prelude {
     //!
     //! The prelude may grow over time, but it is unlikely to shrink.
     pub use super::context::{Configuration, Context, Contextual};
-    pub use super::log;
     pub use super::mapper::Mapper;
     pub use super::reducer::Reducer;
+    pub use super::{log, log_error, log_info, log_warn};
 }
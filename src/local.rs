@@ -0,0 +1,426 @@
+//! Local execution of MapReduce jobs, without Hadoop.
+//!
+//! This module offers the ability to run an entire job in memory, by
+//! running a `Mapper` against an input, simulating the Hadoop shuffle
+//! by sorting the mapper output, and feeding it through a `Reducer`.
+//! This is primarily useful when testing a job against sample data.
+//!
+//! To test partition-sensitive jobs, `run_local_partitioned` will split
+//! the mapper output across several reducers using a `Partitioner`.
+//!
+//! With the `parallel` feature enabled, `run_local_parallel` can be used to
+//! run the `Mapper` across several threads, which speeds up large inputs.
+use std::cell::RefCell;
+use std::io::{self, BufRead, Write};
+use std::rc::Rc;
+#[cfg(feature = "parallel")]
+use std::thread;
+
+use crate::context::{split_fields, Context, Delimiters};
+use crate::io::execute;
+use crate::mapper::{Mapper, MapperLifecycle};
+use crate::partition::Partitioner;
+use crate::reducer::{Reducer, ReducerLifecycle};
+
+/// Executes an entire job locally, returning the pairs emitted by the `Reducer`.
+///
+/// The `Mapper` output is sorted by key (mimicking the shuffle of Hadoop) before
+/// being passed to the `Reducer`. Values are kept in the order they were emitted
+/// for each key. Both stages run through the same lifecycles used in production.
+///
+/// # Panics
+///
+/// This function will panic if either stage fails with an IO error.
+pub fn run_local<M, R, I>(mapper: M, reducer: R, input: I) -> Vec<(Vec<u8>, Vec<u8>)>
+where
+    M: Mapper,
+    R: Reducer,
+    I: BufRead,
+{
+    // run the mapping stage into a buffer
+    let buffer = Buffer::default();
+    let mut ctx = Context::new();
+
+    ctx.set_output(Box::new(buffer.clone()));
+
+    execute(input, MapperLifecycle::new(mapper), &mut ctx).expect("mapping stage failed");
+
+    reduce_local(&buffer.take(), ctx.delimiters(), reducer)
+}
+
+/// Executes an entire job locally, simulating `partitions` reducers.
+///
+/// The `Mapper` output is split across partitions using the `Partitioner`,
+/// and each partition is then sorted and passed to a separate clone of the
+/// `Reducer`, as with `run_local`. The pairs emitted by each reducer are
+/// returned in order of partition (including any empty partitions). If the
+/// number of partitions is `0`, a single partition is used.
+///
+/// # Panics
+///
+/// This function will panic if either stage fails with an IO error, or if
+/// the `Partitioner` returns a partition outside of the valid range.
+pub fn run_local_partitioned<M, R, P, I>(
+    mapper: M,
+    reducer: R,
+    partitioner: P,
+    partitions: usize,
+    input: I,
+) -> Vec<Vec<(Vec<u8>, Vec<u8>)>>
+where
+    M: Mapper,
+    R: Reducer + Clone,
+    P: Partitioner,
+    I: BufRead,
+{
+    // run the mapping stage into a buffer
+    let buffer = Buffer::default();
+    let mut ctx = Context::new();
+
+    ctx.set_output(Box::new(buffer.clone()));
+
+    execute(input, MapperLifecycle::new(mapper), &mut ctx).expect("mapping stage failed");
+
+    // parse the mapper output into pairs
+    let delim = ctx.delimiters();
+    let pairs = split_pairs(&buffer.take(), delim.output(), delim.key_fields());
+
+    // assign each pair to a partition
+    let mut shuffled = vec![Vec::new(); partitions.max(1)];
+
+    for pair in pairs {
+        let partition = partitioner.partition(&pair.0, shuffled.len());
+
+        assert!(
+            partition < shuffled.len(),
+            "illegal partition {} for {} partitions",
+            partition,
+            shuffled.len()
+        );
+
+        shuffled[partition].push(pair);
+    }
+
+    shuffled
+        .into_iter()
+        .map(|pairs| reduce_pairs(pairs, reducer.clone()))
+        .collect()
+}
+
+/// Executes an entire job locally, running the `Mapper` across many threads.
+///
+/// The input is split into (at most) `threads` chunks on record boundaries,
+/// with each chunk mapped on its own thread using a clone of the `Mapper`
+/// and a separate `Context`. If `threads` is `0`, the available parallelism
+/// of the machine is used. The output of each chunk is merged in order of
+/// the input before the shuffle, which then continues as with `run_local`.
+///
+/// As each chunk is mapped separately, the byte offsets provided to the
+/// `Mapper` are relative to the start of each chunk (much like the input
+/// splits of a Hadoop job). This requires the `parallel` feature.
+///
+/// # Panics
+///
+/// This function will panic if either stage fails with an IO error.
+#[cfg(feature = "parallel")]
+pub fn run_local_parallel<M, R, I>(
+    mapper: M,
+    reducer: R,
+    mut input: I,
+    threads: usize,
+) -> Vec<(Vec<u8>, Vec<u8>)>
+where
+    M: Mapper + Clone + Send,
+    R: Reducer,
+    I: BufRead,
+{
+    let ctx = Context::new();
+    let mut bytes = Vec::new();
+
+    input.read_to_end(&mut bytes).expect("mapping stage failed");
+
+    let threads = match threads {
+        0 => thread::available_parallelism().map_or(1, |threads| threads.get()),
+        threads => threads,
+    };
+
+    let chunks = split_chunks(&bytes, ctx.delimiters().record(), threads);
+
+    // map each chunk on a separate thread, collecting the outputs in order
+    let outputs = thread::scope(|scope| {
+        let handles = chunks
+            .into_iter()
+            .map(|chunk| {
+                let mapper = mapper.clone();
+                scope.spawn(move || {
+                    let buffer = Buffer::default();
+                    let mut ctx = Context::new();
+
+                    ctx.set_output(Box::new(buffer.clone()));
+
+                    execute(chunk, MapperLifecycle::new(mapper), &mut ctx)
+                        .expect("mapping stage failed");
+
+                    buffer.take()
+                })
+            })
+            .collect::<Vec<_>>();
+
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("mapping stage panicked"))
+            .collect::<Vec<_>>()
+    });
+
+    reduce_local(&outputs.concat(), ctx.delimiters(), reducer)
+}
+
+/// Shuffles the output of a mapping stage, before passing it to a `Reducer`.
+///
+/// The output is split into pairs using the provided `Delimiters`, which
+/// are then sorted by key before being fed through the reduction stage.
+fn reduce_local<R>(output: &[u8], delim: &Delimiters, reducer: R) -> Vec<(Vec<u8>, Vec<u8>)>
+where
+    R: Reducer,
+{
+    // parse the mapper output into pairs
+    let pairs = split_pairs(output, delim.output(), delim.key_fields());
+
+    reduce_pairs(pairs, reducer)
+}
+
+/// Sorts a set of mapper output pairs by key, before passing them to a `Reducer`.
+fn reduce_pairs<R>(mut pairs: Vec<(Vec<u8>, Vec<u8>)>, reducer: R) -> Vec<(Vec<u8>, Vec<u8>)>
+where
+    R: Reducer,
+{
+    // shuffle by sorting on the key
+    pairs.sort_by(|left, right| left.0.cmp(&right.0));
+
+    // run the reduction stage into a buffer
+    let buffer = Buffer::default();
+    let mut ctx = Context::new();
+
+    ctx.set_output(Box::new(buffer.clone()));
+
+    // rebuild the input using the reducer delimiters
+    let delim = ctx.delimiters().input();
+    let mut input = Vec::new();
+
+    for (key, val) in pairs {
+        input.extend(key);
+        input.extend(delim);
+        input.extend(val);
+        input.push(b'\n');
+    }
+
+    execute(&input[..], ReducerLifecycle::new(reducer), &mut ctx).expect("reduction stage failed");
+
+    // parse the reducer output into pairs
+    let delim = ctx.delimiters().output();
+    split_pairs(&buffer.take(), delim, 1)
+}
+
+/// Splits stage output into key/value pairs using the provided delimiter.
+///
+/// Keys are made up of the first `fields` fields of each line, and lines
+/// without enough delimiters are treated as a key with an empty value.
+pub(crate) fn split_pairs(output: &[u8], delim: &[u8], fields: usize) -> Vec<(Vec<u8>, Vec<u8>)> {
+    let mut pairs = Vec::new();
+
+    for line in output.split(|b| *b == b'\n') {
+        // skip the trailing line
+        if line.is_empty() {
+            continue;
+        }
+
+        // split on the key delimiter, when present
+        let (key, val) = split_fields(line, delim, fields);
+
+        pairs.push((key.to_vec(), val.to_vec()));
+    }
+
+    pairs
+}
+
+/// Splits an input into at most `count` chunks, on record boundaries.
+///
+/// Chunks are roughly equal in size, and each chunk ends after a record
+/// separator (except the last). At least one chunk is always returned.
+#[cfg(feature = "parallel")]
+fn split_chunks<'a>(input: &'a [u8], separator: &[u8], count: usize) -> Vec<&'a [u8]> {
+    let size = input.len() / count.max(1) + 1;
+    let mut chunks = Vec::new();
+    let mut start = 0;
+
+    while start < input.len() {
+        // search slightly early, in case the target splits a separator
+        let target = (start + size).min(input.len());
+        let from = target.saturating_sub(separator.len() - 1).max(start);
+
+        let end = match twoway::find_bytes(&input[from..], separator) {
+            Some(offset) => from + offset + separator.len(),
+            None => input.len(),
+        };
+
+        chunks.push(&input[start..end]);
+        start = end;
+    }
+
+    if chunks.is_empty() {
+        chunks.push(input);
+    }
+
+    chunks
+}
+
+/// Shared in-memory buffer used to capture stage output.
+#[derive(Clone, Default)]
+pub(crate) struct Buffer(Rc<RefCell<Vec<u8>>>);
+
+impl Buffer {
+    /// Takes the buffered bytes, leaving the buffer empty.
+    pub(crate) fn take(&self) -> Vec<u8> {
+        self.0.replace(Vec::new())
+    }
+}
+
+/// `Write` implementation for the shared buffer.
+impl Write for Buffer {
+    #[inline]
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    #[inline]
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::partition::HashPartitioner;
+    use crate::reducer::IdentityReducer;
+
+    #[test]
+    fn test_local_execution() {
+        let input = &b"one two\nthree one\ntwo one\n"[..];
+
+        let mapper = |_key: usize, value: &[u8], ctx: &mut Context| {
+            for word in value.split(|b| *b == b' ') {
+                ctx.write(word, b"1").unwrap();
+            }
+        };
+
+        let reducer = |key: &[u8], values: &[&[u8]], ctx: &mut Context| {
+            ctx.write_fmt(std::str::from_utf8(key).unwrap(), values.len())
+                .unwrap();
+        };
+
+        let output = run_local(mapper, reducer, input);
+
+        assert_eq!(
+            output,
+            vec![
+                (b"one".to_vec(), b"3".to_vec()),
+                (b"three".to_vec(), b"1".to_vec()),
+                (b"two".to_vec(), b"2".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "parallel")]
+    fn test_parallel_local_execution() {
+        let input = b"one two\nthree one\ntwo one\nfour\none\n".repeat(10);
+
+        let mapper = |_key: usize, value: &[u8], ctx: &mut Context| {
+            for word in value.split(|b| *b == b' ') {
+                ctx.write(word, b"1").unwrap();
+            }
+        };
+
+        let reducer = |key: &[u8], values: &[&[u8]], ctx: &mut Context| {
+            ctx.write_fmt(std::str::from_utf8(key).unwrap(), values.len())
+                .unwrap();
+        };
+
+        for threads in 0..4 {
+            assert_eq!(
+                run_local_parallel(mapper, reducer, &input[..], threads),
+                run_local(mapper, reducer, &input[..])
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "parallel")]
+    fn test_splitting_chunks() {
+        let chunks = split_chunks(b"one||two||three||four", b"||", 4);
+
+        assert_eq!(chunks, vec![&b"one||two||"[..], b"three||", b"four"]);
+        assert_eq!(split_chunks(b"", b"\n", 3), vec![&b""[..]]);
+    }
+
+    #[test]
+    fn test_partitioned_local_execution() {
+        let input = &b"one two\nthree one\ntwo one\nfour\n"[..];
+
+        let mapper = |_key: usize, value: &[u8], ctx: &mut Context| {
+            for word in value.split(|b| *b == b' ') {
+                ctx.write(word, b"1").unwrap();
+            }
+        };
+
+        let reducer = |key: &[u8], values: &[&[u8]], ctx: &mut Context| {
+            ctx.write_fmt(std::str::from_utf8(key).unwrap(), values.len())
+                .unwrap();
+        };
+
+        let output = run_local_partitioned(mapper, reducer, HashPartitioner, 3, input);
+
+        assert_eq!(
+            output,
+            vec![
+                vec![(b"three".to_vec(), b"1".to_vec())],
+                vec![(b"four".to_vec(), b"1".to_vec())],
+                vec![
+                    (b"one".to_vec(), b"3".to_vec()),
+                    (b"two".to_vec(), b"2".to_vec()),
+                ],
+            ]
+        );
+
+        let output = run_local_partitioned(mapper, reducer, HashPartitioner, 0, input);
+
+        assert_eq!(output, vec![run_local(mapper, reducer, input)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "illegal partition 3 for 3 partitions")]
+    fn test_partitioned_local_range() {
+        let mapper = |_key: usize, value: &[u8], ctx: &mut Context| {
+            ctx.write(value, b"1").unwrap();
+        };
+
+        let partitioner = |_key: &[u8], num_partitions: usize| num_partitions;
+
+        run_local_partitioned(mapper, IdentityReducer, partitioner, 3, &b"one\n"[..]);
+    }
+
+    #[test]
+    fn test_splitting_pairs() {
+        let pairs = split_pairs(b"key\tvalue\nkey\nkey\t\n", b"\t", 1);
+
+        assert_eq!(
+            pairs,
+            vec![
+                (b"key".to_vec(), b"value".to_vec()),
+                (b"key".to_vec(), b"".to_vec()),
+                (b"key".to_vec(), b"".to_vec()),
+            ]
+        );
+    }
+}
@@ -11,6 +11,44 @@ macro_rules! log {
     ($fmt:expr, $($arg:tt)*) => (eprintln!($fmt, $($arg)*));
 }
 
+/// Prints an informational message to the Hadoop task logs.
+///
+/// This is a wrapper around `log!` which prefixes the message with an
+/// `INFO` level token, to make it easier to filter the task logs.
+#[macro_export]
+macro_rules! log_info {
+    ($($arg:tt)+) => (log!("INFO: {}", format_args!($($arg)+)));
+}
+
+/// Prints a warning message to the Hadoop task logs.
+///
+/// This is a wrapper around `log!` which prefixes the message with a
+/// `WARN` level token, to make it easier to filter the task logs.
+#[macro_export]
+macro_rules! log_warn {
+    ($($arg:tt)+) => (log!("WARN: {}", format_args!($($arg)+)));
+}
+
+/// Prints an error message to the Hadoop task logs.
+///
+/// This is a wrapper around `log!` which prefixes the message with an
+/// `ERROR` level token, to make it easier to filter the task logs.
+#[macro_export]
+macro_rules! log_error {
+    ($($arg:tt)+) => (log!("ERROR: {}", format_args!($($arg)+)));
+}
+
+/// Emits a key/value pair to the output of a `Context`.
+///
+/// This is a thin wrapper around `Context::write_fmt`, accepting any types
+/// which implement `Display`. The result of the write is returned as-is.
+#[macro_export]
+macro_rules! emit {
+    ($ctx:expr, $key:expr, $val:expr) => {
+        $ctx.write_fmt($key, $val)
+    };
+}
+
 /// Updates a counter for the current job.
 ///
 /// A counter belongs to a group by a label; as such both must be given
@@ -20,6 +58,8 @@ macro_rules! log {
 ///
 /// This is simply a sane wrapper around `log!` to ensure that
 /// counter updates are always logged in the correct formatting.
+/// Each update writes a line to the task logs; for counters updated
+/// frequently, see `CounterBatch` in the `context` module instead.
 #[macro_export]
 macro_rules! update_counter {
     ($group:expr, $label:expr, $amount:expr) => {
@@ -3,8 +3,26 @@
 //! This module offers the `Mapper` trait, which allows a developer
 //! to easily create a mapping stage due to the sane defaults. Also
 //! offered is the `MapperLifecycle` binding for use as an IO stage.
-use crate::context::{Context, Offset};
-use crate::io::Lifecycle;
+//!
+//! For input which is already made up of key/value pairs, the `KeyValueMapper`
+//! trait receives each record pre-split (much like `KeyValueTextInputFormat`
+//! in Hadoop), and is executed by wrapping it in the `KeyValue` adapter.
+//!
+//! Setting `efflux.input.whole.file` to `true` will instead pass the entire
+//! input to `Mapper::map` as a single record (much like a whole file input
+//! format in Hadoop), with a key of `0`. As the input is buffered in memory
+//! in full, this should only be used for small inputs (such as a single
+//! document per split).
+use std::error::Error;
+use std::io;
+
+use crate::context::{
+    split_fields_checked, Configuration, Context, Contextual, LineNumber, Offset, Records,
+};
+use crate::error::EffluxError;
+use crate::io::{
+    catch_panic, catches_panics, check_error, dispatch_errors, stream_entries, Input, Lifecycle,
+};
 
 /// Trait to represent the mapping stage of MapReduce.
 ///
@@ -15,15 +33,67 @@ pub trait Mapper {
     /// Setup handler for the current `Mapper`.
     fn setup(&mut self, _ctx: &mut Context) {}
 
+    /// Fallible setup handler for the current `Mapper`.
+    ///
+    /// Any error returned will abort the stage before any input is read,
+    /// exiting with a logged message when run via the `efflux` runners. The
+    /// default implementation simply delegates to the infallible `setup`.
+    fn try_setup(&mut self, ctx: &mut Context) -> Result<(), Box<dyn Error>> {
+        self.setup(ctx);
+        Ok(())
+    }
+
     /// Mapping handler for the current `Mapper`.
     ///
     /// The default implementation is to simply emit each key/value pair as they
     /// are received, without any changes. As such, this is where most developers
     /// will immediately begin to change things.
+    ///
+    /// The key is the byte offset of the record, as a `usize` for compatibility;
+    /// this truncates offsets beyond 4GB on 32-bit targets, in which case the
+    /// full offset should be read via `Context::offset` instead.
+    ///
+    /// The output of the default implementation can be selected by setting
+    /// `efflux.map.default.output` in the job `Configuration` (which is read
+    /// once, as the stage starts):
+    ///
+    /// - `offset`: emits the byte offset as the key, and the record as the value.
+    ///   This is the default, to match the Hadoop implementation.
+    /// - `value`: emits the record as the key, with no separator or value.
     fn map(&mut self, key: usize, value: &[u8], ctx: &mut Context) {
-        ctx.write(key.to_string().as_bytes(), value);
+        let output = ctx.get::<DefaultOutput>().copied().unwrap_or_default();
+
+        // errors are surfaced by the lifecycle
+        let _ = match output {
+            DefaultOutput::Offset => ctx.write(key.to_string(), value),
+            DefaultOutput::Value => ctx.write_key(value),
+        };
     }
 
+    /// Batch mapping handler for the current `Mapper`.
+    ///
+    /// This is only called when `mapreduce.input.lineinputformat.linespermap`
+    /// is set (much like `NLineInputFormat` in Hadoop), in which case records
+    /// are provided in batches of that many lines, rather than one at a time.
+    /// The key is the byte offset of the first line, and the final batch may
+    /// contain fewer lines if the input runs out.
+    ///
+    /// The default implementation passes each line through to `map`, using
+    /// the key of the batch for every line. The key is truncated on 32-bit
+    /// targets in the same way as the key passed to `map`.
+    fn map_batch(&mut self, key: usize, lines: &[Vec<u8>], ctx: &mut Context) {
+        for line in lines {
+            self.map(key, line, ctx);
+        }
+    }
+
+    /// Error handler for the current `Mapper`.
+    ///
+    /// This is called for each failure which is skipped under the current
+    /// `ErrorPolicy` (such as a record which could not be read), allowing
+    /// a `Mapper` to track failures. The default implementation is a no-op.
+    fn on_error(&mut self, _err: &EffluxError, _ctx: &mut Context) {}
+
     /// Cleanup handler for the current `Mapper`.
     fn cleanup(&mut self, _ctx: &mut Context) {}
 }
@@ -40,12 +110,140 @@ where
     }
 }
 
+/// Output of the default `Mapper::map` implementation.
+///
+/// This is parsed from `efflux.map.default.output` as the stage starts, and
+/// stored in the `Context` to avoid a lookup in the `Configuration` per record.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub(crate) enum DefaultOutput {
+    #[default]
+    Offset,
+    Value,
+}
+
+impl DefaultOutput {
+    /// Parses the `DefaultOutput` from a `Configuration`.
+    fn new(conf: &Configuration) -> Self {
+        match conf.get("efflux.map.default.output") {
+            Some("value") => DefaultOutput::Value,
+            _ => DefaultOutput::Offset,
+        }
+    }
+}
+
+impl Contextual for DefaultOutput {}
+
+/// `Mapper` which emits each record unchanged, keyed by its byte offset.
+///
+/// This simply uses the default `Mapper` implementation, and exists to
+/// avoid declaring an empty `Mapper` for jobs where mapping is a no-op.
+/// As such, the output can be changed via `efflux.map.default.output`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct IdentityMapper;
+
+impl Mapper for IdentityMapper {}
+
+/// Trait to represent a mapping stage operating on key/value records.
+///
+/// This mirrors the `Mapper` trait, except that each record is split into a
+/// key and value on the first occurrence of the map input separator (which is
+/// set via `stream.map.input.field.separator`, defaulting to a tab). Records
+/// without a separator are provided as a key with an empty value.
+pub trait KeyValueMapper {
+    /// Setup handler for the current `KeyValueMapper`.
+    fn setup(&mut self, _ctx: &mut Context) {}
+
+    /// Mapping handler for the current `KeyValueMapper`.
+    fn map(&mut self, key: &[u8], value: &[u8], ctx: &mut Context);
+
+    /// Error handler for failures skipped by the current `KeyValueMapper`.
+    fn on_error(&mut self, _err: &EffluxError, _ctx: &mut Context) {}
+
+    /// Cleanup handler for the current `KeyValueMapper`.
+    fn cleanup(&mut self, _ctx: &mut Context) {}
+}
+
+/// Adapter structure to execute key/value mapping stages.
+#[derive(Debug, Default)]
+pub struct KeyValue<M>(pub M);
+
+/// `Mapper` implementation for key/value mapping stages.
+impl<M> Mapper for KeyValue<M>
+where
+    M: KeyValueMapper,
+{
+    /// Setup handler by passing through to the inner mapper.
+    #[inline]
+    fn setup(&mut self, ctx: &mut Context) {
+        self.0.setup(ctx)
+    }
+
+    /// Mapping handler by splitting the record before passing to the inner mapper.
+    #[inline]
+    fn map(&mut self, _key: usize, value: &[u8], ctx: &mut Context) {
+        let (key, value) = {
+            let delim = ctx.delimiters();
+            split_fields_checked(value, delim.input(), 1)
+        };
+
+        self.0.map(key, value.unwrap_or(b""), ctx)
+    }
+
+    /// Error handler by passing through to the inner mapper.
+    #[inline]
+    fn on_error(&mut self, err: &EffluxError, ctx: &mut Context) {
+        self.0.on_error(err, ctx)
+    }
+
+    /// Cleanup handler by passing through to the inner mapper.
+    #[inline]
+    fn cleanup(&mut self, ctx: &mut Context) {
+        self.0.cleanup(ctx)
+    }
+}
+
 /// Lifecycle structure to represent a mapping.
 pub(crate) struct MapperLifecycle<M>
 where
     M: Mapper,
 {
     mapper: M,
+    catch_panics: bool,
+    whole_file: bool,
+    batch: Batch,
+}
+
+/// Batch of lines buffered for `Mapper::map_batch`.
+///
+/// Lines are only buffered when a batch size is configured, and buffers are
+/// retained between batches to avoid reallocating each line.
+#[derive(Default)]
+struct Batch {
+    size: Option<usize>,
+    key: usize,
+    lines: Vec<Vec<u8>>,
+    filled: usize,
+}
+
+impl Batch {
+    /// Appends a line to the batch, returning whether the batch is full.
+    fn push(&mut self, key: usize, line: &[u8]) -> bool {
+        if self.filled == 0 {
+            self.key = key;
+        }
+
+        if self.filled == self.lines.len() {
+            self.lines.push(Vec::new());
+        }
+
+        let buffer = &mut self.lines[self.filled];
+
+        buffer.clear();
+        buffer.extend_from_slice(line);
+
+        self.filled += 1;
+        self.size.is_some_and(|size| self.filled >= size)
+    }
 }
 
 /// Basic creation for `MapperLifecycle`
@@ -55,7 +253,24 @@ where
 {
     /// Constructs a new `MapperLifecycle` instance.
     pub(crate) fn new(mapper: M) -> Self {
-        Self { mapper }
+        Self {
+            mapper,
+            catch_panics: false,
+            whole_file: false,
+            batch: Batch::default(),
+        }
+    }
+
+    /// Passes the buffered batch of lines through to the mapper.
+    fn map_batch(&mut self, ctx: &mut Context) {
+        let (batch, mapper) = (&mut self.batch, &mut self.mapper);
+        let lines = &batch.lines[..batch.filled];
+
+        catch_panic(self.catch_panics, ctx, |ctx| {
+            mapper.map_batch(batch.key, lines, ctx)
+        });
+
+        batch.filled = 0;
     }
 }
 
@@ -68,26 +283,93 @@ where
     #[inline]
     fn on_start(&mut self, ctx: &mut Context) {
         ctx.insert(Offset::new());
-        self.mapper.setup(ctx);
+        ctx.insert(LineNumber::new());
+        self.catch_panics = catches_panics(ctx);
+
+        if let Some(conf) = ctx.get::<Configuration>() {
+            let output = DefaultOutput::new(conf);
+
+            self.whole_file = conf.get_bool("efflux.input.whole.file").unwrap_or(false);
+            self.batch.size = conf
+                .get("mapreduce.input.lineinputformat.linespermap")
+                .and_then(|size| size.parse().ok())
+                .filter(|size| *size > 0);
+
+            ctx.insert(output);
+        }
+
+        if let Err(err) = self.mapper.try_setup(ctx) {
+            let msg = format!("failed to setup mapper: {}", err);
+            ctx.set_error(io::Error::other(msg));
+        }
+    }
+
+    /// Streams the input through to the mapper.
+    ///
+    /// If whole file input is enabled, the entire input is read into memory
+    /// and passed through as a single entry, at an offset of `0`. Otherwise
+    /// each record is passed through to `on_entry` as usual.
+    fn on_stream(&mut self, input: &mut Input<'_>, ctx: &mut Context) -> io::Result<()> {
+        if !self.whole_file {
+            return stream_entries(self, input, ctx);
+        }
+
+        let mut buffer = Vec::new();
+        input.read_all(&mut buffer)?;
+
+        if let Some(records) = ctx.get_mut::<Records>() {
+            records.increment();
+        }
+
+        self.on_entry(&buffer, ctx);
+
+        dispatch_errors(self, Some(input), ctx);
+        check_error(ctx)
     }
 
-    /// Passes each entry through to the mapper as a value, with the current
-    /// byte offset being provided as the key (this follows the implementation
-    /// provided in the Hadoop MapReduce Java interfaces, but it's unclear as
-    /// to whether this is the desired default behaviour here).
+    /// Passes each entry through to the mapper as a value, with the byte
+    /// offset of the entry being provided as the key (this follows the
+    /// implementation provided in the Hadoop MapReduce Java interfaces).
+    /// The line number of the entry is also made available in the `Context`.
+    ///
+    /// If a batch size is configured, entries are buffered and passed to the
+    /// mapper in batches instead, keyed by the offset of the first entry.
     #[inline]
     fn on_entry(&mut self, input: &[u8], ctx: &mut Context) {
-        let offset = {
-            // grabs the offset from the context, and shifts the offset
-            ctx.get_mut::<Offset>().unwrap().shift(input.len() + 2)
-        };
+        if let Some(line) = ctx.get_mut::<LineNumber>() {
+            line.increment();
+        }
 
-        self.mapper.map(offset, input, ctx);
+        // keys are a usize for compatibility, which may truncate on 32-bit
+        let offset = ctx.offset();
+
+        if self.batch.size.is_some() {
+            if self.batch.push(offset as usize, input) {
+                self.map_batch(ctx);
+            }
+            return;
+        }
+
+        let mapper = &mut self.mapper;
+        catch_panic(self.catch_panics, ctx, |ctx| {
+            mapper.map(offset as usize, input, ctx)
+        });
+    }
+
+    /// Passes any skipped failures through to the mapper.
+    #[inline]
+    fn on_error(&mut self, err: &EffluxError, ctx: &mut Context) {
+        self.mapper.on_error(err, ctx);
     }
 
     /// Finalizes the lifecycle by calling cleanup.
+    ///
+    /// Any partially filled batch is passed to the mapper beforehand.
     #[inline]
     fn on_end(&mut self, ctx: &mut Context) {
+        if self.batch.filled > 0 {
+            self.map_batch(ctx);
+        }
         self.mapper.cleanup(ctx);
     }
 }
@@ -95,8 +377,8 @@ where
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::context::Contextual;
-    use crate::io::Lifecycle;
+    use crate::context::{Contextual, Delimiters, ErrorPolicy};
+    use crate::io::{execute, Lifecycle};
 
     #[test]
     fn test_mapper_lifecycle() {
@@ -104,29 +386,284 @@ mod tests {
         let mut mapper = MapperLifecycle::new(TestMapper);
 
         mapper.on_start(&mut ctx);
+        mapper.on_entry(b"first_input_line", &mut ctx);
 
         {
-            let mut vet = |input: &[u8], expected: usize| {
-                mapper.on_entry(input, &mut ctx);
+            let pair = ctx.get::<TestPair>();
 
-                let pair = ctx.get::<TestPair>();
+            assert!(pair.is_some());
 
-                assert!(pair.is_some());
+            let pair = pair.unwrap();
 
-                let pair = pair.unwrap();
+            assert_eq!(pair.0, 0);
+            assert_eq!(pair.1, b"first_input_line");
+        }
 
-                assert_eq!(pair.0, expected);
-                assert_eq!(pair.1, input);
-            };
+        mapper.on_entry(b"second_input_line", &mut ctx);
 
-            vet(b"first_input_line", 18);
-            vet(b"second_input_line", 37);
-            vet(b"third_input_line", 55);
-        }
+        assert_eq!(ctx.line_number(), 2);
 
         mapper.on_end(&mut ctx);
     }
 
+    #[test]
+    fn test_mapper_offsets() {
+        let cases = vec![
+            (
+                &b"first_input_line\nsecond_input_line\nthird_input_line\n"[..],
+                17,
+                35,
+            ),
+            (
+                &b"first_input_line\r\nsecond_input_line\r\nthird_input_line\r\n"[..],
+                18,
+                37,
+            ),
+        ];
+
+        for (input, second, third) in cases {
+            let mut ctx = Context::buffered();
+
+            ctx.get_mut::<Configuration>()
+                .unwrap()
+                .insert("efflux.input.strip.cr", "true");
+
+            execute(
+                input,
+                MapperLifecycle::new(|key, _: &[u8], ctx: &mut Context| {
+                    let offset = ctx.offset();
+                    emit!(ctx, key, offset).unwrap();
+                }),
+                &mut ctx,
+            )
+            .unwrap();
+
+            let output = ctx.take_output();
+
+            assert!(output.iter().all(|(key, offset)| key == offset));
+
+            let keys = output.into_iter().map(|(key, _)| key).collect::<Vec<_>>();
+
+            assert_eq!(
+                keys,
+                vec![
+                    b"0".to_vec(),
+                    second.to_string().into_bytes(),
+                    third.to_string().into_bytes(),
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn test_identity_mapper() {
+        let mut ctx = Context::buffered();
+
+        execute(
+            &b"first\nsecond\n"[..],
+            MapperLifecycle::new(IdentityMapper),
+            &mut ctx,
+        )
+        .unwrap();
+
+        assert_eq!(
+            ctx.take_output(),
+            vec![
+                (b"0".to_vec(), b"first".to_vec()),
+                (b"6".to_vec(), b"second".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_identity_mapper_output() {
+        let cases = vec![
+            (
+                "offset",
+                DefaultOutput::Offset,
+                vec![(b"0".to_vec(), b"first".to_vec())],
+            ),
+            (
+                "value",
+                DefaultOutput::Value,
+                vec![(b"first".to_vec(), b"".to_vec())],
+            ),
+        ];
+
+        for (output, parsed, expected) in cases {
+            let mut ctx = Context::buffered();
+
+            ctx.get_mut::<Configuration>()
+                .unwrap()
+                .insert("efflux.map.default.output", output);
+
+            execute(
+                &b"first\n"[..],
+                MapperLifecycle::new(IdentityMapper),
+                &mut ctx,
+            )
+            .unwrap();
+
+            assert_eq!(ctx.take_output(), expected);
+
+            // the parsed output is internal state, which is never cleared
+            ctx.clear_user_state();
+
+            assert_eq!(ctx.get::<DefaultOutput>(), Some(&parsed));
+        }
+    }
+
+    #[test]
+    fn test_key_value_mapper() {
+        let mut ctx = Context::buffered();
+
+        ctx.get_mut::<Delimiters>().unwrap().set_input(b"=");
+
+        let input = &b"first=1\nsecond=2=3\nthird\n"[..];
+
+        execute(
+            input,
+            MapperLifecycle::new(KeyValue(TestKeyValue)),
+            &mut ctx,
+        )
+        .unwrap();
+
+        assert_eq!(
+            ctx.take_output(),
+            vec![
+                (b"1".to_vec(), b"first".to_vec()),
+                (b"2=3".to_vec(), b"second".to_vec()),
+                (b"".to_vec(), b"third".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_mapper_batching() {
+        let mut ctx = Context::buffered();
+
+        ctx.get_mut::<Configuration>()
+            .unwrap()
+            .insert("mapreduce.input.lineinputformat.linespermap", "2");
+
+        let input = &b"one\ntwo\nthree\nfour\nfive\n"[..];
+
+        execute(input, MapperLifecycle::new(TestBatcher), &mut ctx).unwrap();
+
+        assert_eq!(
+            ctx.take_output(),
+            vec![
+                (b"0".to_vec(), b"one,two".to_vec()),
+                (b"8".to_vec(), b"three,four".to_vec()),
+                (b"19".to_vec(), b"five".to_vec()),
+            ]
+        );
+        assert_eq!(ctx.line_number(), 5);
+    }
+
+    #[test]
+    fn test_mapper_whole_file() {
+        let mut ctx = Context::buffered();
+
+        ctx.get_mut::<Configuration>()
+            .unwrap()
+            .insert("efflux.input.whole.file", "true");
+
+        let input = &b"{\n  \"one\": 1\n}\n"[..];
+
+        execute(
+            input,
+            MapperLifecycle::new(|key, value: &[u8], ctx: &mut Context| {
+                let lines = value.split(|b| *b == b'\n').count();
+                ctx.write_fmt(key, lines).unwrap();
+            }),
+            &mut ctx,
+        )
+        .unwrap();
+
+        assert_eq!(ctx.take_output(), vec![(b"0".to_vec(), b"4".to_vec())]);
+        assert_eq!(ctx.record_count(), 1);
+    }
+
+    #[test]
+    fn test_mapper_setup_failure() {
+        let mut ctx = Context::buffered();
+
+        let result = execute(&b"input\n"[..], MapperLifecycle::new(TestFailure), &mut ctx);
+        let err = result.unwrap_err();
+
+        assert_eq!(err.to_string(), "failed to setup mapper: missing lookup");
+        assert_eq!(ctx.take_output(), vec![]);
+    }
+
+    #[test]
+    fn test_mapper_catching_panics() {
+        let cases = vec![(ErrorPolicy::Skip, true), (ErrorPolicy::Fail, false)];
+
+        for (policy, success) in cases {
+            let mut ctx = Context::buffered();
+
+            ctx.insert(policy);
+            ctx.get_mut::<Configuration>()
+                .unwrap()
+                .insert("efflux.task.catch.panics", "true");
+
+            let input = &b"bad\ngood\n"[..];
+            let result = execute(input, MapperLifecycle::new(TestPanic), &mut ctx);
+
+            assert_eq!(result.is_ok(), success);
+
+            if success {
+                assert_eq!(ctx.take_output(), vec![(b"4".to_vec(), b"good".to_vec())]);
+            } else {
+                let err = result.unwrap_err();
+                assert_eq!(
+                    err.to_string(),
+                    "panicked whilst handling record: bad value"
+                );
+            }
+        }
+    }
+
+    struct TestBatcher;
+
+    impl Mapper for TestBatcher {
+        fn map_batch(&mut self, key: usize, lines: &[Vec<u8>], ctx: &mut Context) {
+            ctx.write(key.to_string(), lines.join(&b","[..])).unwrap();
+        }
+    }
+
+    struct TestKeyValue;
+
+    impl KeyValueMapper for TestKeyValue {
+        fn map(&mut self, key: &[u8], value: &[u8], ctx: &mut Context) {
+            ctx.write(value, key).unwrap();
+        }
+    }
+
+    struct TestPanic;
+
+    impl Mapper for TestPanic {
+        fn map(&mut self, key: usize, value: &[u8], ctx: &mut Context) {
+            if value == b"bad" {
+                panic!("bad value");
+            }
+            ctx.write(key.to_string().as_bytes(), value).unwrap();
+        }
+
+        fn on_error(&mut self, err: &EffluxError, _ctx: &mut Context) {
+            assert!(matches!(err, EffluxError::Panic(_)));
+        }
+    }
+
+    struct TestFailure;
+
+    impl Mapper for TestFailure {
+        fn try_setup(&mut self, _ctx: &mut Context) -> Result<(), Box<dyn Error>> {
+            Err("missing lookup".into())
+        }
+    }
+
     struct TestPair(usize, Vec<u8>);
 
     impl Contextual for TestPair {}
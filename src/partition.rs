@@ -0,0 +1,86 @@
+//! Exposed structures based on the partitioning of stage output.
+//!
+//! This module offers the `Partitioner` trait, which determines the reducer
+//! each key of the map output is sent to. Hadoop performs partitioning itself,
+//! so this is primarily useful to simulate the shuffle locally (e.g. via the
+//! `run_local_partitioned` function) when testing partition-sensitive jobs.
+//!
+//! The `HashPartitioner` matches the default partitioning used by Hadoop.
+
+/// Trait to represent the partitioning of keys across reducers.
+pub trait Partitioner {
+    /// Returns the partition of a key, in the range `0..num_partitions`.
+    fn partition(&self, key: &[u8], num_partitions: usize) -> usize;
+}
+
+/// Enables raw functions to act as `Partitioner` types.
+impl<P> Partitioner for P
+where
+    P: Fn(&[u8], usize) -> usize,
+{
+    /// Partitioning handler by passing through the key to the inner closure.
+    #[inline]
+    fn partition(&self, key: &[u8], num_partitions: usize) -> usize {
+        self(key, num_partitions)
+    }
+}
+
+/// Partitioner matching the default `HashPartitioner` of Hadoop.
+///
+/// Keys are hashed in the same way as a Hadoop `Text` key, and partitions are
+/// assigned using `(hash & Integer.MAX_VALUE) % numReduceTasks`, so keys will
+/// be sent to the same partition as they would be in a Hadoop Streaming job.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct HashPartitioner;
+
+impl HashPartitioner {
+    /// Hashes a key in the same way as `Text::hashCode` in Hadoop.
+    ///
+    /// This is `WritableComparator::hashBytes`, which (unlike the hashing
+    /// of a Java `String`) starts from `1` and uses signed bytes.
+    #[inline]
+    pub fn hash(key: &[u8]) -> i32 {
+        key.iter().fold(1i32, |hash, b| {
+            hash.wrapping_mul(31).wrapping_add(*b as i8 as i32)
+        })
+    }
+}
+
+/// `Partitioner` implementation to partition keys using their hash.
+impl Partitioner for HashPartitioner {
+    #[inline]
+    fn partition(&self, key: &[u8], num_partitions: usize) -> usize {
+        (Self::hash(key) & i32::MAX) as usize % num_partitions.max(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hadoop_hashing() {
+        let cases: Vec<(&[u8], i32, usize)> = vec![
+            (b"", 1, 1),
+            (b"\xff", 30, 0),
+            (b"hello", 127_791_473, 2),
+            (b"partition", -1_996_323_831, 2),
+            (b"efflux", -418_574_005, 1),
+        ];
+
+        for (key, hash, partition) in cases {
+            assert_eq!(HashPartitioner::hash(key), hash);
+            assert_eq!(HashPartitioner.partition(key, 3), partition);
+        }
+
+        assert_eq!(HashPartitioner.partition(b"efflux", 7), 6);
+        assert_eq!(HashPartitioner.partition(b"efflux", 0), 0);
+    }
+
+    #[test]
+    fn test_closure_partitioning() {
+        let partitioner = |key: &[u8], num_partitions: usize| key.len() % num_partitions;
+
+        assert_eq!(partitioner.partition(b"four", 3), 1);
+    }
+}
@@ -3,8 +3,28 @@
 //! This module offers the `Reducer` trait, which allows a developer
 //! to easily create a reduction stage due to the sane defaults. Also
 //! offered is the `ReducerLifecycle` binding for use as an IO stage.
-use crate::context::{Context, Delimiters};
-use crate::io::Lifecycle;
+use std::cmp::Ordering;
+use std::error::Error;
+use std::io;
+use std::iter::Sum;
+use std::marker::PhantomData;
+use std::mem;
+use std::slice;
+use std::str::FromStr;
+
+use crate::context::{
+    split_fields_checked, Configuration, Context, ErrorPolicy, Records, ValueOrder,
+};
+use crate::error::EffluxError;
+use crate::io::{catch_panic, catches_panics, dispatch_errors, Input, Lifecycle};
+use crate::spill::{Merge, Spill};
+use crate::typed;
+
+/// Comparator type used to order values within a group.
+pub type ValueComparator = fn(&[u8], &[u8]) -> Ordering;
+
+/// Comparator type used to determine whether two keys share a group.
+pub type GroupComparator = Box<dyn Fn(&[u8], &[u8]) -> bool>;
 
 /// Trait to represent the reduction stage of MapReduce.
 ///
@@ -15,21 +35,396 @@ pub trait Reducer {
     /// Setup handler for the current `Reducer`.
     fn setup(&mut self, _ctx: &mut Context) {}
 
+    /// Fallible setup handler for the current `Reducer`.
+    ///
+    /// Any error returned will abort the stage before any input is read,
+    /// exiting with a logged message when run via the `efflux` runners. The
+    /// default implementation simply delegates to the infallible `setup`.
+    fn try_setup(&mut self, ctx: &mut Context) -> Result<(), Box<dyn Error>> {
+        self.setup(ctx);
+        Ok(())
+    }
+
     /// Reduction handler for the current `Reducer`.
     ///
     /// The default implementation of this handler will emit each value against
     /// the key in the order they were received. This is typically the stage of
     /// interest for many MapReduce developers.
+    ///
+    /// Records without a separator (e.g. `key`) are provided as an empty value,
+    /// just like records with a trailing separator (e.g. `key\t`), as in Hadoop.
+    /// To tell them apart, implement `reduce_streaming` and read each value via
+    /// `Values::next_value_checked` instead.
     fn reduce(&mut self, key: &[u8], values: &[&[u8]], ctx: &mut Context) {
         for value in values {
-            ctx.write(key, value);
+            // errors are surfaced by the lifecycle
+            if ctx.write(key, value).is_err() {
+                return;
+            }
         }
     }
 
+    /// Streaming reduction handler for the current `Reducer`.
+    ///
+    /// Values are read lazily from the input as they're pulled from `values`,
+    /// so a group is never held in memory in full. Any values left unread when
+    /// this handler returns are skipped before the next group begins.
+    ///
+    /// The default implementation collects all values and passes them through
+    /// to `reduce`, so this only needs to be implemented for very large groups.
+    /// As `reduce` receives a slice, collected values are always held in memory
+    /// in full; they're bounded only by `efflux.reduce.buffer.max.bytes`, and
+    /// are never spilled to disk.
+    fn reduce_streaming(&mut self, key: &[u8], values: &mut Values<'_, '_>, ctx: &mut Context) {
+        values.collect_with(|values| self.reduce(key, values, ctx));
+    }
+
+    /// Comparator used to determine whether two keys belong to the same group.
+    ///
+    /// This can be used when the natural key differs from the grouping key (e.g.
+    /// a composite key in which only the first field should be grouped on). The
+    /// first key of a group is the key passed to the reduction handlers. Input
+    /// must still be sorted consistently with this comparator, as groups are
+    /// contiguous. The default implementation returns `None` to group keys by
+    /// exact byte equality.
+    ///
+    /// This is called once after `setup`, so the comparator can capture any
+    /// state of the `Reducer` (e.g. a configured number of fields). It's held
+    /// separately from the `Reducer`, as keys are still compared whilst values
+    /// are streamed to `reduce_streaming`.
+    fn group_comparator(&self) -> Option<GroupComparator> {
+        None
+    }
+
+    /// Comparator used to order the values of each group before reduction.
+    ///
+    /// When provided, this takes precedence over any `ValueOrder` configured
+    /// for the job. Sorting values requires buffering each group in memory, so
+    /// the default implementation returns `None` to keep values in the order
+    /// they were received.
+    fn value_comparator(&self) -> Option<ValueComparator> {
+        None
+    }
+
+    /// Error handler for the current `Reducer`.
+    ///
+    /// This is called for each failure which is skipped under the current
+    /// `ErrorPolicy` (such as a record which could not be read), allowing
+    /// a `Reducer` to track failures. The default implementation is a no-op.
+    fn on_error(&mut self, _err: &EffluxError, _ctx: &mut Context) {}
+
     /// Cleanup handler for the current `Reducer`.
     fn cleanup(&mut self, _ctx: &mut Context) {}
 }
 
+/// Lazy stream of values for a single key of a reduction.
+///
+/// Values are read from the stage input as they're requested, rather than
+/// being buffered ahead of time (unless values are being sorted). As each
+/// value is borrowed from the buffer of the input, this cannot implement
+/// `Iterator`; values should be pulled using `while let Some(value) =
+/// values.next_value()` instead.
+///
+/// Common aggregations are offered directly (e.g. `values.sum::<i64>()`),
+/// and values can be parsed lazily via `parse`, which does implement the
+/// `Iterator` trait. Parse failures are handled using the `ErrorPolicy`.
+pub struct Values<'a, 'i> {
+    source: Source<'a, 'i>,
+    count: u64,
+    done: bool,
+    error: Option<io::Error>,
+    policy: ErrorPolicy,
+    skipped: Vec<EffluxError>,
+    buffer: Option<&'a mut GroupBuffer>,
+}
+
+/// Source of the values within a `Values` stream.
+enum Source<'a, 'i> {
+    Input {
+        input: &'a mut Input<'i>,
+        key: &'a [u8],
+        grouping: &'a Grouping<'a>,
+    },
+    Buffer(GroupValues<'a>),
+    Merge(Merge<'a, GroupValues<'a>>),
+}
+
+impl<'a, 'i> Values<'a, 'i> {
+    /// Constructs a new `Values` reading a group from an `Input`.
+    fn new(input: &'a mut Input<'i>, key: &'a [u8], grouping: &'a Grouping<'a>) -> Self {
+        Self::with_source(
+            Source::Input {
+                input,
+                key,
+                grouping,
+            },
+            grouping.policy,
+        )
+    }
+
+    /// Constructs a new `Values` reading a group from a buffer.
+    fn buffered(values: GroupValues<'a>, policy: ErrorPolicy) -> Self {
+        Self::with_source(Source::Buffer(values), policy)
+    }
+
+    /// Constructs a new `Values` merging a group from spilled runs.
+    fn merged(merge: Merge<'a, GroupValues<'a>>, policy: ErrorPolicy) -> Self {
+        Self::with_source(Source::Merge(merge), policy)
+    }
+
+    /// Constructs a new `Values` from a `Source`.
+    fn with_source(source: Source<'a, 'i>, policy: ErrorPolicy) -> Self {
+        Self {
+            source,
+            count: 0,
+            done: false,
+            error: None,
+            policy,
+            skipped: Vec::new(),
+            buffer: None,
+        }
+    }
+
+    /// Parses the remaining values lazily via `FromStr`.
+    ///
+    /// Values which fail to parse are handled using the `ErrorPolicy`; they
+    /// are either skipped, counted and skipped, or end the group with an error
+    /// which is then surfaced by the lifecycle. Skipped failures are passed to
+    /// the `on_error` handler of the `Reducer`.
+    pub fn parse<T>(&mut self) -> ParsedValues<'_, 'a, 'i, T>
+    where
+        T: FromStr,
+    {
+        ParsedValues {
+            values: self,
+            marker: PhantomData,
+        }
+    }
+
+    /// Sums the remaining values, after parsing them via `FromStr`.
+    pub fn sum<T>(&mut self) -> T
+    where
+        T: FromStr + Sum<T>,
+    {
+        self.parse().sum()
+    }
+
+    /// Finds the maximum of the remaining values, after parsing them via `FromStr`.
+    pub fn max<T>(&mut self) -> Option<T>
+    where
+        T: FromStr + Ord,
+    {
+        self.parse().max()
+    }
+
+    /// Counts the remaining values, without parsing them.
+    pub fn count(&mut self) -> usize {
+        let mut count = 0;
+        while self.next_value().is_some() {
+            count += 1;
+        }
+        count
+    }
+
+    /// Collects the remaining values, before passing them through to a handler.
+    ///
+    /// Values are buffered into the attached `GroupBuffer`, which is reused
+    /// across groups to avoid allocating each value. Values which are already
+    /// buffered (e.g. after sorting) are passed through without being copied.
+    /// Failures end the group, and are then surfaced by the lifecycle.
+    pub(crate) fn collect_with<F>(&mut self, handler: F)
+    where
+        F: FnOnce(&[&[u8]]),
+    {
+        if let Source::Buffer(values) = &mut self.source {
+            let values = values.collect::<Vec<_>>();
+            return handler(&values);
+        }
+
+        let mut owned = GroupBuffer::default();
+        let buffer = match self.buffer.take() {
+            Some(buffer) => buffer,
+            None => &mut owned,
+        };
+
+        buffer.clear();
+        while let Some(value) = self.next_value() {
+            if let Err(err) = buffer.push(value) {
+                buffer.clear();
+                self.error = Some(err);
+                self.done = true;
+                return;
+            }
+        }
+
+        handler(&buffer.group.values().collect::<Vec<_>>());
+    }
+
+    /// Passes any skipped failures through to a `Context`, returning any error.
+    fn finish(&mut self, ctx: &mut Context) -> Option<io::Error> {
+        ctx.skipped_mut().append(&mut self.skipped);
+        self.error.take()
+    }
+
+    /// Retrieves the next value for the current key, if any.
+    ///
+    /// Once this returns `None` the group has ended, and all subsequent calls
+    /// will also return `None`. Read errors also end the group, and are then
+    /// surfaced by the lifecycle. Records without a separator are provided as
+    /// an empty value; see `next_value_checked` to tell these apart.
+    pub fn next_value(&mut self) -> Option<&[u8]> {
+        self.next_value_checked()
+            .map(|value| value.unwrap_or_default())
+    }
+
+    /// Retrieves the next value for the current key, if any, noting whether
+    /// the record contained a separator.
+    ///
+    /// Records without a separator (e.g. `key`) are returned as `Some(None)`,
+    /// whereas records with a trailing separator (e.g. `key\t`) are returned as
+    /// `Some(Some(b""))`. This distinction is only available when values are
+    /// streamed from the input; values buffered beforehand (e.g. for sorting)
+    /// are always returned as if a separator was present.
+    pub fn next_value_checked(&mut self) -> Option<Option<&[u8]>> {
+        if self.done {
+            return None;
+        }
+
+        let (input, group, grouping) = match &mut self.source {
+            Source::Buffer(values) => return values.next().map(Some),
+            Source::Merge(merge) => {
+                return match merge.next() {
+                    Ok(value) => value.map(Some),
+                    Err(err) => {
+                        self.error = Some(err);
+                        self.done = true;
+                        None
+                    }
+                };
+            }
+            Source::Input {
+                input,
+                key,
+                grouping,
+            } => (input, *key, *grouping),
+        };
+
+        // locate the value in the next record, if it's in this group
+        let offset = match input.peek_record() {
+            Ok(Some(record)) => {
+                let (key, value) = grouping.split(record);
+
+                if grouping.eq(group, key) {
+                    Some(value.map(|value| record.len() - value.len()))
+                } else {
+                    None
+                }
+            }
+            Ok(None) => None,
+            Err(err) => {
+                self.error = Some(err);
+                None
+            }
+        };
+
+        // the group has ended
+        let offset = match offset {
+            Some(offset) => offset,
+            None => {
+                self.done = true;
+                return None;
+            }
+        };
+
+        self.count += 1;
+
+        // consume the peeked record, which cannot fail
+        match input.next_record() {
+            Ok(Some(record)) => Some(offset.map(|offset| &record[offset..])),
+            _ => None,
+        }
+    }
+}
+
+/// Iterator over the values of a group, parsed lazily via `FromStr`.
+///
+/// This is created via `Values::parse`; see there for how parse failures
+/// are handled. Iteration ends at the end of the group, or on failure.
+pub struct ParsedValues<'v, 'a, 'i, T> {
+    values: &'v mut Values<'a, 'i>,
+    marker: PhantomData<T>,
+}
+
+/// `Iterator` implementation to parse values from the inner `Values`.
+impl<T> Iterator for ParsedValues<'_, '_, '_, T>
+where
+    T: FromStr,
+{
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        // the skipped list is taken, as each value borrows the stream
+        let policy = self.values.policy;
+        let mut skipped = mem::take(&mut self.values.skipped);
+
+        let parsed = loop {
+            let value = match self.values.next_value() {
+                Some(value) => value,
+                None => break None,
+            };
+
+            match typed::parse(value, policy, &mut skipped) {
+                Ok(Some(parsed)) => break Some(parsed),
+                Ok(None) => continue,
+                Err(err) => {
+                    self.values.error = Some(err);
+                    self.values.done = true;
+                    break None;
+                }
+            }
+        };
+
+        self.values.skipped = skipped;
+        parsed
+    }
+}
+
+/// Grouping rules used to separate reduce input into groups.
+struct Grouping<'g> {
+    delim: Vec<u8>,
+    fields: usize,
+    policy: ErrorPolicy,
+    comparator: Option<&'g GroupComparator>,
+}
+
+impl<'g> Grouping<'g> {
+    /// Constructs a new `Grouping` from a `Context` and group comparator.
+    fn new(ctx: &Context, comparator: Option<&'g GroupComparator>) -> Self {
+        let delim = ctx.delimiters();
+
+        Self {
+            delim: delim.input().to_vec(),
+            fields: delim.key_fields(),
+            policy: ctx.get::<ErrorPolicy>().copied().unwrap_or_default(),
+            comparator,
+        }
+    }
+
+    /// Determines whether two keys belong to the same group.
+    #[inline]
+    fn eq(&self, left: &[u8], right: &[u8]) -> bool {
+        group_eq(self.comparator, left, right)
+    }
+
+    /// Splits a record into a key/value pair.
+    ///
+    /// Records without a separator have no value, rather than an empty value.
+    #[inline]
+    fn split<'r>(&self, record: &'r [u8]) -> (&'r [u8], Option<&'r [u8]>) {
+        split_fields_checked(record, &self.delim, self.fields)
+    }
+}
+
 /// Enables raw functions to act as `Reducer` types.
 impl<R> Reducer for R
 where
@@ -42,14 +437,169 @@ where
     }
 }
 
+/// `Reducer` which emits each value unchanged, against its key.
+///
+/// This simply uses the default `Reducer` implementation, and exists to
+/// avoid declaring an empty `Reducer` for jobs used purely for sorting.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct IdentityReducer;
+
+impl Reducer for IdentityReducer {}
+
+/// Buffered group of values for a single key of a reduction.
+///
+/// Values are stored contiguously in a single arena, with the range of each
+/// value recorded separately; sorting only moves the ranges. Resetting a group
+/// retains the allocated buffers, so a single group can be reused for every
+/// key of a reduction without reallocating.
+#[derive(Default)]
+struct Group {
+    active: bool,
+    key: Vec<u8>,
+    arena: Vec<u8>,
+    ranges: Vec<(usize, usize)>,
+}
+
+impl Group {
+    /// Determines whether the group has been started with a key.
+    #[inline]
+    fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// Returns the key of the group.
+    #[inline]
+    fn key(&self) -> &[u8] {
+        &self.key
+    }
+
+    /// Returns the size of all values buffered in the group.
+    #[inline]
+    fn buffered(&self) -> usize {
+        self.arena.len()
+    }
+
+    /// Returns an iterator over the values of the group.
+    #[inline]
+    fn values(&self) -> GroupValues<'_> {
+        GroupValues {
+            arena: &self.arena,
+            ranges: self.ranges.iter(),
+        }
+    }
+
+    /// Adds a value to the group, returning the new buffered size.
+    #[inline]
+    fn push(&mut self, value: &[u8]) -> usize {
+        let start = self.arena.len();
+        self.arena.extend_from_slice(value);
+        self.ranges.push((start, self.arena.len()));
+        self.arena.len()
+    }
+
+    /// Removes all values from the group, retaining the key.
+    #[inline]
+    fn clear(&mut self) {
+        self.arena.clear();
+        self.ranges.clear();
+    }
+
+    /// Starts a new group for a key, removing all existing values.
+    fn reset(&mut self, key: &[u8]) {
+        self.active = true;
+        self.key.clear();
+        self.key.extend(key);
+        self.clear();
+    }
+
+    /// Sorts the values of the group using a comparator.
+    fn sort_by<F>(&mut self, mut compare: F)
+    where
+        F: FnMut(&[u8], &[u8]) -> Ordering,
+    {
+        let arena = &self.arena;
+        self.ranges
+            .sort_by(|&(ls, le), &(rs, re)| compare(&arena[ls..le], &arena[rs..re]));
+    }
+
+    /// Passes the group through to a `Reducer`, after sorting if enabled.
+    fn reduce<R>(&mut self, reducer: &mut R, ctx: &mut Context)
+    where
+        R: Reducer,
+    {
+        // order the values in the group, if enabled
+        if let Some(sorting) = ValueSort::new(reducer, ctx) {
+            self.sort_by(|left, right| sorting.compare(left, right));
+        }
+
+        // construct a references list into the arena
+        let values = self.values().collect::<Vec<_>>();
+
+        reducer.reduce(&self.key, &values, ctx);
+    }
+}
+
+/// Buffer used to collect the values of a group for `Reducer::reduce`.
+///
+/// As `Reducer::reduce` requires all values of a group at once, values are
+/// always held in memory; exceeding the buffer limit fails the group.
+#[derive(Default)]
+struct GroupBuffer {
+    group: Group,
+    limit: Option<usize>,
+}
+
+impl GroupBuffer {
+    /// Removes all values from the buffer.
+    fn clear(&mut self) {
+        self.group.clear();
+    }
+
+    /// Adds a value to the buffer, failing once beyond the limit.
+    fn push(&mut self, value: &[u8]) -> io::Result<()> {
+        check_limit(self.group.push(value), self.limit)
+    }
+}
+
+/// Iterator over the values buffered in a `Group`.
+#[derive(Clone)]
+pub(crate) struct GroupValues<'a> {
+    arena: &'a [u8],
+    ranges: slice::Iter<'a, (usize, usize)>,
+}
+
+impl<'a> Iterator for GroupValues<'a> {
+    type Item = &'a [u8];
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.ranges
+            .next()
+            .map(|&(start, end)| &self.arena[start..end])
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.ranges.size_hint()
+    }
+}
+
 /// Lifecycle structure to represent a reduction.
-pub(crate) struct ReducerLifecycle<R>
+///
+/// This is what the `efflux` runners execute for a `Reducer`. It can also be
+/// driven manually via the `Lifecycle` hooks, e.g. to inspect the size of the
+/// buffered group (via `buffered_bytes`) in the tests of a `Reducer`.
+pub struct ReducerLifecycle<R>
 where
     R: Reducer,
 {
-    on: bool,
-    key: Vec<u8>,
-    values: Vec<Vec<u8>>,
+    group: Group,
+    buffer: GroupBuffer,
+    limit: Option<usize>,
+    spill: Spill,
+    threshold: Option<usize>,
+    catch_panics: bool,
+    comparator: Option<GroupComparator>,
     reducer: R,
 }
 
@@ -59,14 +609,50 @@ where
     R: Reducer,
 {
     /// Constructs a new `ReducerLifecycle` instance.
-    pub(crate) fn new(reducer: R) -> Self {
+    pub fn new(reducer: R) -> Self {
         Self {
             reducer,
-            on: false,
-            key: Vec::new(),
-            values: Vec::new(),
+            group: Group::default(),
+            buffer: GroupBuffer::default(),
+            limit: None,
+            spill: Spill::default(),
+            threshold: None,
+            catch_panics: false,
+            comparator: None,
         }
     }
+
+    /// Passes the buffered group through to the reducer.
+    fn reduce_group(&mut self, ctx: &mut Context) {
+        let (group, reducer) = (&mut self.group, &mut self.reducer);
+        catch_panic(self.catch_panics, ctx, |ctx| group.reduce(reducer, ctx));
+    }
+
+    /// Returns the size of the values currently buffered for a group.
+    ///
+    /// Values are buffered when they need to be sorted, when they're collected
+    /// for `Reducer::reduce`, or when entries are provided individually via
+    /// `on_entry`. Values which have been spilled to disk are not included.
+    ///
+    /// ```rust
+    /// use efflux::io::Lifecycle;
+    /// use efflux::prelude::*;
+    /// use efflux::reducer::ReducerLifecycle;
+    ///
+    /// let reducer = |_key: &[u8], _values: &[&[u8]], _ctx: &mut Context| {};
+    ///
+    /// let mut ctx = Context::new();
+    /// let mut lifecycle = ReducerLifecycle::new(reducer);
+    ///
+    /// lifecycle.on_start(&mut ctx);
+    /// lifecycle.on_entry(b"key\tone", &mut ctx);
+    /// lifecycle.on_entry(b"key\ttwo", &mut ctx);
+    ///
+    /// assert_eq!(lifecycle.buffered_bytes(), 6);
+    /// ```
+    pub fn buffered_bytes(&self) -> usize {
+        self.group.buffered() + self.buffer.group.buffered()
+    }
 }
 
 /// `Lifecycle` implementation for the reduction stage.
@@ -77,7 +663,137 @@ where
     /// Creates all required state for the lifecycle.
     #[inline]
     fn on_start(&mut self, ctx: &mut Context) {
-        self.reducer.setup(ctx);
+        if let Some(conf) = ctx.get::<Configuration>() {
+            self.limit = bytes(conf, "efflux.reduce.buffer.max.bytes");
+            self.threshold = bytes(conf, "efflux.reduce.spill.bytes");
+        }
+
+        self.buffer.limit = self.limit;
+
+        self.catch_panics = catches_panics(ctx);
+
+        if let Err(err) = self.reducer.try_setup(ctx) {
+            let msg = format!("failed to setup reducer: {}", err);
+            ctx.set_error(io::Error::other(msg));
+        }
+
+        self.comparator = self.reducer.group_comparator();
+    }
+
+    /// Streams each group of values directly from the input into the reducer.
+    ///
+    /// The key of the next record determines the next group, and values are
+    /// then read lazily by the `Reducer` until the key changes. If values are
+    /// to be sorted, each group is buffered and sorted before reduction. When
+    /// `efflux.reduce.spill.bytes` is set, values buffered for sorting beyond
+    /// that size are spilled to temporary files as sorted runs, which are read
+    /// back and merged as the values are pulled by `reduce_streaming`.
+    ///
+    /// Values are also buffered when collected for `Reducer::reduce`, which is
+    /// the case for any `Reducer` without a custom `reduce_streaming`. These
+    /// must all be held in memory at once, so they're never spilled and can't
+    /// be bounded by spilling. If `efflux.reduce.buffer.max.bytes` is set,
+    /// exceeding that size fails the stage.
+    fn on_stream(&mut self, input: &mut Input<'_>, ctx: &mut Context) -> io::Result<()> {
+        // copy the grouping rules, as the context is passed to the reducer
+        let comparator = self.comparator.take();
+        let grouping = Grouping::new(ctx, comparator.as_ref());
+
+        // check whether groups need to be buffered for sorting
+        let sorting = ValueSort::new(&self.reducer, ctx);
+
+        // the key is held separately, as values borrow it whilst buffering
+        let mut key = Vec::new();
+
+        // the reducer is borrowed separately, to allow catching panics
+        let catch_panics = self.catch_panics;
+
+        // the next record determines the key of the next group
+        while let Some(record) = input.peek_record()? {
+            key.clear();
+            key.extend(grouping.split(record).0);
+
+            let mut values = Values::new(&mut *input, &key, &grouping);
+
+            if let Some(sorting) = &sorting {
+                let compare = |left: &[u8], right: &[u8]| sorting.compare(left, right);
+
+                // buffer the entire group, to allow sorting
+                self.group.clear();
+                while let Some(value) = values.next_value() {
+                    let buffered = self.group.push(value);
+
+                    // spill the buffer as a sorted run once too large
+                    if self.threshold.is_some_and(|threshold| buffered > threshold) {
+                        self.group.sort_by(compare);
+                        self.spill.write(self.group.values(), &compare)?;
+                        self.group.clear();
+                        continue;
+                    }
+
+                    check_limit(buffered, self.limit)?;
+                }
+
+                self.group.sort_by(compare);
+
+                if self.spill.is_empty() {
+                    let mut sorted = Values::buffered(self.group.values(), grouping.policy);
+                    let reducer = &mut self.reducer;
+                    catch_panic(catch_panics, ctx, |ctx| {
+                        reducer.reduce_streaming(&key, &mut sorted, ctx)
+                    });
+
+                    if let Some(err) = sorted.finish(ctx) {
+                        return Err(err);
+                    }
+                } else {
+                    let merge = Merge::new(&mut self.spill, self.group.values(), &compare);
+                    let mut merged = Values::merged(merge, grouping.policy);
+
+                    merged.buffer = Some(&mut self.buffer);
+
+                    let reducer = &mut self.reducer;
+                    catch_panic(catch_panics, ctx, |ctx| {
+                        reducer.reduce_streaming(&key, &mut merged, ctx)
+                    });
+
+                    let error = merged.finish(ctx);
+
+                    // remove the spill files before the next group
+                    self.spill.clear();
+
+                    if let Some(err) = error {
+                        return Err(err);
+                    }
+                }
+            } else {
+                values.buffer = Some(&mut self.buffer);
+
+                let reducer = &mut self.reducer;
+                catch_panic(catch_panics, ctx, |ctx| {
+                    reducer.reduce_streaming(&key, &mut values, ctx)
+                });
+
+                // skip any values not consumed by the reducer
+                while values.next_value().is_some() {}
+            }
+
+            if let Some(records) = ctx.get_mut::<Records>() {
+                records.add(values.count);
+            }
+
+            if let Some(err) = values.finish(ctx).or_else(|| ctx.take_error()) {
+                return Err(err);
+            }
+
+            dispatch_errors(self, Some(&mut *input), ctx);
+        }
+
+        // the comparator is held by the lifecycle outside of streaming
+        drop(grouping);
+        self.comparator = comparator;
+
+        Ok(())
     }
 
     /// Processes each entry by buffering sequential key entries into the
@@ -86,71 +802,116 @@ where
     fn on_entry(&mut self, input: &[u8], ctx: &mut Context) {
         let (key, value) = {
             // grab the delimiters from the context
-            let delim = ctx.get::<Delimiters>().unwrap();
-
-            // search (quickly) for the input byte delimiter
-            match twoway::find_bytes(&input, delim.input()) {
-                Some(n) if n < input.len() => {
-                    // split the input at the given index when applicable
-                    (&input[..n], &input[n + delim.input().len()..])
-                }
+            let delim = ctx.delimiters();
 
-                // otherwise the input is the key
-                _ => (&input[..], &b""[..]),
-            }
+            // split the key after the configured number of fields
+            split_fields_checked(input, delim.input(), delim.key_fields())
         };
 
         // first key
-        if !self.on {
-            self.on = true;
-            self.key.clear();
-            self.key.extend(key);
-        }
-
-        // append to buffer
-        if self.key == key {
-            self.values.push(value.to_vec());
-            return;
+        if !self.group.is_active() {
+            self.group.reset(key);
         }
 
-        // construct a references list to avoid exposing vecs
-        let mut values = Vec::with_capacity(self.values.len());
-        for value in &self.values {
-            values.push(value.as_slice());
+        // reduce the prior group once the key changes
+        if !group_eq(self.comparator.as_ref(), self.group.key(), key) {
+            self.reduce_group(ctx);
+            self.group.reset(key);
         }
 
-        // reduce the key and value group
-        self.reducer.reduce(&self.key, &values, ctx);
+        // append to buffer, with missing values provided as empty
+        self.group.push(value.unwrap_or_default());
 
-        // reset the key
-        self.key.clear();
-        self.key.extend(key);
+        if let Err(err) = check_limit(self.group.buffered(), self.limit) {
+            ctx.set_error(err);
+        }
+    }
 
-        // drain the internal buffer
-        self.values.clear();
-        self.values.push(value.to_vec());
+    /// Passes any skipped failures through to the reducer.
+    #[inline]
+    fn on_error(&mut self, err: &EffluxError, ctx: &mut Context) {
+        self.reducer.on_error(err, ctx);
     }
 
     /// Finalizes the lifecycle by emitting any leftover pairs.
     #[inline]
     fn on_end(&mut self, ctx: &mut Context) {
-        // construct a references list to avoid exposing vecs
-        let mut values = Vec::with_capacity(self.values.len());
-        for value in &self.values {
-            values.push(value.as_slice());
+        // reduce the last batch of buffered values
+        if self.group.is_active() {
+            self.reduce_group(ctx);
         }
 
-        // reduce the last batche of values
-        self.reducer.reduce(&self.key, &values, ctx);
         self.reducer.cleanup(ctx);
     }
 }
 
+/// Compares two keys using a group comparator, or byte equality without one.
+#[inline]
+fn group_eq(comparator: Option<&GroupComparator>, left: &[u8], right: &[u8]) -> bool {
+    match comparator {
+        Some(comparator) => comparator(left, right),
+        None => left == right,
+    }
+}
+
+/// Reads a size in bytes from a `Configuration`, ignoring invalid sizes.
+fn bytes(conf: &Configuration, key: &str) -> Option<usize> {
+    conf.get_i64(key)
+        .filter(|bytes| *bytes > 0)
+        .map(|bytes| bytes as usize)
+}
+
+/// Checks the size of a buffered group against the buffer limit, if any.
+fn check_limit(buffered: usize, limit: Option<usize>) -> io::Result<()> {
+    match limit {
+        Some(limit) if buffered > limit => Err(io::Error::other(format!(
+            "reduce group exceeded buffer limit of {} bytes",
+            limit
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Sort rules used to order the values of each group.
+enum ValueSort {
+    Comparator(ValueComparator),
+    Order(ValueOrder),
+}
+
+impl ValueSort {
+    /// Constructs a new `ValueSort` for a `Reducer`, if sorting is enabled.
+    ///
+    /// The comparator of the `Reducer` takes precedence over the `ValueOrder`
+    /// from the `Context`, which is copied as the context is later borrowed.
+    fn new<R>(reducer: &R, ctx: &Context) -> Option<Self>
+    where
+        R: Reducer,
+    {
+        if let Some(comparator) = reducer.value_comparator() {
+            return Some(ValueSort::Comparator(comparator));
+        }
+
+        ctx.get::<ValueOrder>()
+            .filter(|order| order.is_enabled())
+            .map(|order| ValueSort::Order(order.clone()))
+    }
+
+    /// Compares two values using the sort rules.
+    #[inline]
+    fn compare(&self, left: &[u8], right: &[u8]) -> Ordering {
+        match self {
+            ValueSort::Comparator(comparator) => comparator(left, right),
+            ValueSort::Order(order) => order.compare(left, right),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::context::Contextual;
-    use crate::io::Lifecycle;
+    use crate::context::{Contextual, Delimiters};
+    use crate::io::{execute, Lifecycle};
+    use crate::local::Buffer;
 
     #[test]
     fn test_reducer_lifecycle() {
@@ -189,6 +950,35 @@ mod tests {
         assert_eq!(pair.1, vec![&b"one"[..], b"two", b"three"]);
     }
 
+    #[test]
+    fn test_group_reuse() {
+        let mut group = Group::default();
+
+        assert!(!group.is_active());
+
+        group.reset(b"first");
+        group.push(b"one");
+        group.push(b"three");
+
+        let capacity = group.arena.capacity();
+
+        assert_eq!(
+            group.values().collect::<Vec<_>>(),
+            vec![&b"one"[..], b"three"]
+        );
+
+        assert!(group.is_active());
+        assert_eq!(group.key(), b"first");
+        assert_eq!(group.buffered(), 8);
+
+        group.reset(b"second");
+
+        assert_eq!(group.key(), b"second");
+        assert_eq!(group.buffered(), 0);
+        assert_eq!(group.values().count(), 0);
+        assert_eq!(group.arena.capacity(), capacity);
+    }
+
     #[test]
     fn test_reducer_empty_values() {
         let mut ctx = Context::new();
@@ -207,6 +997,467 @@ mod tests {
 
         assert_eq!(pair.0, b"key");
         assert_eq!(pair.1, vec![b"", b""]);
+
+        let input = &b"key\nkey\t\nkey\tvalue\nother\n"[..];
+
+        let mut ctx = Context::buffered();
+
+        execute(input, ReducerLifecycle::new(IdentityReducer), &mut ctx).unwrap();
+
+        assert_eq!(
+            ctx.take_output(),
+            vec![
+                (b"key".to_vec(), b"".to_vec()),
+                (b"key".to_vec(), b"".to_vec()),
+                (b"key".to_vec(), b"value".to_vec()),
+                (b"other".to_vec(), b"".to_vec()),
+            ]
+        );
+
+        let mut ctx = Context::buffered();
+
+        execute(input, ReducerLifecycle::new(TestMissing), &mut ctx).unwrap();
+
+        assert_eq!(
+            ctx.take_output(),
+            vec![
+                (b"key".to_vec(), b"<missing>".to_vec()),
+                (b"key".to_vec(), b"".to_vec()),
+                (b"key".to_vec(), b"value".to_vec()),
+                (b"other".to_vec(), b"<missing>".to_vec()),
+            ]
+        );
+        assert_eq!(ctx.record_count(), 4);
+    }
+
+    #[test]
+    fn test_reducer_custom_separators() {
+        let mut ctx = Context::new();
+        let mut reducer = ReducerLifecycle::new(TestReducer);
+
+        ctx.insert(Delimiters::with_separators(b":", b"\t"));
+
+        reducer.on_start(&mut ctx);
+        reducer.on_entry(b"key:one", &mut ctx);
+        reducer.on_entry(b"key:two", &mut ctx);
+        reducer.on_end(&mut ctx);
+
+        let pair = ctx.get::<TestPair>().unwrap();
+
+        assert_eq!(pair.0, b"key");
+        assert_eq!(pair.1, vec![b"one", b"two"]);
+    }
+
+    #[test]
+    fn test_reducer_key_fields() {
+        let env = vec![("stream.num.map.output.key.fields", "2")];
+        let conf = Configuration::with_env(env.into_iter());
+
+        let mut ctx = Context::new();
+        let mut reducer = ReducerLifecycle::new(TestReducer);
+
+        ctx.insert(Delimiters::new(&conf));
+
+        reducer.on_start(&mut ctx);
+        reducer.on_entry(b"first\tone\t1", &mut ctx);
+        reducer.on_entry(b"first\ttwo\t2", &mut ctx);
+        reducer.on_entry(b"first\ttwo\t3", &mut ctx);
+
+        {
+            let pair = ctx.get::<TestPair>().unwrap();
+
+            assert_eq!(pair.0, b"first\tone");
+            assert_eq!(pair.1, vec![b"1"]);
+        }
+
+        reducer.on_end(&mut ctx);
+
+        let pair = ctx.get::<TestPair>().unwrap();
+
+        assert_eq!(pair.0, b"first\ttwo");
+        assert_eq!(pair.1, vec![b"2", b"3"]);
+    }
+
+    #[test]
+    fn test_reducer_streaming() {
+        let buffer = Buffer::default();
+        let mut ctx = Context::with_output(Box::new(buffer.clone()));
+
+        let input = &b"first\t1\nfirst\t2\nfirst\t3\nsecond\t4\nthird\t5\n"[..];
+
+        execute(input, ReducerLifecycle::new(TestStreamer), &mut ctx).unwrap();
+
+        assert_eq!(buffer.take(), b"first\t1\nfirst\t2\nsecond\t4\nthird\t5\n");
+        assert_eq!(ctx.record_count(), 5);
+    }
+
+    #[test]
+    fn test_reducer_value_combinators() {
+        let cases = vec![(ErrorPolicy::Skip, true), (ErrorPolicy::Fail, false)];
+
+        for (policy, success) in cases {
+            let mut ctx = Context::buffered();
+
+            ctx.insert(policy);
+
+            let input = &b"count\ta\ncount\tb\nmax\t3\nmax\tbad\nmax\t7\nsum\t1\nsum\t2\n"[..];
+            let result = execute(input, ReducerLifecycle::new(TestCombinators), &mut ctx);
+
+            if success {
+                result.unwrap();
+
+                assert_eq!(
+                    ctx.take_output(),
+                    vec![
+                        (b"count".to_vec(), b"2".to_vec()),
+                        (b"max".to_vec(), b"7".to_vec()),
+                        (b"sum".to_vec(), b"3".to_vec()),
+                    ]
+                );
+            } else {
+                let err = result.unwrap_err();
+
+                assert_eq!(err.to_string(), "failed to parse value: \"bad\"");
+
+                // the group ends at the failure, before the error is surfaced
+                assert_eq!(
+                    ctx.take_output(),
+                    vec![
+                        (b"count".to_vec(), b"2".to_vec()),
+                        (b"max".to_vec(), b"3".to_vec()),
+                    ]
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_reducer_streaming_defaults() {
+        let buffer = Buffer::default();
+        let mut ctx = Context::with_output(Box::new(buffer.clone()));
+
+        let input = &b"first\tone\nfirst\ttwo\nsecond\tthree\n"[..];
+
+        execute(input, ReducerLifecycle::new(TestReducer), &mut ctx).unwrap();
+
+        let pair = ctx.get::<TestPair>().unwrap();
+
+        assert_eq!(pair.0, b"second");
+        assert_eq!(pair.1, vec![b"three"]);
+        assert_eq!(ctx.record_count(), 3);
+    }
+
+    #[test]
+    fn test_reducer_value_ordering() {
+        let buffer = Buffer::default();
+        let mut ctx = Context::with_output(Box::new(buffer.clone()));
+
+        let env = vec![("mapreduce.partition.keycomparator.options", "-k2,2n")];
+        let conf = Configuration::with_env(env.into_iter());
+
+        ctx.insert(ValueOrder::new(&conf));
+
+        let input = &b"first\t10\nfirst\t9\nfirst\t100\nsecond\t2\nsecond\t1\n"[..];
+
+        execute(input, ReducerLifecycle::new(TestStreamer), &mut ctx).unwrap();
+
+        assert_eq!(
+            buffer.take(),
+            b"first\t9\nfirst\t10\nsecond\t1\nsecond\t2\n"
+        );
+        assert_eq!(ctx.record_count(), 5);
+    }
+
+    #[test]
+    fn test_reducer_value_spilling() {
+        let mut ctx = Context::buffered();
+
+        let env = vec![
+            ("mapreduce.partition.keycomparator.options", "-k2,2n"),
+            ("efflux.reduce.spill.bytes", "3"),
+        ];
+        let conf = Configuration::with_env(env.into_iter());
+
+        ctx.insert(ValueOrder::new(&conf));
+        ctx.insert(conf);
+
+        let input = &b"first\t10\nfirst\t9\nfirst\t100\nfirst\t1\nfirst\t50\nsecond\t2\n"[..];
+
+        execute(input, ReducerLifecycle::new(IdentityReducer), &mut ctx).unwrap();
+
+        assert_eq!(
+            ctx.take_output(),
+            vec![
+                (b"first".to_vec(), b"1".to_vec()),
+                (b"first".to_vec(), b"9".to_vec()),
+                (b"first".to_vec(), b"10".to_vec()),
+                (b"first".to_vec(), b"50".to_vec()),
+                (b"first".to_vec(), b"100".to_vec()),
+                (b"second".to_vec(), b"2".to_vec()),
+            ]
+        );
+        assert_eq!(ctx.record_count(), 6);
+    }
+
+    #[test]
+    fn test_reducer_buffer_limit() {
+        let mut ctx = Context::new();
+        let mut reducer = ReducerLifecycle::new(TestReducer);
+
+        ctx.get_mut::<Configuration>()
+            .unwrap()
+            .insert("efflux.reduce.buffer.max.bytes", "6");
+
+        reducer.on_start(&mut ctx);
+        reducer.on_entry(b"first\tone", &mut ctx);
+        reducer.on_entry(b"first\ttwo", &mut ctx);
+
+        assert_eq!(reducer.buffered_bytes(), 6);
+        assert!(ctx.take_error().is_none());
+
+        reducer.on_entry(b"second\tthree", &mut ctx);
+
+        assert_eq!(reducer.buffered_bytes(), 5);
+        assert!(ctx.take_error().is_none());
+
+        reducer.on_entry(b"second\tfour", &mut ctx);
+
+        assert_eq!(reducer.buffered_bytes(), 9);
+        assert_eq!(
+            ctx.take_error().unwrap().to_string(),
+            "reduce group exceeded buffer limit of 6 bytes"
+        );
+
+        let input = &b"first\t10\nfirst\t9\nfirst\t100\n"[..];
+        // values collected for `reduce` are never bounded by spilling
+        let cases = vec![
+            (None, true, false, false),
+            (Some("4"), true, false, false),
+            (Some("4"), true, true, true),
+            (None, false, false, false),
+            (Some("4"), false, false, false),
+            (None, false, true, true),
+        ];
+
+        for (spill, sorted, streaming, success) in cases {
+            let mut ctx = Context::buffered();
+            let mut env = vec![("efflux.reduce.buffer.max.bytes", "5")];
+
+            if sorted {
+                env.push(("mapreduce.partition.keycomparator.options", "-k2,2n"));
+            }
+
+            if let Some(spill) = spill {
+                env.push(("efflux.reduce.spill.bytes", spill));
+            }
+
+            let conf = Configuration::with_env(env.into_iter());
+
+            ctx.insert(ValueOrder::new(&conf));
+            ctx.insert(conf);
+
+            let result = if streaming {
+                execute(input, ReducerLifecycle::new(TestStreamer), &mut ctx)
+            } else {
+                execute(input, ReducerLifecycle::new(IdentityReducer), &mut ctx)
+            };
+
+            assert_eq!(result.is_ok(), success);
+
+            if !success {
+                assert_eq!(
+                    result.unwrap_err().to_string(),
+                    "reduce group exceeded buffer limit of 5 bytes"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_reducer_value_comparator() {
+        let mut ctx = Context::new();
+        let mut reducer = ReducerLifecycle::new(TestSorter);
+
+        reducer.on_start(&mut ctx);
+        reducer.on_entry(b"key\tb", &mut ctx);
+        reducer.on_entry(b"key\tc", &mut ctx);
+        reducer.on_entry(b"key\ta", &mut ctx);
+        reducer.on_end(&mut ctx);
+
+        let pair = ctx.get::<TestPair>().unwrap();
+
+        assert_eq!(pair.0, b"key");
+        assert_eq!(pair.1, vec![b"c", b"b", b"a"]);
+    }
+
+    #[test]
+    fn test_reducer_grouping() {
+        let buffer = Buffer::default();
+        let mut ctx = Context::with_output(Box::new(buffer.clone()));
+
+        ctx.insert(Delimiters::new(&Configuration::with_env(
+            vec![("stream.num.map.output.key.fields", "2")].into_iter(),
+        )));
+
+        let input = &b"first\ta\t1\nfirst\tb\t2\nsecond\ta\t3\n"[..];
+
+        execute(input, ReducerLifecycle::new(TestGrouper(b'\t')), &mut ctx).unwrap();
+
+        assert_eq!(buffer.take(), b"first\ta\t2\nsecond\ta\t1\n");
+
+        let mut reducer = ReducerLifecycle::new(TestGrouper(b'\t'));
+
+        reducer.on_start(&mut ctx);
+        reducer.on_entry(b"first\ta\t1", &mut ctx);
+        reducer.on_entry(b"first\tb\t2", &mut ctx);
+        reducer.on_entry(b"second\ta\t3", &mut ctx);
+        reducer.on_end(&mut ctx);
+
+        assert_eq!(buffer.take(), b"first\ta\t2\nsecond\ta\t1\n");
+    }
+
+    struct TestGrouper(u8);
+
+    impl Reducer for TestGrouper {
+        fn reduce(&mut self, key: &[u8], values: &[&[u8]], ctx: &mut Context) {
+            ctx.write(key, values.len().to_string().as_bytes()).unwrap();
+        }
+
+        fn group_comparator(&self) -> Option<GroupComparator> {
+            let separator = self.0;
+            Some(Box::new(move |left, right| {
+                left.split(|b| *b == separator).next() == right.split(|b| *b == separator).next()
+            }))
+        }
+    }
+
+    struct TestSorter;
+
+    impl Reducer for TestSorter {
+        fn reduce(&mut self, key: &[u8], values: &[&[u8]], ctx: &mut Context) {
+            TestReducer.reduce(key, values, ctx)
+        }
+
+        fn value_comparator(&self) -> Option<ValueComparator> {
+            Some(|left, right| right.cmp(left))
+        }
+    }
+
+    #[test]
+    fn test_identity_reducer() {
+        let mut ctx = Context::buffered();
+
+        let input = &b"first\tone\nfirst\ttwo\nsecond\tthree\n"[..];
+
+        execute(input, ReducerLifecycle::new(IdentityReducer), &mut ctx).unwrap();
+
+        assert_eq!(
+            ctx.take_output(),
+            vec![
+                (b"first".to_vec(), b"one".to_vec()),
+                (b"first".to_vec(), b"two".to_vec()),
+                (b"second".to_vec(), b"three".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reducer_setup_failure() {
+        let mut ctx = Context::buffered();
+
+        let input = &b"key\tvalue\n"[..];
+        let result = execute(input, ReducerLifecycle::new(TestFailure), &mut ctx);
+        let err = result.unwrap_err();
+
+        assert_eq!(err.to_string(), "failed to setup reducer: missing lookup");
+        assert_eq!(ctx.take_output(), vec![]);
+    }
+
+    #[test]
+    fn test_reducer_catching_panics() {
+        let cases = vec![(ErrorPolicy::Skip, true), (ErrorPolicy::Fail, false)];
+
+        for (policy, success) in cases {
+            let mut ctx = Context::buffered();
+
+            ctx.insert(policy);
+            ctx.get_mut::<Configuration>()
+                .unwrap()
+                .insert("efflux.task.catch.panics", "true");
+
+            let input = &b"bad\tone\ngood\ttwo\n"[..];
+            let result = execute(input, ReducerLifecycle::new(TestPanic), &mut ctx);
+
+            assert_eq!(result.is_ok(), success);
+
+            if success {
+                assert_eq!(ctx.take_output(), vec![(b"good".to_vec(), b"two".to_vec())]);
+            } else {
+                let err = result.unwrap_err();
+                assert_eq!(err.to_string(), "panicked whilst handling record: bad key");
+            }
+        }
+    }
+
+    struct TestPanic;
+
+    impl Reducer for TestPanic {
+        fn reduce(&mut self, key: &[u8], values: &[&[u8]], ctx: &mut Context) {
+            if key == b"bad" {
+                panic!("bad key");
+            }
+            for value in values {
+                ctx.write(key, value).unwrap();
+            }
+        }
+
+        fn on_error(&mut self, err: &EffluxError, _ctx: &mut Context) {
+            assert!(matches!(err, EffluxError::Panic(_)));
+        }
+    }
+
+    struct TestFailure;
+
+    impl Reducer for TestFailure {
+        fn try_setup(&mut self, _ctx: &mut Context) -> Result<(), Box<dyn Error>> {
+            Err("missing lookup".into())
+        }
+    }
+
+    struct TestStreamer;
+
+    impl Reducer for TestStreamer {
+        fn reduce_streaming(&mut self, key: &[u8], values: &mut Values<'_, '_>, ctx: &mut Context) {
+            // only emit the first two values of each group
+            for _ in 0..2 {
+                if let Some(value) = values.next_value() {
+                    ctx.write(key, value).unwrap();
+                }
+            }
+        }
+    }
+
+    struct TestMissing;
+
+    impl Reducer for TestMissing {
+        fn reduce_streaming(&mut self, key: &[u8], values: &mut Values<'_, '_>, ctx: &mut Context) {
+            while let Some(value) = values.next_value_checked() {
+                ctx.write(key, value.unwrap_or(b"<missing>")).unwrap();
+            }
+        }
+    }
+
+    struct TestCombinators;
+
+    impl Reducer for TestCombinators {
+        fn reduce_streaming(&mut self, key: &[u8], values: &mut Values<'_, '_>, ctx: &mut Context) {
+            match key {
+                b"count" => ctx.write_fmt("count", values.count()),
+                b"max" => ctx.write_fmt("max", values.max::<i64>().unwrap()),
+                _ => ctx.write_fmt("sum", values.sum::<i64>()),
+            }
+            .unwrap();
+        }
     }
 
     struct TestPair(Vec<u8>, Vec<Vec<u8>>);
@@ -0,0 +1,396 @@
+//! Spilling of buffered reduction values to temporary files.
+//!
+//! When the values of a group must be buffered (e.g. to be sorted), large
+//! groups can exceed the available memory. To bound memory usage, values can
+//! be written to disk as sorted runs, which are then merged back together as
+//! the values are read by the `Reducer`. Runs are read back through buffered
+//! readers, so only the head of each run is held in memory whilst merging.
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::iter;
+use std::mem;
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+
+/// Counter used to generate unique names for spill files.
+static SPILL_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Number of runs of the same level which are merged into a single run.
+const SPILL_FAN_IN: usize = 16;
+
+/// Comparator type used to order spilled values.
+pub(crate) type Compare<'a> = &'a dyn Fn(&[u8], &[u8]) -> Ordering;
+
+/// Creates a new temporary file for spilled values.
+fn create_file() -> io::Result<(PathBuf, File)> {
+    let path = env::temp_dir().join(format!(
+        "efflux-spill-{}-{}",
+        process::id(),
+        SPILL_COUNTER.fetch_add(1, AtomicOrdering::Relaxed)
+    ));
+
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(&path)?;
+
+    Ok((path, file))
+}
+
+/// Collection of sorted runs spilled to disk for a single group.
+///
+/// Runs are merged in tiers to bound the number of open files; once there are
+/// `SPILL_FAN_IN` runs of the same level, they are merged into a single run of
+/// the next level. All spill files are removed when the runs are cleared (or
+/// dropped).
+#[derive(Default)]
+pub(crate) struct Spill {
+    runs: Vec<Run>,
+}
+
+impl Spill {
+    /// Determines whether any runs have been spilled.
+    #[inline]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.runs.is_empty()
+    }
+
+    /// Writes a sorted run of values to a new temporary file.
+    pub(crate) fn write<'v, I>(&mut self, values: I, compare: Compare<'_>) -> io::Result<()>
+    where
+        I: IntoIterator<Item = &'v [u8]>,
+    {
+        let (mut run, mut writer) = Run::create(0)?;
+
+        for value in values {
+            run.write(&mut writer, value)?;
+        }
+
+        run.finish(writer)?;
+        self.runs.push(run);
+
+        // merge the trailing runs while there are too many of the same level
+        while let Some(level) = self.runs.last().map(|run| run.level) {
+            let tail = self
+                .runs
+                .iter()
+                .rev()
+                .take_while(|run| run.level == level)
+                .count();
+
+            if tail < SPILL_FAN_IN {
+                break;
+            }
+
+            let start = self.runs.len() - tail;
+            let (mut run, mut writer) = Run::create(level + 1)?;
+            let mut merge = Merge::with_runs(&mut self.runs[start..], iter::empty(), compare);
+
+            while let Some(value) = merge.next()? {
+                run.write(&mut writer, value)?;
+            }
+
+            run.finish(writer)?;
+
+            // dropping the merged runs removes their files
+            self.runs.truncate(start);
+            self.runs.push(run);
+        }
+
+        Ok(())
+    }
+
+    /// Removes all spilled runs, and their files.
+    #[inline]
+    pub(crate) fn clear(&mut self) {
+        self.runs.clear();
+    }
+}
+
+/// Sorted run of values stored in a temporary file.
+struct Run {
+    path: PathBuf,
+    level: usize,
+    reader: Option<BufReader<File>>,
+}
+
+impl Run {
+    /// Creates a new run at a level, with a writer for the run values.
+    fn create(level: usize) -> io::Result<(Self, BufWriter<File>)> {
+        let (path, file) = create_file()?;
+
+        // the run owns the path from here, to ensure it's removed on error
+        let run = Run {
+            path,
+            level,
+            reader: None,
+        };
+
+        Ok((run, BufWriter::new(file)))
+    }
+
+    /// Writes a value to the run, prefixed by the length of the value.
+    ///
+    /// Values are prefixed by their length, as they may contain anything.
+    #[inline]
+    fn write(&mut self, writer: &mut BufWriter<File>, value: &[u8]) -> io::Result<()> {
+        writer.write_all(&(value.len() as u64).to_le_bytes())?;
+        writer.write_all(value)
+    }
+
+    /// Finishes writing the run, rewinding it to be read.
+    fn finish(&mut self, writer: BufWriter<File>) -> io::Result<()> {
+        let mut file = writer.into_inner().map_err(|err| err.into_error())?;
+        file.seek(SeekFrom::Start(0))?;
+        self.reader = Some(BufReader::new(file));
+        Ok(())
+    }
+
+    /// Reads the next value of the run into a buffer, if any.
+    fn read(&mut self, buffer: &mut Vec<u8>) -> io::Result<bool> {
+        let reader = match self.reader.as_mut() {
+            Some(reader) => reader,
+            None => return Ok(false),
+        };
+
+        let mut len = [0; 8];
+        match reader.read_exact(&mut len) {
+            Ok(()) => (),
+            Err(ref err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(false),
+            Err(err) => return Err(err),
+        }
+
+        buffer.clear();
+        buffer.resize(u64::from_le_bytes(len) as usize, 0);
+
+        reader.read_exact(buffer)?;
+
+        Ok(true)
+    }
+}
+
+/// Removes the run file once the run is no longer needed.
+impl Drop for Run {
+    fn drop(&mut self) {
+        self.reader = None;
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Merge of spilled runs with the remaining in-memory values of a group.
+///
+/// Values are merged in order of the provided comparator, using a heap of
+/// the next value of each run. Equal values are taken from earlier runs first,
+/// and the in-memory values (which were read last) are treated as the final
+/// run, keeping the merge stable.
+pub(crate) struct Merge<'a, I> {
+    runs: &'a mut [Run],
+    heap: BinaryHeap<Head<'a>>,
+    memory: I,
+    head: Option<&'a [u8]>,
+    compare: Compare<'a>,
+    value: Vec<u8>,
+    last: Option<Position>,
+    started: bool,
+}
+
+/// Position of the last value provided by a `Merge`.
+enum Position {
+    Run(usize),
+    Memory,
+}
+
+impl<'a, I> Merge<'a, I>
+where
+    I: Iterator<Item = &'a [u8]>,
+{
+    /// Constructs a new `Merge` over spilled runs and in-memory values.
+    pub(crate) fn new(spill: &'a mut Spill, memory: I, compare: Compare<'a>) -> Self {
+        Self::with_runs(&mut spill.runs, memory, compare)
+    }
+
+    /// Constructs a new `Merge` over a slice of runs and in-memory values.
+    fn with_runs(runs: &'a mut [Run], memory: I, compare: Compare<'a>) -> Self {
+        Self {
+            heap: BinaryHeap::with_capacity(runs.len()),
+            runs,
+            memory,
+            head: None,
+            compare,
+            value: Vec::new(),
+            last: None,
+            started: false,
+        }
+    }
+
+    /// Retrieves the next value of the merge, if any.
+    pub(crate) fn next(&mut self) -> io::Result<Option<&[u8]>> {
+        // advance whichever run provided the last value
+        if !self.started {
+            self.started = true;
+            for idx in 0..self.runs.len() {
+                self.advance(idx, Vec::new())?;
+            }
+            self.head = self.memory.next();
+        } else {
+            match self.last.take() {
+                Some(Position::Run(idx)) => {
+                    let buffer = mem::take(&mut self.value);
+                    self.advance(idx, buffer)?;
+                }
+                Some(Position::Memory) => self.head = self.memory.next(),
+                None => return Ok(None),
+            }
+        }
+
+        // in-memory values only win when strictly smaller than all runs
+        let memory = match (self.heap.peek(), self.head) {
+            (Some(top), Some(head)) => (self.compare)(head, &top.value) == Ordering::Less,
+            (None, head) => head.is_some(),
+            (Some(_), None) => false,
+        };
+
+        if memory {
+            self.last = Some(Position::Memory);
+            return Ok(self.head);
+        }
+
+        match self.heap.pop() {
+            Some(top) => {
+                self.last = Some(Position::Run(top.idx));
+                self.value = top.value;
+                Ok(Some(&self.value))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Reads the next value of a run into the heap, reusing a buffer.
+    fn advance(&mut self, idx: usize, mut buffer: Vec<u8>) -> io::Result<()> {
+        if self.runs[idx].read(&mut buffer)? {
+            self.heap.push(Head {
+                value: buffer,
+                idx,
+                compare: self.compare,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Next value of a run within the heap of a `Merge`.
+struct Head<'a> {
+    value: Vec<u8>,
+    idx: usize,
+    compare: Compare<'a>,
+}
+
+/// `Ord` implementation for `Head`, reversed as the heap pops the greatest.
+///
+/// Values are ordered by the comparator, then by the index of the run, so
+/// that equal values are taken from earlier runs first.
+impl Ord for Head<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.compare)(&other.value, &self.value).then_with(|| other.idx.cmp(&self.idx))
+    }
+}
+
+/// `PartialOrd` implementation for `Head`, delegating to `Ord`.
+impl PartialOrd for Head<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// `PartialEq` implementation for `Head`, delegating to `Ord`.
+impl PartialEq for Head<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Head<'_> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merging_runs() {
+        let mut spill = Spill::default();
+        let mut paths = Vec::new();
+
+        let compare = |left: &[u8], right: &[u8]| left[0].cmp(&right[0]);
+
+        spill.write(vec![&b"1a"[..], b"3a"], &compare).unwrap();
+        spill.write(vec![&b"1b"[..], b"2b"], &compare).unwrap();
+
+        for run in &spill.runs {
+            assert!(run.path.exists());
+            paths.push(run.path.clone());
+        }
+
+        let memory = [&b"0c"[..], b"3c"];
+
+        let mut merge = Merge::new(&mut spill, memory.iter().copied(), &compare);
+        let mut merged = Vec::new();
+
+        while let Some(value) = merge.next().unwrap() {
+            merged.push(value.to_vec());
+        }
+
+        assert_eq!(
+            merged,
+            vec![
+                b"0c".to_vec(),
+                b"1a".to_vec(),
+                b"1b".to_vec(),
+                b"2b".to_vec(),
+                b"3a".to_vec(),
+                b"3c".to_vec(),
+            ]
+        );
+
+        spill.clear();
+
+        for path in paths {
+            assert!(!path.exists());
+        }
+    }
+
+    #[test]
+    fn test_cascading_runs() {
+        let mut spill = Spill::default();
+        let compare = |left: &[u8], right: &[u8]| left[0].cmp(&right[0]);
+
+        let values = (0..SPILL_FAN_IN * SPILL_FAN_IN + 3)
+            .map(|idx| vec![b'a' + (idx % 5) as u8, (idx / 5) as u8])
+            .collect::<Vec<_>>();
+
+        for value in &values {
+            spill.write(vec![&value[..]], &compare).unwrap();
+        }
+
+        // one run of the second level, and three single value runs
+        let levels = spill.runs.iter().map(|run| run.level).collect::<Vec<_>>();
+        assert_eq!(levels, vec![2, 0, 0, 0]);
+
+        let mut merge = Merge::new(&mut spill, iter::empty(), &compare);
+        let mut merged = Vec::new();
+
+        while let Some(value) = merge.next().unwrap() {
+            merged.push(value.to_vec());
+        }
+
+        // merging is stable, so equal values keep the order they were written
+        let mut expected = values.clone();
+        expected.sort_by(|left, right| compare(left, right));
+
+        assert_eq!(merged, expected);
+    }
+}
@@ -0,0 +1,134 @@
+//! Testing utilities for jobs built on the `efflux` crate.
+//!
+//! This module offers the `Harness` type, which runs a stage against an
+//! in-memory input and captures the emitted pairs. This avoids having to
+//! construct a `Context` by hand when testing a `Mapper` or `Reducer`:
+//!
+//! ```rust
+//! use efflux::prelude::*;
+//! use efflux::testing::Harness;
+//!
+//! let mapper = |_key: usize, value: &[u8], ctx: &mut Context| {
+//!     ctx.write(value, b"1").unwrap();
+//! };
+//!
+//! let output = Harness::new(mapper).input(b"a\nb\n").run();
+//!
+//! assert_eq!(output, vec![
+//!     (b"a".to_vec(), b"1".to_vec()),
+//!     (b"b".to_vec(), b"1".to_vec()),
+//! ]);
+//! ```
+//!
+//! This module is only available when the `testing` feature is enabled.
+use crate::context::Context;
+use crate::io::{execute, Lifecycle};
+use crate::mapper::{Mapper, MapperLifecycle};
+use crate::reducer::{Reducer, ReducerLifecycle};
+
+/// Harness structure to execute a stage against an in-memory input.
+pub struct Harness {
+    input: Vec<u8>,
+    lifecycle: Box<dyn Lifecycle>,
+}
+
+impl Harness {
+    /// Creates a new `Harness` to execute a `Mapper`.
+    pub fn new<M>(mapper: M) -> Self
+    where
+        M: Mapper + 'static,
+    {
+        Self::with_lifecycle(MapperLifecycle::new(mapper))
+    }
+
+    /// Creates a new `Harness` to execute a `Reducer`.
+    ///
+    /// As in Hadoop, the input of a `Reducer` is expected to be sorted.
+    pub fn reducer<R>(reducer: R) -> Self
+    where
+        R: Reducer + 'static,
+    {
+        Self::with_lifecycle(ReducerLifecycle::new(reducer))
+    }
+
+    /// Creates a new `Harness` from a `Lifecycle`.
+    fn with_lifecycle<L>(lifecycle: L) -> Self
+    where
+        L: Lifecycle + 'static,
+    {
+        Self {
+            input: Vec::new(),
+            lifecycle: Box::new(lifecycle),
+        }
+    }
+
+    /// Appends bytes to the input of the stage.
+    pub fn input(mut self, input: &[u8]) -> Self {
+        self.input.extend(input);
+        self
+    }
+
+    /// Executes the stage, returning the pairs emitted to the output.
+    ///
+    /// Pairs are split using the output delimiter of the `Context`, with
+    /// any output without a delimiter treated as a key with an empty value.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the stage fails with an IO error.
+    pub fn run(self) -> Vec<(Vec<u8>, Vec<u8>)> {
+        let mut ctx = Context::buffered();
+
+        execute(&self.input[..], self.lifecycle, &mut ctx).expect("stage execution failed");
+
+        ctx.take_output()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mapper_harness() {
+        let mapper = |_key: usize, value: &[u8], ctx: &mut Context| {
+            for word in value.split(|b| *b == b' ') {
+                ctx.write(word, b"1").unwrap();
+            }
+        };
+
+        let output = Harness::new(mapper)
+            .input(b"one two\n")
+            .input(b"three\n")
+            .run();
+
+        assert_eq!(
+            output,
+            vec![
+                (b"one".to_vec(), b"1".to_vec()),
+                (b"two".to_vec(), b"1".to_vec()),
+                (b"three".to_vec(), b"1".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reducer_harness() {
+        let reducer = |key: &[u8], values: &[&[u8]], ctx: &mut Context| {
+            ctx.write_fmt(std::str::from_utf8(key).unwrap(), values.len())
+                .unwrap();
+        };
+
+        let output = Harness::reducer(reducer)
+            .input(b"one\t1\none\t1\ntwo\t1\n")
+            .run();
+
+        assert_eq!(
+            output,
+            vec![
+                (b"one".to_vec(), b"2".to_vec()),
+                (b"two".to_vec(), b"1".to_vec()),
+            ]
+        );
+    }
+}
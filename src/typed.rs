@@ -0,0 +1,433 @@
+//! Typed adapters for stages working with parsed values.
+//!
+//! This module offers the `TypedMapper` and `TypedReducer` traits, which
+//! receive values which have already been parsed via `FromStr`, rather than
+//! raw bytes. Typed stages are executed by wrapping them in the `Typed`
+//! adapter, which handles parsing (and parse failures) on their behalf:
+//!
+//! ```rust,no_run
+//! use efflux::prelude::*;
+//! use efflux::typed::{Typed, TypedMapper};
+//!
+//! struct Doubler;
+//!
+//! impl TypedMapper for Doubler {
+//!     type Value = i64;
+//!
+//!     fn map(&mut self, _key: usize, value: i64, ctx: &mut Context) {
+//!         let _ = ctx.write_fmt(value, value * 2);
+//!     }
+//! }
+//!
+//! efflux::run_mapper(Typed(Doubler));
+//! ```
+//!
+//! Values which fail to parse are handled using the `ErrorPolicy` stored
+//! in the `Context`; they're either skipped, counted under the `efflux`
+//! group counter `parse_errors`, or cause the stage to fail. Any skipped
+//! failures are passed to the `on_error` handler of the typed stage.
+use std::io;
+use std::marker::PhantomData;
+use std::slice;
+use std::str::{self, FromStr};
+
+use crate::context::{Context, ErrorPolicy};
+use crate::error::EffluxError;
+use crate::mapper::Mapper;
+use crate::reducer::{Reducer, Values};
+
+/// Trait to represent a mapping stage operating on parsed values.
+///
+/// This mirrors the `Mapper` trait, except that each value is parsed into
+/// the associated `Value` type before being provided. The `Mapper` trait is
+/// still available for cases where byte level control is necessary.
+pub trait TypedMapper {
+    /// The type each input value is parsed into.
+    type Value: FromStr;
+
+    /// Setup handler for the current `TypedMapper`.
+    fn setup(&mut self, _ctx: &mut Context) {}
+
+    /// Mapping handler for the current `TypedMapper`.
+    fn map(&mut self, key: usize, value: Self::Value, ctx: &mut Context);
+
+    /// Error handler for failures skipped by the current `TypedMapper`.
+    fn on_error(&mut self, _err: &EffluxError, _ctx: &mut Context) {}
+
+    /// Cleanup handler for the current `TypedMapper`.
+    fn cleanup(&mut self, _ctx: &mut Context) {}
+}
+
+/// Trait to represent a reduction stage operating on parsed values.
+///
+/// This mirrors the `Reducer` trait, except that each value is parsed into
+/// the associated `Value` type as it's pulled from the provided iterator.
+/// Values are parsed lazily, so groups are never buffered by the adapter.
+pub trait TypedReducer {
+    /// The type each input value is parsed into.
+    type Value: FromStr;
+
+    /// Setup handler for the current `TypedReducer`.
+    fn setup(&mut self, _ctx: &mut Context) {}
+
+    /// Reduction handler for the current `TypedReducer`.
+    fn reduce(&mut self, key: &[u8], values: impl Iterator<Item = Self::Value>, ctx: &mut Context);
+
+    /// Error handler for failures skipped by the current `TypedReducer`.
+    fn on_error(&mut self, _err: &EffluxError, _ctx: &mut Context) {}
+
+    /// Cleanup handler for the current `TypedReducer`.
+    fn cleanup(&mut self, _ctx: &mut Context) {}
+}
+
+/// Adapter structure to execute typed stages.
+#[derive(Debug, Default)]
+pub struct Typed<T>(pub T);
+
+/// `Mapper` implementation for typed mapping stages.
+impl<M> Mapper for Typed<M>
+where
+    M: TypedMapper,
+{
+    /// Setup handler by passing through to the inner mapper.
+    #[inline]
+    fn setup(&mut self, ctx: &mut Context) {
+        self.0.setup(ctx)
+    }
+
+    /// Mapping handler by parsing the value before passing to the inner mapper.
+    #[inline]
+    fn map(&mut self, key: usize, value: &[u8], ctx: &mut Context) {
+        let policy = ctx.get::<ErrorPolicy>().copied().unwrap_or_default();
+
+        match parse(value, policy, ctx.skipped_mut()) {
+            Ok(Some(value)) => self.0.map(key, value, ctx),
+            Ok(None) => (),
+            Err(err) => ctx.set_error(err),
+        }
+    }
+
+    /// Error handler by passing through to the inner mapper.
+    #[inline]
+    fn on_error(&mut self, err: &EffluxError, ctx: &mut Context) {
+        self.0.on_error(err, ctx)
+    }
+
+    /// Cleanup handler by passing through to the inner mapper.
+    #[inline]
+    fn cleanup(&mut self, ctx: &mut Context) {
+        self.0.cleanup(ctx)
+    }
+}
+
+/// `Reducer` implementation for typed reduction stages.
+impl<R> Reducer for Typed<R>
+where
+    R: TypedReducer,
+{
+    /// Setup handler by passing through to the inner reducer.
+    #[inline]
+    fn setup(&mut self, ctx: &mut Context) {
+        self.0.setup(ctx)
+    }
+
+    /// Reduction handler by parsing the buffered values for the inner reducer.
+    #[inline]
+    fn reduce(&mut self, key: &[u8], values: &[&[u8]], ctx: &mut Context) {
+        reduce(&mut self.0, key, &mut values.iter(), ctx)
+    }
+
+    /// Reduction handler by parsing the streamed values for the inner reducer.
+    #[inline]
+    fn reduce_streaming(&mut self, key: &[u8], values: &mut Values<'_, '_>, ctx: &mut Context) {
+        reduce(&mut self.0, key, values, ctx)
+    }
+
+    /// Error handler by passing through to the inner reducer.
+    #[inline]
+    fn on_error(&mut self, err: &EffluxError, ctx: &mut Context) {
+        self.0.on_error(err, ctx)
+    }
+
+    /// Cleanup handler by passing through to the inner reducer.
+    #[inline]
+    fn cleanup(&mut self, ctx: &mut Context) {
+        self.0.cleanup(ctx)
+    }
+}
+
+/// Source trait to allow parsing values from borrowed sources.
+trait Source {
+    /// Retrieves the next value from the source, if any.
+    fn next_value(&mut self) -> Option<&[u8]>;
+}
+
+/// `Source` implementation for buffered values.
+impl Source for slice::Iter<'_, &[u8]> {
+    #[inline]
+    fn next_value(&mut self) -> Option<&[u8]> {
+        self.next().copied()
+    }
+}
+
+/// `Source` implementation for streamed values.
+impl Source for Values<'_, '_> {
+    #[inline]
+    fn next_value(&mut self) -> Option<&[u8]> {
+        Values::next_value(self)
+    }
+}
+
+/// Iterator structure to lazily parse values from a `Source`.
+///
+/// Parse failures are handled using the `ErrorPolicy`, with any error stored
+/// (and iteration halted) so that it can be surfaced after reduction.
+struct Parsed<'s, S, T>
+where
+    S: Source + ?Sized,
+{
+    source: &'s mut S,
+    policy: ErrorPolicy,
+    error: Option<io::Error>,
+    skipped: Vec<EffluxError>,
+    marker: PhantomData<T>,
+}
+
+/// `Iterator` implementation to parse values from the inner `Source`.
+impl<S, T> Iterator for Parsed<'_, S, T>
+where
+    S: Source + ?Sized,
+    T: FromStr,
+{
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        if self.error.is_some() {
+            return None;
+        }
+
+        loop {
+            match parse(self.source.next_value()?, self.policy, &mut self.skipped) {
+                Ok(Some(value)) => return Some(value),
+                Ok(None) => continue,
+                Err(err) => {
+                    self.error = Some(err);
+                    return None;
+                }
+            }
+        }
+    }
+}
+
+/// Executes a `TypedReducer` against values from a `Source`.
+fn reduce<R, S>(reducer: &mut R, key: &[u8], source: &mut S, ctx: &mut Context)
+where
+    R: TypedReducer,
+    S: Source + ?Sized,
+{
+    let mut values = Parsed {
+        source,
+        policy: ctx.get::<ErrorPolicy>().copied().unwrap_or_default(),
+        error: None,
+        skipped: Vec::new(),
+        marker: PhantomData,
+    };
+
+    reducer.reduce(key, &mut values, ctx);
+
+    ctx.skipped_mut().append(&mut values.skipped);
+
+    if let Some(err) = values.error {
+        ctx.set_error(err);
+    }
+}
+
+/// Parses a value via `FromStr`, handling failures using an `ErrorPolicy`.
+///
+/// Failures only result in an error when using `ErrorPolicy::Fail`, and are
+/// otherwise skipped (after incrementing a counter for `ErrorPolicy::Count`).
+/// Skipped failures are added to `skipped`, to be passed to `on_error`.
+pub(crate) fn parse<T>(
+    value: &[u8],
+    policy: ErrorPolicy,
+    skipped: &mut Vec<EffluxError>,
+) -> io::Result<Option<T>>
+where
+    T: FromStr,
+{
+    if let Some(parsed) = str::from_utf8(value).ok().and_then(|v| v.parse().ok()) {
+        return Ok(Some(parsed));
+    }
+
+    reject(value, policy, skipped)
+}
+
+/// Handles a value which failed to parse, according to an `ErrorPolicy`.
+///
+/// This is shared with other parsing helpers, such as `Context::parse_json`,
+/// to ensure all parse failures are treated (and counted) the same way.
+/// Skipped failures are added to `skipped`, to be passed to `on_error`.
+pub(crate) fn reject<T>(
+    value: &[u8],
+    policy: ErrorPolicy,
+    skipped: &mut Vec<EffluxError>,
+) -> io::Result<Option<T>> {
+    let value = String::from_utf8_lossy(value);
+    let msg = format!("failed to parse value: {:?}", value);
+
+    fail(msg, "parse_errors", policy, skipped).map(|_| None)
+}
+
+/// Handles a failure with a parsed value, according to an `ErrorPolicy`.
+///
+/// This behaves in the same way as `reject`, except that failures are
+/// counted under the provided label of the `efflux` group counter. This
+/// is used for values which parse, but which cannot be processed further.
+pub(crate) fn fail(
+    msg: String,
+    label: &str,
+    policy: ErrorPolicy,
+    skipped: &mut Vec<EffluxError>,
+) -> io::Result<()> {
+    match policy {
+        ErrorPolicy::Skip => {
+            skipped.push(EffluxError::Parse(msg));
+            Ok(())
+        }
+        ErrorPolicy::Count => {
+            update_counter!("efflux", label, 1);
+            skipped.push(EffluxError::Parse(msg));
+            Ok(())
+        }
+        ErrorPolicy::Fail => Err(io::Error::new(io::ErrorKind::InvalidData, msg)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::Contextual;
+    use crate::io::execute;
+    use crate::local::Buffer;
+    use crate::mapper::MapperLifecycle;
+    use crate::reducer::ReducerLifecycle;
+
+    #[test]
+    fn test_typed_mapper() {
+        let buffer = Buffer::default();
+        let mut ctx = Context::with_output(Box::new(buffer.clone()));
+
+        let input = &b"1\ninvalid\n3\n"[..];
+        let result = execute(input, MapperLifecycle::new(Typed(TestMapper)), &mut ctx);
+
+        assert!(result.is_ok());
+        assert_eq!(buffer.take(), b"1\t2\n3\t6\n");
+    }
+
+    #[test]
+    fn test_typed_error_handling() {
+        let mut ctx = Context::buffered();
+
+        let input = &b"1\ninvalid\n3\n"[..];
+        let result = execute(input, MapperLifecycle::new(Typed(TestMapper)), &mut ctx);
+
+        assert!(result.is_ok());
+        assert_eq!(ctx.get::<TestErrors>().unwrap().0, 1);
+
+        let input = &b"one\t1\none\tinvalid\none\tbad\n"[..];
+        let result = execute(input, ReducerLifecycle::new(Typed(TestReducer)), &mut ctx);
+
+        assert!(result.is_ok());
+        assert_eq!(ctx.get::<TestErrors>().unwrap().0, 3);
+    }
+
+    #[test]
+    fn test_typed_mapper_failure() {
+        let buffer = Buffer::default();
+        let mut ctx = Context::with_output(Box::new(buffer.clone()));
+
+        ctx.insert(ErrorPolicy::Fail);
+
+        let input = &b"1\ninvalid\n3\n"[..];
+        let result = execute(input, MapperLifecycle::new(Typed(TestMapper)), &mut ctx);
+
+        let err = result.unwrap_err();
+
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "failed to parse value: \"invalid\"");
+    }
+
+    #[test]
+    fn test_typed_reducer() {
+        let buffer = Buffer::default();
+        let mut ctx = Context::with_output(Box::new(buffer.clone()));
+
+        let input = &b"one\t1\none\t2\none\tinvalid\ntwo\t3\n"[..];
+        let result = execute(input, ReducerLifecycle::new(Typed(TestReducer)), &mut ctx);
+
+        assert!(result.is_ok());
+        assert_eq!(buffer.take(), b"one\t3\ntwo\t3\n");
+    }
+
+    #[test]
+    fn test_typed_reducer_buffered() {
+        let buffer = Buffer::default();
+        let mut ctx = Context::with_output(Box::new(buffer.clone()));
+
+        let mut reducer = Typed(TestReducer);
+
+        reducer.reduce(b"key", &[b"1", b"2", b"3"], &mut ctx);
+        ctx.flush().unwrap();
+
+        assert_eq!(buffer.take(), b"key\t6\n");
+    }
+
+    #[test]
+    fn test_typed_reducer_failure() {
+        let buffer = Buffer::default();
+        let mut ctx = Context::with_output(Box::new(buffer.clone()));
+
+        ctx.insert(ErrorPolicy::Fail);
+
+        let input = &b"one\t1\none\tinvalid\n"[..];
+        let result = execute(input, ReducerLifecycle::new(Typed(TestReducer)), &mut ctx);
+
+        let err = result.unwrap_err();
+
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "failed to parse value: \"invalid\"");
+    }
+
+    struct TestMapper;
+
+    impl TypedMapper for TestMapper {
+        type Value = i64;
+
+        fn map(&mut self, _key: usize, value: i64, ctx: &mut Context) {
+            ctx.write_fmt(value, value * 2).unwrap();
+        }
+
+        fn on_error(&mut self, _err: &EffluxError, ctx: &mut Context) {
+            ctx.get_or_insert_with(|| TestErrors(0)).0 += 1;
+        }
+    }
+
+    struct TestReducer;
+
+    impl TypedReducer for TestReducer {
+        type Value = i64;
+
+        fn reduce(&mut self, key: &[u8], values: impl Iterator<Item = i64>, ctx: &mut Context) {
+            let key = str::from_utf8(key).unwrap();
+            ctx.write_fmt(key, values.sum::<i64>()).unwrap();
+        }
+
+        fn on_error(&mut self, _err: &EffluxError, ctx: &mut Context) {
+            ctx.get_or_insert_with(|| TestErrors(0)).0 += 1;
+        }
+    }
+
+    struct TestErrors(usize);
+
+    impl Contextual for TestErrors {}
+}